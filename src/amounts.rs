@@ -0,0 +1,407 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result, bail};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    account::Account, commitment_config::CommitmentConfig, instruction::Instruction,
+    program_pack::Pack, pubkey::Pubkey,
+};
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id, instruction::create_associated_token_account,
+};
+use tracing::warn;
+
+use crate::cli::{Dex, Mode, Opts};
+use crate::{meteora, orca, raydium, rpc};
+
+/// Replace `--amount0-ui`/`--amount1-ui`/`--swap-amount-in-ui` with base-unit amounts,
+/// scaled by the decimals of the mints of the pool being opened in or swapped through.
+pub(crate) async fn apply_ui_amounts(opts: &mut Opts) -> Result<()> {
+    if opts.amount0_ui.is_none() && opts.amount1_ui.is_none() && opts.swap_amount_in_ui.is_none() {
+        return Ok(());
+    }
+    let pool_str = match opts.mode()? {
+        Mode::Open(pool) | Mode::Swap(pool) => pool,
+        _ => bail!("UI amounts need an open --pool or a --swap-pool to look up the mint decimals"),
+    };
+    let pool = Pubkey::from_str(&pool_str).context("invalid pool id")?;
+
+    let rpc = rpc::client(opts);
+
+    let (mint0, mint1) = match opts.dex {
+        Dex::Raydium => raydium::pool_mints(&rpc, &pool).await?,
+        Dex::Orca => orca::pool_mints(&rpc, &pool).await?,
+        Dex::Meteora => meteora::pool_mints(&rpc, &pool).await?,
+    };
+    let (decimals0, decimals1) = tokio::try_join!(
+        fetch_mint_decimals(&rpc, &mint0),
+        fetch_mint_decimals(&rpc, &mint1)
+    )?;
+
+    if let Some(ui) = opts.amount0_ui {
+        opts.amount0 = to_base_units(ui, decimals0, "--amount0-ui")?;
+        println!(
+            "amount0: {} {} = {} base units",
+            ui,
+            mint0,
+            group_thousands(opts.amount0 as u128)
+        );
+    }
+    if let Some(ui) = opts.amount1_ui {
+        opts.amount1 = to_base_units(ui, decimals1, "--amount1-ui")?;
+        println!(
+            "amount1: {} {} = {} base units",
+            ui,
+            mint1,
+            group_thousands(opts.amount1 as u128)
+        );
+    }
+    if let Some(ui) = opts.swap_amount_in_ui {
+        let (mint_in, decimals_in) = if opts.swap_a_to_b {
+            (mint0, decimals0)
+        } else {
+            (mint1, decimals1)
+        };
+        opts.swap_amount_in = to_base_units(ui, decimals_in, "--swap-amount-in-ui")?;
+        println!(
+            "swap_amount_in: {} {} = {} base units",
+            ui,
+            mint_in,
+            group_thousands(opts.swap_amount_in as u128)
+        );
+    }
+    Ok(())
+}
+
+fn to_base_units(ui: f64, decimals: u8, flag: &str) -> Result<u64> {
+    let base = (ui * 10f64.powi(decimals as i32)).round();
+    if !(base >= 0.0 && base <= u64::MAX as f64) {
+        bail!("{} {} does not fit in u64 base units", flag, ui);
+    }
+    Ok(base as u64)
+}
+
+/// `--display-precision`: digits after the decimal point, overall and per mint.
+#[derive(Default)]
+struct Precision {
+    default: Option<u8>,
+    per_mint: BTreeMap<Pubkey, u8>,
+}
+
+static PRECISION: OnceLock<Precision> = OnceLock::new();
+/// Mint decimals already looked up, so repeated amounts cost one RPC call per mint.
+static DECIMALS: Mutex<BTreeMap<Pubkey, u8>> = Mutex::new(BTreeMap::new());
+
+/// `--display-precision`: `N` for every token, `MINT=N` for one, or a comma-separated
+/// mix of both. Without it amounts show all of their mint's decimals.
+pub(crate) fn set_display_precision(spec: &str) -> Result<()> {
+    let mut precision = Precision::default();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        match part.split_once('=') {
+            Some((mint, n)) => {
+                let mint = Pubkey::from_str(mint.trim())
+                    .with_context(|| format!("invalid mint in --display-precision {:?}", part))?;
+                let n = n
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid digits in --display-precision {:?}", part))?;
+                precision.per_mint.insert(mint, n);
+            }
+            None => {
+                let n = part
+                    .parse()
+                    .with_context(|| format!("invalid --display-precision {:?}", part))?;
+                precision.default = Some(n);
+            }
+        }
+    }
+    let _ = PRECISION.set(precision);
+    Ok(())
+}
+
+/// `base` units of `mint` for output: the UI amount, rounded to the display precision
+/// and grouped in thousands, next to the exact base units, e.g.
+/// `1,234.5 UI (1,234,500,000 base)`. Only base units are shown if the mint cannot be read.
+pub async fn format_amount(rpc: &RpcClient, mint: &Pubkey, base: u64) -> String {
+    match fetch_mint_decimals(rpc, mint).await {
+        Ok(decimals) => format!(
+            "{} UI ({} base)",
+            format_ui(base as u128, decimals, display_digits(mint, decimals)),
+            group_thousands(base as u128)
+        ),
+        Err(e) => {
+            warn!("showing base units for {}: {:#}", mint, e);
+            format!("{} base", group_thousands(base as u128))
+        }
+    }
+}
+
+fn display_digits(mint: &Pubkey, decimals: u8) -> u8 {
+    let precision = PRECISION.get();
+    precision
+        .and_then(|p| p.per_mint.get(mint).copied().or(p.default))
+        .unwrap_or(decimals)
+        .min(decimals)
+}
+
+/// `base / 10^decimals` rounded half-up to `digits` decimals, trailing zeros trimmed.
+fn format_ui(base: u128, decimals: u8, digits: u8) -> String {
+    let (Some(drop), Some(scale)) = (
+        10u128.checked_pow((decimals - digits) as u32),
+        10u128.checked_pow(digits as u32),
+    ) else {
+        return group_thousands(base);
+    };
+    let rounded = base / drop + u128::from(base % drop >= drop.div_ceil(2));
+    let whole = group_thousands(rounded / scale);
+    let frac = format!("{:0width$}", rounded % scale, width = digits as usize);
+    match frac.trim_end_matches('0') {
+        "" => whole,
+        frac => format!("{}.{}", whole, frac),
+    }
+}
+
+/// `1234567` -> `1,234,567`.
+pub fn group_thousands(n: u128) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+pub(crate) async fn fetch_mint_decimals(rpc: &RpcClient, mint: &Pubkey) -> Result<u8> {
+    if let Some(decimals) = DECIMALS.lock().unwrap().get(mint) {
+        return Ok(*decimals);
+    }
+    let acc = rpc
+        .get_account(mint)
+        .await
+        .with_context(|| format!("fetch mint {}", mint))?;
+    decode_mint_decimals(mint, &acc)
+}
+
+/// Decimals of `mint` from its account, remembered for [`fetch_mint_decimals`].
+fn decode_mint_decimals(mint: &Pubkey, acc: &Account) -> Result<u8> {
+    // Token and Token-2022 share the base Mint layout; extensions follow it.
+    let base = acc
+        .data
+        .get(..spl_token::state::Mint::LEN)
+        .with_context(|| format!("mint {} account too short", mint))?;
+    let state = spl_token::state::Mint::unpack_from_slice(base)
+        .with_context(|| format!("decode mint {}", mint))?;
+    DECIMALS.lock().unwrap().insert(*mint, state.decimals);
+    Ok(state.decimals)
+}
+
+/// Most keys one `getMultipleAccounts` request may ask for.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Accounts read together by [`Prefetched::fetch`], so a flow can read everything it
+/// needs from a pool in one round trip and decode it as it goes.
+#[derive(Debug, Default)]
+pub(crate) struct Prefetched(HashMap<Pubkey, Option<Account>>);
+
+impl Prefetched {
+    /// Read `keys` at processed commitment, so token accounts created by a transaction
+    /// that just landed are seen. Duplicates are read once; past
+    /// [`MAX_MULTIPLE_ACCOUNTS`] keys it takes a round trip per chunk.
+    pub(crate) async fn fetch(rpc: &RpcClient, keys: &[Pubkey]) -> Result<Self> {
+        let mut keys = keys.to_vec();
+        keys.sort_unstable();
+        keys.dedup();
+        let mut accounts = HashMap::with_capacity(keys.len());
+        for chunk in keys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+            let fetched = rpc
+                .get_multiple_accounts_with_commitment(chunk, CommitmentConfig::processed())
+                .await
+                .context("fetch accounts")?
+                .value;
+            accounts.extend(chunk.iter().copied().zip(fetched));
+        }
+        Ok(Self(accounts))
+    }
+
+    /// The account at `key`, or `None` if it does not exist. Errors if `key` was not
+    /// among the keys fetched, which is a bug in the caller rather than chain state.
+    pub(crate) fn get(&self, key: &Pubkey) -> Result<Option<&Account>> {
+        self.0
+            .get(key)
+            .map(Option::as_ref)
+            .with_context(|| format!("account {} was not prefetched", key))
+    }
+
+    /// The account at `key`; `what` names it in the error when it does not exist.
+    pub(crate) fn require(&self, key: &Pubkey, what: &str) -> Result<&Account> {
+        self.get(key)
+            .with_context(|| format!("read {} {}", what, key))?
+            .with_context(|| format!("{} {} not found", what, key))
+    }
+
+    /// Decimals of `mint`, remembered for [`fetch_mint_decimals`].
+    pub(crate) fn mint_decimals(&self, mint: &Pubkey) -> Result<u8> {
+        decode_mint_decimals(mint, self.require(mint, "mint")?)
+    }
+
+    /// Balance of the token account at `key`, or `None` if it does not exist.
+    pub(crate) fn token_balance(&self, key: &Pubkey) -> Result<Option<u64>> {
+        self.get(key)?
+            .map(|acc| decode_token_amount(key, acc))
+            .transpose()
+    }
+}
+
+/// Keys [`token_programs`] and [`ensure_atas`] read for `owner` and `mints`: each mint and
+/// `owner`'s associated token account for it under both token programs, since the
+/// program is only known once the mint is read.
+pub(crate) fn token_keys(owner: &Pubkey, mints: &[Pubkey]) -> Vec<Pubkey> {
+    mints
+        .iter()
+        .flat_map(|mint| {
+            [
+                *mint,
+                get_associated_token_address_with_program_id(owner, mint, &spl_token::ID),
+                get_associated_token_address_with_program_id(owner, mint, &spl_token_2022::ID),
+            ]
+        })
+        .collect()
+}
+
+/// Token program of each of `mints`, read from `accounts`. Their decimals are remembered
+/// for [`fetch_mint_decimals`].
+pub(crate) fn token_programs(accounts: &Prefetched, mints: &[Pubkey]) -> Result<Vec<Pubkey>> {
+    mints
+        .iter()
+        .map(|mint| {
+            let acc = accounts.require(mint, "mint")?;
+            decode_mint_decimals(mint, acc)?;
+            if acc.owner == spl_token_2022::ID {
+                Ok(spl_token_2022::ID)
+            } else {
+                Ok(spl_token::ID)
+            }
+        })
+        .collect()
+}
+
+/// Balance of the token account `pk`.
+fn decode_token_amount(pk: &Pubkey, acc: &Account) -> Result<u64> {
+    // Token and Token-2022 share the base Account layout; extensions follow it.
+    let base = acc
+        .data
+        .get(..spl_token::state::Account::LEN)
+        .with_context(|| format!("token account {} too short", pk))?;
+    let state = spl_token::state::Account::unpack_from_slice(base)
+        .with_context(|| format!("decode token account {}", pk))?;
+    Ok(state.amount)
+}
+
+/// Balance of each token account in `accounts`, read in one round trip; `None` for
+/// accounts that do not exist.
+pub(crate) async fn fetch_token_balances(
+    rpc: &RpcClient,
+    accounts: &[Pubkey],
+    commitment: CommitmentConfig,
+) -> Result<Vec<Option<u64>>> {
+    let fetched = rpc
+        .get_multiple_accounts_with_commitment(accounts, commitment)
+        .await
+        .context("fetch token accounts")?
+        .value;
+    accounts
+        .iter()
+        .zip(fetched)
+        .map(|(pk, acc)| acc.map(|acc| decode_token_amount(pk, &acc)).transpose())
+        .collect()
+}
+
+/// Queue creation of `owner`'s associated token account for each `(mint, token program)`
+/// that does not exist yet and that `ixs` does not already create, reading them from
+/// `accounts` (see [`token_keys`]). Returns each account with its balance (0 for the ones
+/// being created).
+pub(crate) fn ensure_atas(
+    accounts: &Prefetched,
+    ixs: &mut Vec<Instruction>,
+    owner: &Pubkey,
+    mints: &[(Pubkey, Pubkey)],
+) -> Result<Vec<(Pubkey, u64)>> {
+    let mut out = Vec::with_capacity(mints.len());
+    for (mint, program) in mints {
+        let ata = get_associated_token_address_with_program_id(owner, mint, program);
+        let balance = accounts.token_balance(&ata)?;
+        let queued = ixs.iter().any(|ix| {
+            ix.program_id == spl_associated_token_account::id()
+                && ix.accounts.get(1).is_some_and(|meta| meta.pubkey == ata)
+        });
+        if balance.is_none() && !queued {
+            ixs.push(create_associated_token_account(owner, owner, mint, program));
+        }
+        out.push((ata, balance.unwrap_or(0)));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> Account {
+        let mut data = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            state: spl_token::state::AccountState::Initialized,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
+        Account {
+            data,
+            owner: spl_token::ID,
+            ..Account::default()
+        }
+    }
+
+    #[test]
+    fn ensure_atas_creates_missing_accounts_once() {
+        let owner = Pubkey::new_unique();
+        let (held, missing) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let held_ata = get_associated_token_address_with_program_id(&owner, &held, &spl_token::ID);
+        let mut accounts: HashMap<_, _> = token_keys(&owner, &[held, missing])
+            .into_iter()
+            .map(|key| (key, None))
+            .collect();
+        accounts.insert(held_ata, Some(token_account(&held, &owner, 42)));
+        let accounts = Prefetched(accounts);
+
+        let mints = [(held, spl_token::ID), (missing, spl_token::ID)];
+        let mut ixs = Vec::new();
+        let out = ensure_atas(&accounts, &mut ixs, &owner, &mints).unwrap();
+        assert_eq!(out[0], (held_ata, 42));
+        assert_eq!(out[1].1, 0);
+        assert_eq!(ixs.len(), 1);
+        assert_eq!(ixs[0].accounts[1].pubkey, out[1].0);
+
+        // An account the transaction already creates is not created twice.
+        ensure_atas(&accounts, &mut ixs, &owner, &mints).unwrap();
+        assert_eq!(ixs.len(), 1);
+    }
+    #[test]
+    fn require_tells_missing_from_not_prefetched() {
+        let (absent, unread) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = Prefetched(HashMap::from([(absent, None)]));
+
+        assert!(accounts.get(&absent).unwrap().is_none());
+        assert!(accounts.get(&unread).is_err());
+        let missing = accounts.require(&absent, "pool").unwrap_err();
+        assert_eq!(missing.to_string(), format!("pool {} not found", absent));
+        let unread_err = format!("{:#}", accounts.require(&unread, "pool").unwrap_err());
+        assert!(unread_err.contains("was not prefetched"), "{}", unread_err);
+    }
+}
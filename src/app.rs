@@ -0,0 +1,93 @@
+use crate::{
+    amounts, arb, batch, cli, cluster, compare, config, devtools, keystore, log, lut, meteora, nft,
+    orca, position_ref, positions, raydium, tx,
+};
+use anyhow::{Context, Result};
+use clap::FromArgMatches;
+use dotenvy::dotenv;
+use tracing::debug;
+
+/// Parse the command line and run it: the whole `solana_liquidity_arb` binary.
+pub fn run_cli() -> Result<()> {
+    let matches = cli::command().get_matches();
+    let mut opts = cli::Opts::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    opts.apply_command()?;
+    opts.apply_urgency(&matches);
+    load_env(opts.env_file.as_deref())?;
+    config::apply(&mut opts, &matches)?;
+    // After the profile, which may set the verbosity and log file.
+    log::init(opts.verbosity, opts.log_file.as_deref())?;
+    cluster::set(opts.cluster);
+    if opts.cluster != cli::Cluster::Mainnet && opts.jito.is_some() {
+        anyhow::bail!("--jito bundles are only available on mainnet");
+    }
+    let tx_config = tx::TxConfig::from_opts(&opts)?;
+    if let Some(spec) = &opts.display_precision {
+        amounts::set_display_precision(spec)?;
+    }
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("start async runtime")?;
+    runtime.block_on(run(opts, &tx_config))?.print();
+    Ok(())
+}
+
+async fn run(mut opts: cli::Opts, tx_config: &tx::TxConfig) -> Result<tx::Report> {
+    if let Some(path) = &opts.keystore_encrypt {
+        keystore::encrypt_payer(path)?;
+        return Ok(tx::Report::default());
+    }
+    if let Some(path) = &opts.positions_file {
+        return batch::run(&opts, tx_config, path).await;
+    }
+    if let Some(wallet) = &opts.positions {
+        positions::run(&opts, wallet.as_deref()).await?;
+        return Ok(tx::Report::default());
+    }
+    if let Some(mint) = &opts.nft_info {
+        nft::run(&opts, mint).await?;
+        return Ok(tx::Report::default());
+    }
+    if let Some(mints) = &opts.compare {
+        compare::run(&opts, mints).await?;
+        return Ok(tx::Report::default());
+    }
+    if let Some(target) = &opts.equivalent_range {
+        compare::equivalent_range(&opts, target).await?;
+        return Ok(tx::Report::default());
+    }
+    if opts.bootstrap_devnet {
+        return devtools::bootstrap_devnet(&opts, tx_config).await;
+    }
+    if let Some(target) = &opts.lut {
+        return lut::run(&opts, tx_config, target).await;
+    }
+    if opts.arb_buy_pool.is_some() {
+        return arb::run(opts, tx_config).await;
+    }
+    position_ref::apply(&mut opts)?;
+    amounts::apply_ui_amounts(&mut opts).await?;
+    match opts.dex {
+        cli::Dex::Raydium => raydium::run(opts, tx_config).await,
+        cli::Dex::Orca => orca::run(opts, tx_config).await,
+        cli::Dex::Meteora => meteora::run(opts, tx_config).await,
+    }
+}
+
+/// Load `--env-file`, else `.env.<APP_ENV>` when `APP_ENV` is set, then `.env`. Variables
+/// already set win, so the profile file overrides the shared `.env` and the shell overrides both.
+fn load_env(env_file: Option<&str>) -> Result<()> {
+    let profile = match env_file {
+        Some(path) => Some(path.to_string()),
+        None => std::env::var("APP_ENV")
+            .ok()
+            .map(|env| format!(".env.{}", env)),
+    };
+    if let Some(path) = profile {
+        dotenvy::from_filename(&path).with_context(|| format!("load env file {}", path))?;
+        debug!("loaded env file {}", path);
+    }
+    dotenv().ok();
+    Ok(())
+}
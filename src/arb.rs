@@ -0,0 +1,210 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, pubkey::Pubkey,
+};
+use tracing::{debug, instrument};
+
+use crate::cli::{Dex, Opts};
+use crate::error::Error;
+use crate::quote::{SwapLeg, SwapQuote, enforce_max_price_impact, slippage_min_out};
+use crate::signer;
+use crate::tx::{
+    Flow, Report, TxConfig, build_unwrap_sol_ix, build_wrap_sol_ixs, simulate_preview,
+};
+use crate::{amounts, orca, raydium, rpc};
+
+/// Buy on `--arb-buy-pool` and sell back on `--arb-sell-pool` in a single transaction.
+/// The sell leg's min-out is `--swap-amount-in + --min-profit`, so the
+/// simulation in [`Flow::send`] rejects an unprofitable arb before anything is sent.
+#[instrument(name = "arb", skip_all)]
+pub async fn run(opts: Opts, config: &TxConfig) -> Result<Report> {
+    let buy_pool = Pubkey::from_str(opts.arb_buy_pool.as_deref().context("--arb-buy-pool")?)
+        .context("invalid --arb-buy-pool")?;
+    let sell_pool = Pubkey::from_str(
+        opts.arb_sell_pool
+            .as_deref()
+            .context("--arb-sell-pool is required with --arb-buy-pool")?,
+    )
+    .context("invalid --arb-sell-pool")?;
+    let start_mint = Pubkey::from_str(
+        opts.arb_mint
+            .as_deref()
+            .context("--arb-mint is required with --arb-buy-pool")?,
+    )
+    .context("invalid --arb-mint")?;
+    if opts.swap_amount_in == 0 {
+        bail!("--swap-amount-in must be > 0");
+    }
+
+    let rpc = rpc::client(&opts);
+
+    let wallet = signer::load_payer(&opts).await?;
+    let payer_pk = wallet.pubkey();
+    let mut flow = Flow::new(&rpc, config, wallet.as_ref());
+
+    let (buy0, buy1) = pool_mints(&rpc, opts.arb_buy_dex, &buy_pool).await?;
+    let mid_mint = if buy0 == start_mint {
+        buy1
+    } else if buy1 == start_mint {
+        buy0
+    } else {
+        bail!(
+            "--arb-mint {} is not a token of buy pool {}",
+            start_mint,
+            buy_pool
+        );
+    };
+    let (sell0, sell1) = pool_mints(&rpc, opts.arb_sell_dex, &sell_pool).await?;
+    if (sell0, sell1) != (start_mint, mid_mint) && (sell0, sell1) != (mid_mint, start_mint) {
+        bail!(
+            "sell pool {} does not trade {} against {}",
+            sell_pool,
+            mid_mint,
+            start_mint
+        );
+    }
+
+    let mut ixs: Vec<Instruction> = vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
+        ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
+    ];
+    if opts.wrap_sol > 0 {
+        debug!("wrapping {} lamports into WSOL", opts.wrap_sol);
+        ixs.extend(build_wrap_sol_ixs(&rpc, &payer_pk, opts.wrap_sol).await?);
+    }
+
+    // The buy leg must return at least what the sell leg spends, so the sell leg can
+    // never dip into mid-mint tokens the wallet already held. Without --slippage-bps it
+    // must fill at its quote.
+    let buy_leg = SwapLeg {
+        pool: buy_pool,
+        a_to_b: buy0 == start_mint,
+        amount_in: opts.swap_amount_in,
+        min_out: 0,
+        sqrt_price_limit: 0,
+        slippage_bps: Some(opts.slippage_bps.unwrap_or(0)),
+    };
+    let buy_quote = build_swap_ixs(&rpc, opts.arb_buy_dex, &payer_pk, &buy_leg, &mut ixs).await?;
+    enforce_max_price_impact(&buy_quote, opts.max_price_impact_bps)?;
+    let buy_min_out = slippage_min_out(&buy_quote, buy_leg.min_out, buy_leg.slippage_bps);
+    let min_back = opts
+        .swap_amount_in
+        .checked_add(opts.min_profit)
+        .context("--swap-amount-in + --min-profit overflows u64")?;
+    let sell_leg = SwapLeg {
+        pool: sell_pool,
+        a_to_b: sell0 == mid_mint,
+        amount_in: buy_min_out,
+        min_out: min_back,
+        sqrt_price_limit: 0,
+        slippage_bps: None,
+    };
+    let sell_quote =
+        build_swap_ixs(&rpc, opts.arb_sell_dex, &payer_pk, &sell_leg, &mut ixs).await?;
+    enforce_max_price_impact(&sell_quote, opts.max_price_impact_bps)?;
+    debug!(
+        "arb quote: {} {} -> {} {} -> {} {} (profit {}{} base)",
+        amounts::format_amount(&rpc, &start_mint, opts.swap_amount_in).await,
+        start_mint,
+        amounts::format_amount(&rpc, &mid_mint, buy_quote.amount_out).await,
+        mid_mint,
+        amounts::format_amount(&rpc, &start_mint, sell_quote.amount_out).await,
+        start_mint,
+        if sell_quote.amount_out < opts.swap_amount_in {
+            "-"
+        } else {
+            ""
+        },
+        amounts::group_thousands(sell_quote.amount_out.abs_diff(opts.swap_amount_in) as u128)
+    );
+    if sell_quote.amount_out < min_back {
+        bail!(
+            "quoted return {} is below --swap-amount-in + --min-profit ({}); not sending",
+            sell_quote.amount_out,
+            min_back
+        );
+    }
+
+    // Both legs create any missing ATAs for the shared mints; keep one of each.
+    let mut unique: Vec<Instruction> = Vec::with_capacity(ixs.len());
+    for ix in ixs {
+        if !unique.contains(&ix) {
+            unique.push(ix);
+        }
+    }
+    let mut ixs = unique;
+    if opts.unwrap_sol {
+        ixs.push(build_unwrap_sol_ix(&payer_pk));
+    }
+
+    check_mid_inventory(&rpc, &payer_pk, &mid_mint, &ixs).await?;
+
+    let sent = match flow.send(ixs, &[]).await {
+        Ok(sent) => sent,
+        Err(Error::SlippageExceeded { .. }) => bail!(
+            "simulated sell leg returned less than {} (--swap-amount-in + --min-profit); not sending",
+            min_back
+        ),
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(sig) = sent.signature() {
+        println!(
+            "✅ Arb submitted. Tx: {} (amount_in={}, min_back={})",
+            sig,
+            amounts::format_amount(&rpc, &start_mint, opts.swap_amount_in).await,
+            amounts::format_amount(&rpc, &start_mint, min_back).await
+        );
+    }
+    Ok(flow.finish().await?)
+}
+
+/// Refuse an arb whose simulation leaves the wallet with less of `mid_mint` than it
+/// started with: the profit would then come out of inventory the wallet already held.
+async fn check_mid_inventory(
+    rpc: &RpcClient,
+    payer_pk: &Pubkey,
+    mid_mint: &Pubkey,
+    ixs: &[Instruction],
+) -> Result<()> {
+    let preview = simulate_preview(rpc, payer_pk, ixs).await?;
+    // A failing simulation is reported by `Flow::send`, with its hints.
+    if preview.err.is_some() {
+        return Ok(());
+    }
+    for d in &preview.token_deltas {
+        if d.mint == *mid_mint && d.owner == *payer_pk && d.after < d.before {
+            bail!(
+                "arb would spend {} of the {} already held in {}; not sending",
+                d.before - d.after,
+                mid_mint,
+                d.account
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn pool_mints(rpc: &RpcClient, dex: Dex, pool: &Pubkey) -> Result<(Pubkey, Pubkey)> {
+    match dex {
+        Dex::Raydium => raydium::pool_mints(rpc, pool).await,
+        Dex::Orca => orca::pool_mints(rpc, pool).await,
+        Dex::Meteora => bail!("arb legs support raydium and orca pools only"),
+    }
+}
+
+async fn build_swap_ixs(
+    rpc: &RpcClient,
+    dex: Dex,
+    payer_pk: &Pubkey,
+    leg: &SwapLeg,
+    ixs: &mut Vec<Instruction>,
+) -> Result<SwapQuote> {
+    match dex {
+        Dex::Raydium => raydium::build_swap_ixs(rpc, payer_pk, leg, ixs).await,
+        Dex::Orca => orca::build_swap_ixs(rpc, payer_pk, leg, ixs).await,
+        Dex::Meteora => bail!("arb legs support raydium and orca pools only"),
+    }
+}
@@ -0,0 +1,353 @@
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, instruction::Instruction, pubkey::Pubkey,
+};
+use tracing::{info, instrument, warn};
+
+use crate::cli::{Dex, Opts};
+use crate::tx::{Flow, Report, Sent, TxConfig, build_unwrap_sol_ix};
+use crate::{meteora, orca, position_ref, raydium, rpc, signer};
+
+/// What a `--positions-file` row asks for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Action {
+    Collect,
+    /// Remove this percentage (Raydium only below 100) and leave the position open.
+    Remove(u8),
+    /// Remove everything and close the position.
+    Close,
+}
+
+#[derive(Debug)]
+struct Row {
+    /// Line of a CSV row, or 1-based index of a JSON entry.
+    line: usize,
+    dex: Dex,
+    position: String,
+    action: Action,
+}
+
+/// A `--positions-file` entry in JSON form; the fields read like the CSV columns.
+#[derive(Deserialize)]
+struct JsonRow {
+    #[serde(default)]
+    dex: String,
+    position: String,
+    action: String,
+}
+
+/// Run the `--positions-file` rows in file order. Consecutive rows share a transaction
+/// while it still fits and simulates within `--cu-limit`. A row that fails to build is
+/// reported and skipped, a transaction that fails fails every row in it, and the summary
+/// lists each outcome.
+///
+/// The file is either CSV, one `dex,position,action` row per line (blank lines and `#`
+/// comments are skipped), or a JSON array of `{"dex", "position", "action"}` objects.
+/// Actions are `collect`, `remove`, `remove:<percent>` or `close`. The dex may be left
+/// empty when the position is a `ray:`/`orca:`/`met:` reference.
+#[instrument(name = "batch", skip_all)]
+pub async fn run(opts: &Opts, config: &TxConfig, path: &str) -> Result<Report> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("read {}", path))?;
+    let rows = if text.trim_start().starts_with('[') {
+        parse_json(&text).with_context(|| format!("parse {}", path))?
+    } else {
+        parse_rows(&text)?
+    };
+    if rows.is_empty() {
+        bail!("{} has no rows", path);
+    }
+
+    let rpc = rpc::client(opts);
+    let wallet = signer::load_payer(opts).await?;
+    let payer_pk = wallet.pubkey();
+    let mut flow = Flow::new(&rpc, config, wallet.as_ref());
+    let budget = || {
+        vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
+        ]
+    };
+
+    let mut outcomes: Vec<Option<Result<String, String>>> = vec![None; rows.len()];
+    let mut tx_ixs = budget();
+    let mut tx_rows = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        info!(
+            "row {}/{} (line {}): {:?} {} on {:?}",
+            i + 1,
+            rows.len(),
+            row.line,
+            row.action,
+            row.position,
+            row.dex
+        );
+        let mut packed = tx_ixs.clone();
+        if let Err(e) = row_ixs(&rpc, &payer_pk, opts, row, &mut packed).await {
+            warn!("line {} failed: {:#}", row.line, e);
+            outcomes[i] = Some(Err(format!("{:#}", e)));
+            continue;
+        }
+        if tx_rows.is_empty() || flow.fits_budget(&packed, opts.cu_limit).await {
+            tx_ixs = packed;
+            tx_rows.push(i);
+            continue;
+        }
+        let full = std::mem::replace(&mut tx_ixs, budget());
+        send_rows(
+            &mut flow,
+            full,
+            &std::mem::take(&mut tx_rows),
+            &mut outcomes,
+        )
+        .await;
+        // Build the row again on its own: the packed attempt left out token accounts the
+        // transaction just sent was creating.
+        match row_ixs(&rpc, &payer_pk, opts, row, &mut tx_ixs).await {
+            Ok(()) => tx_rows.push(i),
+            Err(e) => {
+                warn!("line {} failed: {:#}", row.line, e);
+                outcomes[i] = Some(Err(format!("{:#}", e)));
+            }
+        }
+    }
+    if !tx_rows.is_empty() {
+        send_rows(&mut flow, tx_ixs, &tx_rows, &mut outcomes).await;
+    }
+    if opts.unwrap_sol {
+        match flow.send(vec![build_unwrap_sol_ix(&payer_pk)], &[]).await {
+            Ok(sent) => {
+                if let Some(sig) = sent.signature() {
+                    println!("✅ Unwrapped WSOL. Tx: {}", sig);
+                }
+            }
+            Err(e) => warn!("unwrap failed: {:#}", e),
+        }
+    }
+    let report = flow.finish().await;
+
+    let failed = outcomes
+        .iter()
+        .filter(|o| !matches!(o, Some(Ok(_))))
+        .count();
+    println!(
+        "Batch summary: {} ok, {} failed",
+        rows.len() - failed,
+        failed
+    );
+    for (row, outcome) in rows.iter().zip(&outcomes) {
+        let dex = format!("{:?}", row.dex).to_lowercase();
+        let action = format!("{:?}", row.action);
+        let outcome = match outcome {
+            Some(Ok(done)) => done.clone(),
+            Some(Err(e)) => format!("FAILED: {}", e),
+            None => "FAILED: not run".to_string(),
+        };
+        println!(
+            "  line {:<4} {:<8} {:<12} {} {}",
+            row.line, dex, action, row.position, outcome
+        );
+    }
+    let report = report?;
+    if failed > 0 {
+        bail!("{} of {} rows failed", failed, rows.len());
+    }
+    Ok(report)
+}
+
+/// Send the instructions of the rows `tx_rows` as one transaction and record how it went
+/// for each of them.
+async fn send_rows(
+    flow: &mut Flow<'_>,
+    ixs: Vec<Instruction>,
+    tx_rows: &[usize],
+    outcomes: &mut [Option<Result<String, String>>],
+) {
+    let outcome = match flow.send(ixs, &[]).await {
+        Ok(Sent::Signed(sig)) => Ok(format!("ok {}", sig)),
+        Ok(Sent::Simulated(_)) => Ok("quoted".to_string()),
+        Ok(Sent::Exported(_)) => Ok("exported".to_string()),
+        Err(e) => {
+            warn!("transaction of {} row(s) failed: {:#}", tx_rows.len(), e);
+            Err(format!("{:#}", e))
+        }
+    };
+    for &i in tx_rows {
+        outcomes[i] = Some(outcome.clone());
+    }
+}
+
+/// Queue `row`'s instructions onto `ixs`, with the batch-wide flags of `opts`.
+async fn row_ixs(
+    rpc: &RpcClient,
+    payer_pk: &Pubkey,
+    opts: &Opts,
+    row: &Row,
+    ixs: &mut Vec<Instruction>,
+) -> Result<()> {
+    let row_opts = row_opts(opts, row);
+    match row.dex {
+        Dex::Raydium => raydium::removal_ixs(rpc, payer_pk, &row_opts, ixs).await,
+        Dex::Orca => orca::removal_ixs(rpc, payer_pk, &row_opts, ixs).await,
+        Dex::Meteora => meteora::removal_ixs(rpc, payer_pk, &row_opts, ixs).await,
+    }
+}
+
+/// `opts` with every per-command field cleared and `row`'s action set in their place.
+fn row_opts(opts: &Opts, row: &Row) -> Opts {
+    let mut row_opts = opts.clone();
+    row_opts.positions_file = None;
+    row_opts.dex = row.dex;
+    row_opts.swap_pool = None;
+    row_opts.pool = None;
+    row_opts.resize_position = None;
+    row_opts.seed_mint = None;
+    row_opts.wrap_sol = 0;
+    row_opts.unwrap_sol = false;
+    row_opts.remove_liquidity = None;
+    row_opts.remove_percent = None;
+    row_opts.collect_fees = None;
+    row_opts.remove_position = None;
+    row_opts.close = false;
+    match row.action {
+        Action::Collect => row_opts.collect_fees = Some(row.position.clone()),
+        Action::Remove(percent) => {
+            row_opts.remove_position = Some(row.position.clone());
+            if percent < 100 {
+                row_opts.remove_percent = Some(percent);
+            }
+        }
+        Action::Close => {
+            row_opts.remove_position = Some(row.position.clone());
+            row_opts.close = true;
+        }
+    }
+    row_opts
+}
+
+fn parse_json(text: &str) -> Result<Vec<Row>> {
+    let entries: Vec<JsonRow> = serde_json::from_str(text)?;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| parse_row(i + 1, &entry.dex, &entry.position, &entry.action))
+        .collect()
+}
+
+fn parse_rows(text: &str) -> Result<Vec<Row>> {
+    let mut rows = Vec::new();
+    for (i, raw) in text.lines().enumerate() {
+        let line = i + 1;
+        let raw = raw.trim();
+        if raw.is_empty() || raw.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = raw.split(',').map(str::trim).collect();
+        let [dex, position, action] = fields[..] else {
+            bail!(
+                "line {}: expected `dex,position,action`, got {:?}",
+                line,
+                raw
+            );
+        };
+        // Tolerate a header row.
+        if dex.eq_ignore_ascii_case("dex") {
+            continue;
+        }
+        rows.push(parse_row(line, dex, position, action)?);
+    }
+    Ok(rows)
+}
+
+fn parse_row(line: usize, dex: &str, position: &str, action: &str) -> Result<Row> {
+    let (venue, position) =
+        position_ref::split(position).with_context(|| format!("line {}", line))?;
+    let dex = match (dex.trim().to_ascii_lowercase().as_str(), venue) {
+        ("", Some(venue)) => venue,
+        ("", None) => bail!(
+            "line {}: no dex and no venue prefix on {:?}",
+            line,
+            position
+        ),
+        ("raydium", _) => Dex::Raydium,
+        ("orca", _) => Dex::Orca,
+        ("meteora", _) => Dex::Meteora,
+        (other, _) => bail!("line {}: unknown dex {:?}", line, other),
+    };
+    if let Some(venue) = venue
+        && venue != dex
+    {
+        bail!(
+            "line {}: dex {:?} but position is on {:?}",
+            line,
+            dex,
+            venue
+        );
+    }
+    let action = match action.trim().to_ascii_lowercase().as_str() {
+        "collect" => Action::Collect,
+        "remove" => Action::Remove(100),
+        "close" => Action::Close,
+        other => match other.strip_prefix("remove:") {
+            Some(p) => {
+                let percent: u8 = p
+                    .trim_end_matches('%')
+                    .parse()
+                    .with_context(|| format!("line {}: bad percent {:?}", line, p))?;
+                if !(1..=100).contains(&percent) {
+                    bail!("line {}: percent must be 1-100, got {}", line, percent);
+                }
+                Action::Remove(percent)
+            }
+            None => bail!("line {}: unknown action {:?}", line, other),
+        },
+    };
+    Ok(Row {
+        line,
+        dex,
+        position: position.to_string(),
+        action,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn json_rows_read_like_csv_rows() {
+        let csv =
+            parse_rows("dex,position,action\nraydium,AAA,remove:50\n,met:BBB,close\n").unwrap();
+        let json = parse_json(
+            r#"[{"dex": "raydium", "position": "AAA", "action": "remove:50"},
+                {"position": "met:BBB", "action": "close"}]"#,
+        )
+        .unwrap();
+        assert_eq!(csv.len(), 2);
+        assert_eq!(json.len(), 2);
+        for (c, j) in csv.iter().zip(&json) {
+            assert_eq!(
+                (c.dex, &c.position, c.action),
+                (j.dex, &j.position, j.action)
+            );
+        }
+        assert_eq!(json[1].dex, Dex::Meteora);
+        assert_eq!(json[1].line, 2);
+    }
+
+    #[test]
+    fn row_opts_clear_the_previous_command() {
+        let mut opts = Opts::parse_from(["solana_liquidity_arb", "--unwrap-sol", "--close"]);
+        opts.resize_position = Some("CCC".to_string());
+        opts.seed_mint = Some("DDD".to_string());
+        let row = parse_row(1, "orca", "AAA", "collect").unwrap();
+        let row_opts = row_opts(&opts, &row);
+        assert_eq!(row_opts.resize_position, None);
+        assert_eq!(row_opts.seed_mint, None);
+        assert!(!row_opts.unwrap_sol && !row_opts.close);
+        assert_eq!(row_opts.collect_fees.as_deref(), Some("AAA"));
+    }
+}
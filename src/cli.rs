@@ -1,7 +1,8 @@
-use clap::{Parser, ValueEnum};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Parser, Subcommand, ValueEnum};
 
 /// Mainnet helper for Raydium, Orca & Meteora CLMM/DLMM and WSOL utilities.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(
     version,
     about = "CLMM/DLMM helper for Raydium, Orca & Meteora (open/remove position, swap, wrap/unwrap SOL)."
@@ -11,13 +12,142 @@ pub struct Opts {
     #[arg(long, value_enum, default_value_t = Dex::Raydium)]
     pub dex: Dex,
 
-    /// Optional mainnet RPC URL (defaults to env RPC_URL or public mainnet RPC)
+    /// Action on --dex. The flags below may follow it; --swap-pool, --remove-position,
+    /// --collect-fees, --pool (open) and --wrap-sol/--unwrap-sol still work without one
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Optional mainnet RPC URL (defaults to env RPC_URLS, RPC_URL or public mainnet RPC).
+    /// Repeat it or give a comma-separated list to fail over between endpoints
+    #[arg(long, value_delimiter = ',')]
+    pub rpc: Vec<String>,
+
+    /// Optional: at most this many requests per second to each RPC endpoint
     #[arg(long)]
-    pub rpc: Option<String>,
+    pub rpc_rate_limit: Option<f64>,
 
-    /// Optional: microlamports per CU for priority fees (default 1000)
-    #[arg(long, default_value_t = 1000)]
-    pub cu_price: u64,
+    /// Network (mainnet|devnet|localnet): picks the DEX program ids and, without --rpc or
+    /// RPC_URL, the public RPC of that cluster
+    #[arg(long, value_enum, default_value_t = Cluster::Mainnet)]
+    pub cluster: Cluster,
+
+    /// Optional: microlamports per CU for priority fees (default 1000, or the --urgency preset),
+    /// or `auto` to pick a percentile of the recent fees paid on the transaction's writable accounts
+    #[arg(long, value_parser = parse_cu_price)]
+    pub cu_price: Option<CuPrice>,
+
+    /// Percentile of recent prioritization fees used by --cu-price auto (1-100)
+    #[arg(long, default_value_t = 75, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub cu_price_percentile: u8,
+
+    /// Optional: preset (chill|normal|urgent|degen) for the priority fee, --cu-price-percentile,
+    /// --slippage-bps, --jito-tip-lamports and front-running protection (urgent and degen
+    /// send Jito bundles on mainnet); each flag given explicitly wins over it
+    #[arg(long, value_enum)]
+    pub urgency: Option<Urgency>,
+
+    /// Digits shown after the decimal point in printed token amounts: N for every token,
+    /// MINT=N for one, comma-separated (default: all of the mint's decimals)
+    #[arg(long, value_name = "SPEC")]
+    pub display_precision: Option<String>,
+
+    /// Send a command's transactions together as a Jito bundle to this block engine URL
+    /// (e.g. https://mainnet.block-engine.jito.wtf) instead of the RPC
+    #[arg(long, value_name = "URL")]
+    pub jito: Option<String>,
+
+    /// Tip paid to a Jito tip account with each --jito bundle (lamports, min 1000)
+    #[arg(long, default_value_t = 10_000, value_parser = clap::value_parser!(u64).range(1000..))]
+    pub jito_tip_lamports: u64,
+
+    /// Take defaults (cluster, rpc, cu_price, cu_limit, slippage_bps, urgency) from this profile of the config
+    /// file (CONFIG_FILE, default ~/.config/solana-liq-arb/config.toml)
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Load environment variables from this file (before .env) instead of .env.<APP_ENV>
+    #[arg(long, value_name = "FILE")]
+    pub env_file: Option<String>,
+
+    /// Log level: quiet (warnings only), info, debug, or trace (adds simulation logs)
+    #[arg(long, value_enum, default_value_t = Verbosity::Info)]
+    pub verbosity: Verbosity,
+
+    /// Append log lines to this file instead of stderr
+    #[arg(long, value_name = "PATH")]
+    pub log_file: Option<String>,
+
+    /// Payer key: `mnemonic:<words>` for a BIP39 seed phrase (Phantom/Solflare),
+    /// `keystore:<file>`, `remote:<url>`, a keypair file path, a JSON byte array or a
+    /// base58 keypair; env MNEMONIC works too
+    #[arg(long, value_name = "SOURCE")]
+    pub payer: Option<String>,
+
+    /// Use this named wallet from the wallets file (WALLETS_FILE, default
+    /// ~/.config/solana-liq-arb/wallets.toml) as the payer
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["payer", "keystore", "remote_signer"])]
+    pub wallet: Option<String>,
+
+    /// BIP44 derivation path for a mnemonic payer
+    #[arg(long, value_name = "PATH", default_value = "m/44'/501'/0'/0'")]
+    pub derivation: String,
+
+    /// Decrypt the payer from this keystore file (see --keystore-encrypt) instead of
+    /// PRIVATE_KEY_B58; env KEYSTORE_PATH works too
+    #[arg(long, value_name = "FILE")]
+    pub keystore: Option<String>,
+
+    /// Encrypt the PRIVATE_KEY_B58 keypair with a passphrase (KEYSTORE_PASSWORD or a
+    /// prompt) into this new keystore file, then exit
+    #[arg(long, value_name = "FILE")]
+    pub keystore_encrypt: Option<String>,
+
+    /// Sign through this remote signing service (JSON-RPC `getPublicKey`/`signMessage`)
+    /// instead of the PRIVATE_KEY_B58 keypair
+    #[arg(long, value_name = "URL")]
+    pub remote_signer: Option<String>,
+
+    /// Re-sign and resend a transaction this many times when its blockhash expires unconfirmed
+    #[arg(long, default_value_t = 2)]
+    pub max_retries: u32,
+
+    /// Rebroadcast an unconfirmed transaction (skipping preflight) every this many milliseconds
+    #[arg(long, default_value_t = 2000, value_parser = clap::value_parser!(u64).range(200..))]
+    pub resend_interval_ms: u64,
+
+    /// Sign against this durable nonce account's blockhash (advancing it first) instead of a
+    /// recent blockhash, so a signed transaction does not expire after ~60s
+    #[arg(long, value_name = "PUBKEY")]
+    pub nonce_account: Option<String>,
+
+    /// Keypair file (Solana CLI JSON) of the --nonce-account authority (default: the payer)
+    #[arg(long, value_name = "FILE", requires = "nonce_account")]
+    pub nonce_authority: Option<String>,
+
+    /// Write each transaction of a swap/open/remove to this file (base64 wire format plus
+    /// its required signers, as JSON; later ones to PATH-2, PATH-3, ... before the
+    /// extension) for external signing instead of sending them
+    #[arg(long, value_name = "PATH", requires = "export_payer")]
+    pub export_tx: Option<String>,
+
+    /// Fee payer and authority of the --export-tx transaction (e.g. a multisig vault);
+    /// no private key is loaded
+    #[arg(long, value_name = "PUBKEY", requires = "export_tx")]
+    pub export_payer: Option<String>,
+
+    /// Wallet other than the payer that transactions may pay tokens or SOL to (repeatable;
+    /// env ALLOWED_DESTINATIONS takes a comma-separated list). Anything else is refused
+    #[arg(long, value_name = "PUBKEY")]
+    pub allow_destination: Vec<String>,
+
+    /// Simulate each transaction of a swap/open/remove and print its token and SOL
+    /// changes as a quote; no key is loaded and nothing is signed or sent
+    #[arg(long, default_value_t = false)]
+    pub quote: bool,
+
+    /// Set by the `cost` subcommand.
+    #[arg(skip)]
+    pub cost: bool,
 
     /// Optional: compute unit limit (default 1_200_000)
     #[arg(long, default_value_t = 1_200_000)]
@@ -27,7 +157,50 @@ pub struct Opts {
     #[arg(long)]
     pub remove_position: Option<String>,
 
-    /// Min amount of token0 to receive when removing (default 0)
+    /// Raydium: remove all liquidity from this position NFT mint, close it, and reopen what
+    /// came out on the same pool over --lower/--upper (or --price-min/--price-max, --width)
+    #[arg(long, value_name = "MINT", conflicts_with_all = ["remove_position", "collect_fees", "swap_pool", "pool"])]
+    pub resize_position: Option<String>,
+
+    /// Raydium: create the CLMM pool of this new token mint, then open the initial range
+    /// (--lower/--upper, --price-min/--price-max or --width) with --amount0/--amount1
+    #[arg(long, value_name = "MINT", requires_all = ["seed_quote_mint", "seed_price", "amm_config"], conflicts_with_all = ["pool", "swap_pool", "remove_position", "collect_fees"])]
+    pub seed_mint: Option<String>,
+
+    /// --seed-mint: the token it is paired with (e.g. WSOL or USDC)
+    #[arg(long, value_name = "MINT", requires = "seed_mint")]
+    pub seed_quote_mint: Option<String>,
+
+    /// --seed-mint: initial price in quote tokens per seed token (UI units); --price-min and
+    /// --price-max are then read in the same units
+    #[arg(long, requires = "seed_mint")]
+    pub seed_price: Option<f64>,
+
+    /// --seed-mint: Raydium CLMM AmmConfig account (fee tier and tick spacing) of the new
+    /// pool; `devtools bootstrap-devnet` defaults to the program's first one (index 0)
+    #[arg(long, value_name = "PUBKEY")]
+    pub amm_config: Option<String>,
+
+    /// Airdrop SOL, create two test mints, their Raydium CLMM pool and a seed position on
+    /// --cluster devnet or localnet, then exit
+    #[arg(long, default_value_t = false)]
+    pub bootstrap_devnet: bool,
+
+    /// Raydium: remove only this much liquidity and leave the position open
+    #[arg(long)]
+    pub remove_liquidity: Option<u128>,
+
+    /// Raydium: remove this percentage (1-100) of the position's liquidity
+    #[arg(long)]
+    pub remove_percent: Option<u8>,
+
+    /// Collect fees and rewards for this position (Raydium/Orca NFT mint, Meteora Position account)
+    /// without removing liquidity.
+    #[arg(long)]
+    pub collect_fees: Option<String>,
+
+    /// Min amount of token0 to receive when removing (default 0). Meteora checks it
+    /// against the simulated payout, as DLMM removals take no min-outs
     #[arg(long, default_value_t = 0)]
     pub min_out0: u64,
 
@@ -35,6 +208,10 @@ pub struct Opts {
     #[arg(long, default_value_t = 0)]
     pub min_out1: u64,
 
+    /// Wallet that holds the position NFT when the signer acts as its SPL delegate (Orca only)
+    #[arg(long)]
+    pub nft_owner: Option<String>,
+
     /// Also closes (burns) the position NFT after removing all liquidity
     #[arg(long)]
     pub close: bool,
@@ -51,6 +228,48 @@ pub struct Opts {
     #[arg(long)]
     pub upper: Option<i32>,
 
+    /// Open: lowest price of the range, in token1 per token0 (UI units); replaces --lower/--upper
+    #[arg(long, requires = "price_max", conflicts_with_all = ["lower", "upper"])]
+    pub price_min: Option<f64>,
+
+    /// Open: highest price of the range, in token1 per token0 (UI units)
+    #[arg(long, requires = "price_min")]
+    pub price_max: Option<f64>,
+
+    /// Open: range of this total width centered on the current price, e.g. `150bps`, or
+    /// `2sigma` for two standard deviations of the pool's observed price either way (Raydium)
+    #[arg(long, value_name = "WIDTH", value_parser = parse_width,
+          conflicts_with_all = ["lower", "upper", "price_min", "price_max"])]
+    pub width: Option<PriceRange>,
+
+    /// Raydium open: score ranges of 1, 2, 4, ... tick spacings around the current price on
+    /// the pool's recent observations (fees on the observed price moves net of divergence
+    /// loss), print them and open the best one (add --quote to stop before sending)
+    #[arg(long, default_value_t = false,
+          conflicts_with_all = ["lower", "upper", "price_min", "price_max", "width"])]
+    pub optimize_range: bool,
+
+    /// Raydium open: snap --lower/--upper to the closest range an existing protocol position already uses (shares rent)
+    #[arg(long, default_value_t = false)]
+    pub snap_to_existing: bool,
+
+    /// Raydium open: if the wallet already holds a position on exactly this range, add liquidity to it
+    #[arg(long, default_value_t = false)]
+    pub reuse_existing: bool,
+
+    /// Open: round --lower/--upper to the nearest multiples of the pool's tick spacing instead of failing
+    #[arg(long, default_value_t = false)]
+    pub snap: bool,
+
+    /// Raydium open: use this keypair file (Solana CLI JSON) as the position NFT mint
+    /// instead of a fresh random one
+    #[arg(long, value_name = "FILE")]
+    pub position_mint_keypair: Option<String>,
+
+    /// Open: wallet that will own the new position NFT (default: the payer)
+    #[arg(long)]
+    pub position_owner: Option<String>,
+
     /// Max amount of token0 to deposit (base units, u64; e.g., 1 SOL = 1_000_000_000)
     #[arg(long, default_value_t = 0)]
     pub amount0: u64,
@@ -59,6 +278,14 @@ pub struct Opts {
     #[arg(long, default_value_t = 0)]
     pub amount1: u64,
 
+    /// Max amount of token0 to deposit, in UI units (scaled by the mint's decimals; replaces --amount0)
+    #[arg(long, conflicts_with = "amount0")]
+    pub amount0_ui: Option<f64>,
+
+    /// Max amount of token1 to deposit, in UI units (replaces --amount1)
+    #[arg(long, conflicts_with = "amount1")]
+    pub amount1_ui: Option<f64>,
+
     /// Wrap this many lamports into WSOL (standalone if no open/remove args)
     #[arg(long, default_value_t = 0)]
     pub wrap_sol: u64,
@@ -76,6 +303,10 @@ pub struct Opts {
     #[arg(long, default_value_t = 0)]
     pub swap_amount_in: u64,
 
+    /// Swap input amount in UI units of the input token (replaces --swap-amount-in)
+    #[arg(long, conflicts_with_all = ["swap_amount_in", "arb_buy_pool"])]
+    pub swap_amount_in_ui: Option<f64>,
+
     /// Minimum output amount (base units) to receive for the swap
     #[arg(long, default_value_t = 0)]
     pub swap_min_out: u64,
@@ -87,12 +318,531 @@ pub struct Opts {
     /// Optional sqrt price limit (Q64.64); default 0 uses protocol min/max
     #[arg(long, default_value_t = 0)]
     pub swap_sqrt_price_limit: u128,
+
+    /// Swaps: derive --swap-min-out from the off-chain quote minus this many bps.
+    /// Removals: raise both min-outs to the expected amounts minus that many bps (a higher
+    /// --min-out0/--min-out1 still wins)
+    #[arg(
+        long,
+        alias = "remove-slippage-bps",
+        value_parser = clap::value_parser!(u64).range(0..=10_000)
+    )]
+    pub slippage_bps: Option<u64>,
+
+    /// Abort the swap if the off-chain quote's price impact exceeds this many bps
+    #[arg(long)]
+    pub max_price_impact_bps: Option<u64>,
+
+    // --- POSITIONS mode ---
+    /// List open Raydium, Orca & Meteora positions of WALLET (default: the signer) and exit
+    #[arg(long, value_name = "WALLET")]
+    pub positions: Option<Option<String>>,
+
+    /// Run the collect/remove/close rows of this CSV (`dex,position,action`) or JSON file,
+    /// packing consecutive rows into shared transactions, then print a summary
+    #[arg(long, value_name = "FILE")]
+    pub positions_file: Option<String>,
+
+    /// Raydium: list everyone's positions on --pool (only on exactly --lower/--upper if given) and exit
+    #[arg(long, default_value_t = false)]
+    pub find_positions: bool,
+
+    // --- NFT mode ---
+    /// Print the metadata of this position NFT mint and the Raydium/Orca position behind it, then exit
+    #[arg(long)]
+    pub nft_info: Option<String>,
+
+    // --- LUT mode ---
+    /// Create the address lookup table for this pool or position (or extend the stored one)
+    /// and exit
+    #[arg(long, value_name = "POOL|POSITION")]
+    pub lut: Option<String>,
+
+    /// JSON file keeping the lookup table of each pool and position --lut has set up
+    /// (default: ~/.config/solana-liq-arb/luts.json)
+    #[arg(long, value_name = "FILE")]
+    pub lut_file: Option<String>,
+
+    // --- COMPARE mode ---
+    /// Compare MINT_IN -> MINT_OUT prices across Raydium, Orca & Meteora and exit
+    #[arg(long, num_args = 2, value_names = ["MINT_IN", "MINT_OUT"])]
+    pub compare: Option<Vec<String>>,
+
+    /// Print the range on this pool (venue-prefixed, e.g. met:<lb_pair>) that covers the
+    /// same prices as --lower/--upper on --pool (--dex), then exit
+    #[arg(long, value_name = "VENUE:POOL", requires_all = ["pool", "lower", "upper"])]
+    pub equivalent_range: Option<String>,
+
+    /// Notional for --compare, in base units of MINT_IN
+    #[arg(long, default_value_t = 0)]
+    pub compare_amount: u64,
+
+    // --- ARB mode ---
+    /// Two-leg arb: buy on this pool, paying --arb-mint, then sell back on --arb-sell-pool in one tx
+    #[arg(long)]
+    pub arb_buy_pool: Option<String>,
+
+    /// DEX of --arb-buy-pool (raydium|orca)
+    #[arg(long, value_enum, default_value_t = Dex::Raydium)]
+    pub arb_buy_dex: Dex,
+
+    /// Pool that sells the bought token back into --arb-mint
+    #[arg(long)]
+    pub arb_sell_pool: Option<String>,
+
+    /// DEX of --arb-sell-pool (raydium|orca)
+    #[arg(long, value_enum, default_value_t = Dex::Orca)]
+    pub arb_sell_dex: Dex,
+
+    /// Token spent on the buy leg (--swap-amount-in of it) and received on the sell leg
+    #[arg(long)]
+    pub arb_mint: Option<String>,
+
+    /// Arb must return at least --swap-amount-in plus this much --arb-mint, in its base
+    /// units (lamports for WSOL)
+    #[arg(long, alias = "min-profit-lamports", default_value_t = 0)]
+    pub min_profit: u64,
+
+    // --- TWAP mode ---
+    /// Print the on-chain TWAP for this pool (Raydium observations / Meteora oracle) and exit
+    #[arg(long)]
+    pub twap_pool: Option<String>,
+
+    /// TWAP window: seconds or a duration like 30s, 5m, 1h (default 5m)
+    #[arg(long, default_value = "5m", value_parser = parse_duration_secs)]
+    pub twap_window: u64,
+}
+
+/// Parse `300`, `30s`, `5m` or `1h` into seconds.
+fn parse_duration_secs(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: u64 = num
+        .parse()
+        .map_err(|_| format!("invalid duration '{s}' (expected e.g. 300, 30s, 5m, 1h)"))?;
+    let mult = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        _ => return Err(format!("unknown duration unit '{unit}' (use s, m or h)")),
+    };
+    Ok(n * mult)
+}
+
+/// Parse a `--width` such as `150bps` (or a bare `150`) or `2sigma`.
+fn parse_width(s: &str) -> Result<PriceRange, String> {
+    let s = s.trim();
+    if let Some(n) = s.strip_suffix("sigma") {
+        let n: f64 = n
+            .parse()
+            .map_err(|_| format!("invalid width '{s}' (expected e.g. 2sigma)"))?;
+        if !(n > 0.0 && n.is_finite()) {
+            return Err(format!("sigma width must be positive, got {n}"));
+        }
+        return Ok(PriceRange::WidthSigma(n));
+    }
+    let bps: u64 = s
+        .strip_suffix("bps")
+        .unwrap_or(s)
+        .parse()
+        .map_err(|_| format!("invalid width '{s}' (expected e.g. 150bps)"))?;
+    if !(1..20_000).contains(&bps) {
+        return Err(format!("width must be between 1 and 19999 bps, got {bps}"));
+    }
+    Ok(PriceRange::WidthBps(bps))
+}
+
+/// `--cu-price`: a fixed price in microlamports per CU, or `auto`.
+pub(crate) fn parse_cu_price(s: &str) -> Result<CuPrice, String> {
+    if s.eq_ignore_ascii_case("auto") {
+        return Ok(CuPrice::Auto);
+    }
+    s.parse()
+        .map(CuPrice::Fixed)
+        .map_err(|_| format!("invalid cu price '{s}' (expected microlamports or auto)"))
+}
+
+/// Build the parser. Every flag is global so it can follow the subcommand
+/// (`--dex orca open POOL --lower ...`).
+pub fn command() -> clap::Command {
+    <Opts as clap::CommandFactory>::command().mut_args(|arg| arg.global(true))
+}
+
+impl Opts {
+    /// Move the subcommand's argument into the flag it stands for, so the DEX modules,
+    /// `--positions-file` rows and `ray:`/`orca:`/`met:` references all see the same fields.
+    pub fn apply_command(&mut self) -> anyhow::Result<()> {
+        let Some(command) = self.command.take() else {
+            return Ok(());
+        };
+        let action = match command {
+            Command::Action(action) => action,
+            Command::Cost { action } => {
+                if self.quote {
+                    anyhow::bail!("`cost` already simulates without sending; drop --quote");
+                }
+                self.cost = true;
+                match action {
+                    Some(action) => action,
+                    None => return Ok(()),
+                }
+            }
+            Command::Devtools {
+                tool: Devtool::BootstrapDevnet,
+            } => {
+                self.bootstrap_devnet = true;
+                return Ok(());
+            }
+            Command::Lut { target } => {
+                if self.lut.is_some() {
+                    anyhow::bail!("the subcommand already names the target; drop --lut");
+                }
+                self.lut = Some(target);
+                return Ok(());
+            }
+        };
+        let (field, flag, value) = match action {
+            Action::Open { pool } => (&mut self.pool, "--pool", pool),
+            Action::Remove { position } => {
+                (&mut self.remove_position, "--remove-position", position)
+            }
+            Action::Collect { position } => (&mut self.collect_fees, "--collect-fees", position),
+            Action::Swap { pool } => (&mut self.swap_pool, "--swap-pool", pool),
+            Action::Wrap { lamports } => {
+                if self.wrap_sol > 0 {
+                    anyhow::bail!("`wrap` takes the lamports; drop --wrap-sol");
+                }
+                self.wrap_sol = lamports;
+                return Ok(());
+            }
+            Action::Unwrap => {
+                self.unwrap_sol = true;
+                return Ok(());
+            }
+        };
+        if field.is_some() {
+            anyhow::bail!("the subcommand already names the target; drop {}", flag);
+        }
+        *field = Some(value);
+        Ok(())
+    }
+
+    /// The one thing a DEX `run` does. Naming more than one of swap, collect, remove,
+    /// resize, seed and open is an error rather than one of them silently winning.
+    pub fn mode(&self) -> anyhow::Result<Mode> {
+        let candidates = [
+            ("swap", self.swap_pool.clone().map(Mode::Swap)),
+            ("collect", self.collect_fees.clone().map(Mode::Collect)),
+            ("remove", self.remove_position.clone().map(Mode::Remove)),
+            (
+                "--resize-position",
+                self.resize_position.clone().map(Mode::Resize),
+            ),
+            ("--seed-mint", self.seed_mint.clone().map(Mode::Seed)),
+            ("open", self.pool.clone().map(Mode::Open)),
+        ];
+        let mut given = candidates
+            .into_iter()
+            .filter_map(|(name, mode)| mode.map(|mode| (name, mode)));
+        let Some((first, mode)) = given.next() else {
+            return Ok(Mode::Wrap);
+        };
+        if let Some((second, _)) = given.next() {
+            anyhow::bail!(
+                "{} and {} both given; run them as separate commands",
+                first,
+                second
+            );
+        }
+        Ok(mode)
+    }
+
+    /// The open range given as prices (`--price-min/--price-max` or `--width`), if any.
+    pub fn price_range(&self) -> anyhow::Result<Option<PriceRange>> {
+        if let Some(width) = self.width {
+            return Ok(Some(width));
+        }
+        let (Some(min), Some(max)) = (self.price_min, self.price_max) else {
+            return Ok(None);
+        };
+        if !(min > 0.0 && max > min && max.is_finite()) {
+            anyhow::bail!("--price-min/--price-max must satisfy 0 < min < max");
+        }
+        Ok(Some(PriceRange::Absolute(min, max)))
+    }
+
+    /// Fill the fields the `--urgency` preset covers that the command line left unset. The
+    /// preset's slippage also outranks a config profile's, as its fee does.
+    pub fn apply_urgency(&mut self, matches: &ArgMatches) {
+        let Some(urgency) = self.urgency else {
+            return;
+        };
+        let preset = urgency.preset();
+        let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        if !from_cli("cu_price_percentile") {
+            self.cu_price_percentile = preset.cu_price_percentile;
+        }
+        if !from_cli("jito_tip_lamports") {
+            self.jito_tip_lamports = preset.jito_tip_lamports;
+        }
+        if self.slippage_bps.is_none() {
+            self.slippage_bps = Some(preset.slippage_bps);
+        }
+    }
+
+    /// Block engine to send through: --jito, else the default one when the --urgency
+    /// preset asks for Jito protection on mainnet.
+    pub fn effective_jito(&self) -> Option<String> {
+        if self.jito.is_some() {
+            return self.jito.clone();
+        }
+        let protected = self
+            .urgency
+            .is_some_and(|u| u.preset().protection == Protection::Jito);
+        (protected && self.cluster == Cluster::Mainnet).then(|| JITO_BLOCK_ENGINE.to_string())
+    }
+
+    /// Priority fee to use: explicit --cu-price, else the --urgency preset, else 1000.
+    /// With `--cu-price auto` this is the fallback for when no fee estimate is available.
+    pub fn effective_cu_price(&self) -> u64 {
+        match self.cu_price {
+            Some(CuPrice::Fixed(price)) => price,
+            _ => self.urgency.map_or(1000, Urgency::cu_price),
+        }
+    }
+}
+
+/// What to do on `--dex`: an action, or the cost of one.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    #[command(flatten)]
+    Action(Action),
+    /// Simulate ACTION (or the action the flags name) and print the lamports each of its
+    /// transactions needs: network and priority fees, the --jito tip and rent for every
+    /// account it creates. No key is loaded and nothing is sent
+    Cost {
+        #[command(subcommand)]
+        action: Option<Action>,
+    },
+    /// Create an address lookup table holding the accounts a swap on POOL, or collecting
+    /// POSITION, touches on --dex (vaults, tick arrays, ATAs, programs), or extend the one
+    /// --lut-file already keeps for it
+    Lut {
+        #[arg(id = "target", value_name = "POOL|POSITION")]
+        target: String,
+    },
+    /// Helpers for contributors and CI
+    Devtools {
+        #[command(subcommand)]
+        tool: Devtool,
+    },
+}
+
+/// `devtools` helpers.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Devtool {
+    /// Airdrop SOL to the payer, create two test mints, create their Raydium CLMM pool and
+    /// open a seed position (--cluster devnet or localnet)
+    BootstrapDevnet,
+}
+
+/// Position, swap and WSOL actions on `--dex`.
+// The positionals are given the id `target` so they do not clash with the global --pool.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Action {
+    /// Open a position on POOL over --lower/--upper, --price-min/--price-max or --width
+    Open {
+        #[arg(id = "target", value_name = "POOL")]
+        pool: String,
+    },
+    /// Remove liquidity from POSITION (all of it unless --remove-liquidity/--remove-percent);
+    /// --close also burns the position NFT
+    Remove {
+        #[arg(id = "target", value_name = "POSITION")]
+        position: String,
+    },
+    /// Collect fees and rewards of POSITION without removing liquidity
+    Collect {
+        #[arg(id = "target", value_name = "POSITION")]
+        position: String,
+    },
+    /// Swap --swap-amount-in (or --swap-amount-in-ui) on POOL
+    Swap {
+        #[arg(id = "target", value_name = "POOL")]
+        pool: String,
+    },
+    /// Wrap LAMPORTS into WSOL
+    Wrap {
+        #[arg(value_name = "LAMPORTS")]
+        lamports: u64,
+    },
+    /// Unwrap the WSOL ATA back to SOL
+    Unwrap,
+}
+
+/// What a DEX `run` does, resolved from the subcommand or the equivalent flags.
+#[derive(Clone, Debug)]
+pub enum Mode {
+    Swap(String),
+    Collect(String),
+    Remove(String),
+    Resize(String),
+    Seed(String),
+    Open(String),
+    /// Only --wrap-sol and/or --unwrap-sol (nothing to do without them).
+    Wrap,
+}
+
+/// An open range in token1 per token0 (UI units), before conversion to ticks or bins.
+#[derive(Copy, Clone, Debug)]
+pub enum PriceRange {
+    Absolute(f64, f64),
+    /// Total width in bps, split evenly around the current price.
+    WidthBps(u64),
+    /// This many standard deviations of the observed log price on each side of the
+    /// current price.
+    WidthSigma(f64),
+}
+
+impl PriceRange {
+    /// `(min, max)` prices, given the pool's current price and, for sigma widths, the
+    /// standard deviation of its log price (`None` where the pool keeps no history).
+    pub fn resolve(self, current: f64, sigma: Option<f64>) -> anyhow::Result<(f64, f64)> {
+        Ok(match self {
+            PriceRange::Absolute(min, max) => (min, max),
+            PriceRange::WidthBps(bps) => {
+                let half = bps as f64 / 20_000.0;
+                (current * (1.0 - half), current * (1.0 + half))
+            }
+            PriceRange::WidthSigma(n) => {
+                let Some(sigma) = sigma else {
+                    anyhow::bail!(
+                        "sigma widths need the pool's observed volatility, which is only read from Raydium's observation ring; use bps"
+                    );
+                };
+                if sigma <= 0.0 {
+                    anyhow::bail!("the pool's observed price has not moved; use a bps width");
+                }
+                let factor = (n * sigma).exp();
+                (current / factor, current * factor)
+            }
+        })
+    }
+}
+
+/// `--cu-price` setting.
+#[derive(Copy, Clone, Debug)]
+pub enum CuPrice {
+    Fixed(u64),
+    /// Estimated per transaction from `getRecentPrioritizationFees`.
+    Auto,
 }
 
 /// Pick a DEX implementation.
-#[derive(Copy, Clone, Debug, ValueEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
 pub enum Dex {
     Raydium,
     Orca,
     Meteora,
 }
+
+/// Network the transactions target; selects program ids and the default RPC.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Cluster {
+    Mainnet,
+    Devnet,
+    /// A local test validator with the programs cloned at their mainnet addresses.
+    Localnet,
+}
+
+impl Cluster {
+    /// Public RPC used when neither --rpc, RPC_URL nor a profile gives one.
+    pub fn default_rpc_url(self) -> &'static str {
+        match self {
+            Cluster::Mainnet => "https://api.mainnet-beta.solana.com",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+            Cluster::Localnet => "http://127.0.0.1:8899",
+        }
+    }
+}
+
+/// How much `--verbosity` logs.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Verbosity {
+    Quiet,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Verbosity {
+    /// Most detailed level that is logged.
+    pub fn level(self) -> tracing::Level {
+        match self {
+            Verbosity::Quiet => tracing::Level::WARN,
+            Verbosity::Info => tracing::Level::INFO,
+            Verbosity::Debug => tracing::Level::DEBUG,
+            Verbosity::Trace => tracing::Level::TRACE,
+        }
+    }
+}
+
+/// Named presets for how hard to push a transaction through.
+#[derive(Copy, Clone, Debug, ValueEnum)]
+pub enum Urgency {
+    Chill,
+    Normal,
+    Urgent,
+    Degen,
+}
+
+/// Block engine an [`Urgency`] preset with [`Protection::Jito`] sends to.
+pub const JITO_BLOCK_ENGINE: &str = "https://mainnet.block-engine.jito.wtf";
+
+/// How an [`Urgency`] preset keeps its transactions from being front-run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Protection {
+    /// Sent through the RPC.
+    Public,
+    /// Sent as Jito bundles to [`JITO_BLOCK_ENGINE`] when --jito is not given; bundles
+    /// never reach the public mempool. Mainnet only: other clusters use the RPC.
+    Jito,
+}
+
+/// What an [`Urgency`] sets in place of the flags that are not given.
+#[derive(Copy, Clone, Debug)]
+pub struct UrgencyPreset {
+    /// Microlamports per CU, when --cu-price is not given or `auto` has no estimate.
+    pub cu_price: u64,
+    pub cu_price_percentile: u8,
+    pub slippage_bps: u64,
+    pub jito_tip_lamports: u64,
+    pub protection: Protection,
+}
+
+impl Urgency {
+    pub fn preset(self) -> UrgencyPreset {
+        let (cu_price, cu_price_percentile, slippage_bps, jito_tip_lamports, protection) =
+            match self {
+                Urgency::Chill => (100, 25, 30, 1_000, Protection::Public),
+                Urgency::Normal => (1_000, 75, 50, 10_000, Protection::Public),
+                Urgency::Urgent => (50_000, 90, 100, 100_000, Protection::Jito),
+                Urgency::Degen => (500_000, 99, 300, 1_000_000, Protection::Jito),
+            };
+        UrgencyPreset {
+            cu_price,
+            cu_price_percentile,
+            slippage_bps,
+            jito_tip_lamports,
+            protection,
+        }
+    }
+
+    /// Microlamports per CU for this preset.
+    pub fn cu_price(self) -> u64 {
+        self.preset().cu_price
+    }
+}
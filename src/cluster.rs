@@ -0,0 +1,15 @@
+use std::sync::OnceLock;
+
+use crate::cli::Cluster;
+
+static CLUSTER: OnceLock<Cluster> = OnceLock::new();
+
+/// `--cluster`: the network whose program ids the DEX modules use. Only the first call
+/// counts, so set it before building any instruction.
+pub fn set(cluster: Cluster) {
+    let _ = CLUSTER.set(cluster);
+}
+
+pub fn current() -> Cluster {
+    CLUSTER.get().copied().unwrap_or(Cluster::Mainnet)
+}
@@ -0,0 +1,155 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result, bail};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use tracing::{instrument, warn};
+
+use crate::cli::{Dex, Opts};
+use crate::quote::{Grid, convert_range, index_to_price};
+use crate::{amounts, meteora, orca, position_ref, raydium, rpc};
+
+/// Print the spot and effective price of `--compare-amount` MINT_IN on each venue.
+#[instrument(name = "compare", skip_all)]
+pub async fn run(opts: &Opts, mints: &[String]) -> Result<()> {
+    let [mint_in, mint_out] = mints else {
+        bail!("--compare takes exactly two mints: MINT_IN MINT_OUT");
+    };
+    let mint_in = Pubkey::from_str(mint_in).context("invalid --compare MINT_IN")?;
+    let mint_out = Pubkey::from_str(mint_out).context("invalid --compare MINT_OUT")?;
+    if opts.compare_amount == 0 {
+        bail!("--compare-amount must be > 0");
+    }
+
+    let rpc = rpc::client(opts);
+
+    let decimals_in = amounts::fetch_mint_decimals(&rpc, &mint_in)
+        .await
+        .context("fetch MINT_IN decimals")?;
+    let decimals_out = amounts::fetch_mint_decimals(&rpc, &mint_out)
+        .await
+        .context("fetch MINT_OUT decimals")?;
+    // Quotes are in base units; scale to MINT_OUT per MINT_IN in UI units.
+    let decimals_adj = 10f64.powi(decimals_in as i32 - decimals_out as i32);
+
+    // Quote the venues concurrently.
+    let (raydium, orca, meteora) = tokio::join!(
+        raydium::compare_quote(&rpc, &mint_in, &mint_out, opts.compare_amount),
+        orca::compare_quote(&rpc, &mint_in, &mint_out, opts.compare_amount),
+        meteora::compare_quote(&rpc, &mint_in, &mint_out, opts.compare_amount),
+    );
+    println!(
+        "Prices for {} {} -> {} (MINT_OUT per MINT_IN):",
+        amounts::format_amount(&rpc, &mint_in, opts.compare_amount).await,
+        mint_in,
+        mint_out
+    );
+    let mut effective: Vec<(&str, f64)> = Vec::new();
+    for (name, quote) in [("raydium", raydium), ("orca", orca), ("meteora", meteora)] {
+        match quote {
+            Ok(Some((pool, quote))) => {
+                let spot = quote.spot_out_per_in * decimals_adj;
+                let price = quote.amount_out as f64 / quote.amount_in.max(1) as f64 * decimals_adj;
+                println!(
+                    "  {:<8} pool={} spot={:.10} effective={:.10} est_out={} fee={} price_impact={}bps",
+                    name,
+                    pool,
+                    spot,
+                    price,
+                    amounts::format_amount(&rpc, &mint_out, quote.amount_out).await,
+                    amounts::format_amount(&rpc, &mint_in, quote.fee).await,
+                    quote.price_impact_bps()
+                );
+                effective.push((name, price));
+            }
+            Ok(None) => println!("  {:<8} no quotable pool for this pair", name),
+            Err(e) => warn!("{} quote failed: {:#}", name, e),
+        }
+    }
+
+    let best = effective.iter().max_by(|a, b| a.1.total_cmp(&b.1));
+    let worst = effective.iter().min_by(|a, b| a.1.total_cmp(&b.1));
+    if let (Some(best), Some(worst)) = (best, worst)
+        && effective.len() > 1
+    {
+        let spread_bps = (best.1 / worst.1 - 1.0) * 10_000.0;
+        println!(
+            "Spread: {} beats {} by {:.1}bps",
+            best.0, worst.0, spread_bps
+        );
+    }
+    Ok(())
+}
+
+/// `--equivalent-range`: the range on another venue's pool of the same pair that covers
+/// the prices of `--lower`/`--upper` on `--pool`, so the two positions stay equivalent
+/// despite different tick spacings or bin steps.
+#[instrument(name = "equivalent_range", skip_all)]
+pub async fn equivalent_range(opts: &Opts, target: &str) -> Result<()> {
+    let (Some(pool), Some(lower), Some(upper)) = (&opts.pool, opts.lower, opts.upper) else {
+        bail!("--equivalent-range needs --pool, --lower and --upper (on --dex)");
+    };
+    let pool = Pubkey::from_str(pool).context("invalid --pool")?;
+    let (Some(target_dex), target_pool) = position_ref::split(target)? else {
+        bail!("prefix the --equivalent-range pool with its venue (ray:, orca: or met:)");
+    };
+    let target_pool = Pubkey::from_str(target_pool).context("invalid --equivalent-range pool")?;
+
+    let rpc = rpc::client(opts);
+
+    let (mint0, mint1, from) = pool_grid(&rpc, opts.dex, &pool).await?;
+    let (target0, target1, to) = pool_grid(&rpc, target_dex, &target_pool).await?;
+    let inverted = match (target0, target1) {
+        (a, b) if (a, b) == (mint0, mint1) => false,
+        (a, b) if (a, b) == (mint1, mint0) => true,
+        _ => bail!("{} and {} trade different pairs", pool, target_pool),
+    };
+    let (l, u) = convert_range((lower, upper), from, to, inverted);
+
+    let decimals0 = amounts::fetch_mint_decimals(&rpc, &mint0).await?;
+    let decimals1 = amounts::fetch_mint_decimals(&rpc, &mint1).await?;
+    let price = |index: i32, grid: Grid, inverted: bool| {
+        let (d0, d1) = if inverted {
+            (decimals1, decimals0)
+        } else {
+            (decimals0, decimals1)
+        };
+        let p = index_to_price(index, d0, d1, grid.base);
+        if inverted { 1.0 / p } else { p }
+    };
+    // Inverted target ends swap places once expressed in --pool's orientation.
+    let (target_lo, target_hi) = if inverted { (u, l) } else { (l, u) };
+    println!("Prices in {} per {}:", mint1, mint0);
+    println!(
+        "  {:<8} pool={} range=[{}, {}] price {:.10}..{:.10}",
+        format!("{:?}", opts.dex).to_lowercase(),
+        pool,
+        lower,
+        upper,
+        price(lower, from, false),
+        price(upper, from, false)
+    );
+    println!(
+        "  {:<8} pool={} range=[{}, {}] price {:.10}..{:.10}{}",
+        format!("{:?}", target_dex).to_lowercase(),
+        target_pool,
+        l,
+        u,
+        price(target_lo, to, inverted),
+        price(target_hi, to, inverted),
+        if inverted {
+            " (pool lists the mints the other way round)"
+        } else {
+            ""
+        }
+    );
+    Ok(())
+}
+
+async fn pool_grid(rpc: &RpcClient, dex: Dex, pool: &Pubkey) -> Result<(Pubkey, Pubkey, Grid)> {
+    match dex {
+        Dex::Raydium => raydium::pool_grid(rpc, pool).await,
+        Dex::Orca => orca::pool_grid(rpc, pool).await,
+        Dex::Meteora => meteora::pool_grid(rpc, pool).await,
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::{Context, Result, bail};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use tracing::{debug, warn};
+
+use crate::cli::{self, Opts};
+use crate::rpc;
+use crate::signer;
+
+/// Apply a profile from `CONFIG_FILE` or `~/.config/solana-liq-arb/config.toml`:
+/// `--profile`, else the file's `default_profile`. Profile values only fill in what
+/// neither the command line nor the environment (`RPC_URLS`, `RPC_URL`) already set. A
+/// profile's `urgency` preset fills what the profile's own keys leave unset, below a
+/// command-line `--urgency`.
+///
+/// ```toml
+/// default_profile = "mainnet"
+///
+/// [profiles.mainnet]
+/// cluster = "mainnet"
+/// rpc = "https://my-rpc.example.com"
+/// cu_price = "auto"
+/// cu_limit = 600000
+/// slippage_bps = 50
+/// urgency = "urgent"
+/// verbosity = "quiet"
+/// log_file = "/var/log/liq-arb.log"
+/// ```
+pub fn apply(opts: &mut Opts, matches: &ArgMatches) -> Result<()> {
+    let path = std::env::var("CONFIG_FILE")
+        .unwrap_or_else(|_| signer::expand_home("~/.config/solana-liq-arb/config.toml"));
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && opts.profile.is_none() => {
+            return Ok(());
+        }
+        Err(e) => return Err(e).with_context(|| format!("read config file {}", path)),
+    };
+    let config: toml::value::Table =
+        toml::from_str(&text).with_context(|| format!("parse config file {}", path))?;
+    let name = match (&opts.profile, config.get("default_profile")) {
+        (Some(name), _) => name.clone(),
+        (None, Some(toml::Value::String(name))) => name.clone(),
+        (None, Some(_)) => bail!("default_profile in {} must be a string", path),
+        (None, None) => return Ok(()),
+    };
+    let Some(profile) = config
+        .get("profiles")
+        .and_then(|p| p.get(&name))
+        .and_then(|p| p.as_table())
+    else {
+        bail!("no [profiles.{}] in {}", name, path);
+    };
+    debug!("config profile {} from {}", name, path);
+
+    let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let mut urgency = None;
+    for (key, value) in profile {
+        let bad = || format!("profile {}: bad value for {}", name, key);
+        match key.as_str() {
+            "rpc" => {
+                if opts.rpc.is_empty() && !rpc::env_configured() {
+                    opts.rpc = rpc::split_urls(value.as_str().with_context(bad)?);
+                }
+            }
+            "cu_price" => {
+                // A command-line --urgency also outranks a profile price.
+                if opts.cu_price.is_none() && opts.urgency.is_none() {
+                    let price = match value {
+                        toml::Value::Integer(n) => n.to_string(),
+                        v => v.as_str().with_context(bad)?.to_string(),
+                    };
+                    opts.cu_price = Some(cli::parse_cu_price(&price).map_err(anyhow::Error::msg)?);
+                }
+            }
+            "cluster" => {
+                if !from_cli("cluster") {
+                    let cluster = value.as_str().with_context(bad)?;
+                    opts.cluster = cli::Cluster::from_str(cluster, true)
+                        .map_err(|e| anyhow::anyhow!("profile {}: cluster: {}", name, e))?;
+                }
+            }
+            "cu_limit" => {
+                if !from_cli("cu_limit") {
+                    opts.cu_limit = value
+                        .as_integer()
+                        .and_then(|n| u32::try_from(n).ok())
+                        .with_context(bad)?;
+                }
+            }
+            "slippage_bps" => {
+                if opts.slippage_bps.is_none() {
+                    opts.slippage_bps = Some(
+                        value
+                            .as_integer()
+                            .and_then(|n| u64::try_from(n).ok())
+                            .filter(|n| *n <= 10_000)
+                            .with_context(bad)?,
+                    );
+                }
+            }
+            "verbosity" => {
+                if !from_cli("verbosity") {
+                    let verbosity = value.as_str().with_context(bad)?;
+                    opts.verbosity = cli::Verbosity::from_str(verbosity, true)
+                        .map_err(|e| anyhow::anyhow!("profile {}: verbosity: {}", name, e))?;
+                }
+            }
+            "log_file" => {
+                if opts.log_file.is_none() {
+                    opts.log_file = Some(value.as_str().with_context(bad)?.to_string());
+                }
+            }
+            "urgency" => {
+                if opts.urgency.is_none() {
+                    let preset = value.as_str().with_context(bad)?;
+                    urgency = Some(
+                        cli::Urgency::from_str(preset, true)
+                            .map_err(|e| anyhow::anyhow!("profile {}: urgency: {}", name, e))?,
+                    );
+                }
+            }
+            other => warn!("profile {}: ignoring unknown key {}", name, other),
+        }
+    }
+    // Applied last, so the profile's own slippage_bps and cu_price win over its preset.
+    if urgency.is_some() {
+        opts.urgency = urgency;
+        opts.apply_urgency(matches);
+    }
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use anyhow::{Context, Result, bail};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use tracing::{info, instrument};
+
+use crate::cli::{Cluster, Opts, PriceRange};
+use crate::tx::{Flow, Report, TxConfig};
+use crate::{raydium, rpc, signer};
+
+/// The payer is topped up to this balance before anything is created.
+const AIRDROP_TARGET_LAMPORTS: u64 = 2 * LAMPORTS_PER_SOL;
+
+/// Decimals of both test mints, so `--amount0`/`--amount1` read the same whichever
+/// mint sorts first in the pool.
+const TEST_DECIMALS: u8 = 6;
+
+/// Whole tokens of each test mint minted to the payer.
+const TEST_SUPPLY: u64 = 1_000_000;
+
+/// Whole tokens of each mint the seed position opens with, unless --amount0/--amount1.
+const SEED_AMOUNT: u64 = 1_000;
+
+/// Range of the seed position, unless one is given.
+const SEED_WIDTH_BPS: u64 = 2_000;
+
+/// `devtools bootstrap-devnet`: top the payer up by airdrop, create two test mints and
+/// mint a supply of each to it, then create their Raydium CLMM pool and open a first
+/// position through the `--seed-mint` flow.
+#[instrument(name = "bootstrap", skip_all)]
+pub async fn bootstrap_devnet(opts: &Opts, config: &TxConfig) -> Result<Report> {
+    if opts.cluster == Cluster::Mainnet {
+        bail!("bootstrap-devnet airdrops and mints test tokens; pass --cluster devnet or localnet");
+    }
+    if opts.quote || opts.cost || opts.export_tx.is_some() {
+        bail!(
+            "bootstrap-devnet sends each step before building the next; drop --quote/--export-tx"
+        );
+    }
+    let rpc = rpc::client(opts);
+
+    let wallet = signer::load_payer(opts).await?;
+    let payer_pk = wallet.pubkey();
+
+    let balance = rpc
+        .get_balance(&payer_pk)
+        .await
+        .context("fetch payer balance")?;
+    if balance < AIRDROP_TARGET_LAMPORTS {
+        let lamports = AIRDROP_TARGET_LAMPORTS - balance;
+        let sig = rpc
+            .request_airdrop(&payer_pk, lamports)
+            .await
+            .context("request airdrop (faucets are rate limited; fund the payer by hand)")?;
+        rpc.poll_for_signature(&sig)
+            .await
+            .context("wait for the airdrop")?;
+        println!(
+            "✅ Airdropped {} lamports to {}. Tx: {}",
+            lamports, payer_pk, sig
+        );
+    } else {
+        info!("payer already holds {} lamports; no airdrop", balance);
+    }
+
+    let mints = [Keypair::new(), Keypair::new()];
+    let rent = rpc
+        .get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)
+        .await
+        .context("fetch mint rent")?;
+    let supply = TEST_SUPPLY * 10u64.pow(TEST_DECIMALS as u32);
+    let mut ixs = vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
+        ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
+    ];
+    for mint in &mints {
+        let mint = mint.pubkey();
+        let ata = get_associated_token_address(&payer_pk, &mint);
+        ixs.extend([
+            system_instruction::create_account(
+                &payer_pk,
+                &mint,
+                rent,
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::ID,
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::ID,
+                &mint,
+                &payer_pk,
+                None,
+                TEST_DECIMALS,
+            )?,
+            create_associated_token_account_idempotent(&payer_pk, &payer_pk, &mint, &spl_token::ID),
+            spl_token::instruction::mint_to(&spl_token::ID, &mint, &ata, &payer_pk, &[], supply)?,
+        ]);
+    }
+    let mut flow = Flow::new(&rpc, config, wallet.as_ref());
+    let sent = flow.send(ixs, &[&mints[0], &mints[1]]).await?;
+    let mut report = flow.finish().await?;
+    let (mint_a, mint_b) = (mints[0].pubkey(), mints[1].pubkey());
+    if let Some(sig) = sent.signature() {
+        println!(
+            "✅ Created test mints {} and {} ({} decimals, {} of each minted to {}). Tx: {}",
+            mint_a, mint_b, TEST_DECIMALS, TEST_SUPPLY, payer_pk, sig
+        );
+    }
+
+    let mut seed = opts.clone();
+    seed.seed_mint = Some(mint_a.to_string());
+    seed.seed_quote_mint = Some(mint_b.to_string());
+    seed.seed_price = Some(1.0);
+    if seed.amm_config.is_none() {
+        let (amm_config, _) = Pubkey::find_program_address(
+            &[
+                raydium_amm_v3::states::AMM_CONFIG_SEED.as_bytes(),
+                &0u16.to_be_bytes(),
+            ],
+            &raydium::clmm_program_id()?,
+        );
+        seed.amm_config = Some(amm_config.to_string());
+    }
+    if seed.price_range()?.is_none() && (seed.lower.is_none() || seed.upper.is_none()) {
+        seed.width = Some(PriceRange::WidthBps(SEED_WIDTH_BPS));
+    }
+    if seed.amount0 == 0 && seed.amount1 == 0 {
+        let amount = SEED_AMOUNT * 10u64.pow(TEST_DECIMALS as u32);
+        (seed.amount0, seed.amount1) = (amount, amount);
+    }
+    let seeded = raydium::run(seed, config).await?;
+    report.signatures.extend(seeded.signatures);
+    println!(
+        "Devnet bootstrap done: test mints {} and {}, with the pool and position above",
+        mint_a, mint_b
+    );
+    Ok(report)
+}
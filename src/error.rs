@@ -0,0 +1,207 @@
+use std::fmt;
+
+use solana_client::client_error::ClientError;
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    signature::Signature,
+    signer::SignerError,
+    transaction::TransactionError,
+};
+
+use crate::hints;
+
+/// Program errors (by hint-table name) that mean a min-out / max-in bound was hit.
+const SLIPPAGE_ERRORS: &[&str] = &[
+    "PriceSlippageCheck",
+    "TooLittleOutputReceived",
+    "TooMuchInputPaid",
+    "TokenMaxExceeded",
+    "TokenMinSubceeded",
+    "AmountOutBelowMinimum",
+    "AmountInAboveMaximum",
+    "ExceededAmountSlippageTolerance",
+    "ExceededBinSlippageTolerance",
+    "InsufficientOutAmount",
+];
+
+/// Failure modes of the shared transaction, decoding and quoting helpers.
+/// These surface through `anyhow::Error` and can be recovered with `downcast_ref`.
+#[derive(Debug)]
+pub enum Error {
+    Rpc(Box<ClientError>),
+    Signing(SignerError),
+    Io(std::io::Error),
+    Decode {
+        what: &'static str,
+        reason: String,
+    },
+    SlippageExceeded {
+        instruction: u8,
+        program_id: Pubkey,
+        name: &'static str,
+    },
+    PriceImpactExceeded {
+        impact_bps: u64,
+        max_bps: u64,
+    },
+    InsufficientFunds {
+        instruction: u8,
+        program_id: Pubkey,
+    },
+    SimulationFailed {
+        err: TransactionError,
+        code: Option<u32>,
+        logs: Vec<String>,
+    },
+    /// The simulation pays `owner` (through `account`), a wallet that is neither the payer
+    /// nor in `--allow-destination`; nothing was sent.
+    DestinationNotAllowed {
+        account: Pubkey,
+        owner: Pubkey,
+    },
+    /// `--jito`: the bundle's transaction was not confirmed before the timeout.
+    BundleNotLanded {
+        bundle_id: String,
+        signature: Signature,
+    },
+    /// Every signed copy expired unconfirmed (`--max-retries` re-signs exhausted).
+    NotLanded {
+        signature: Signature,
+        attempts: u32,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Classify a failed transaction by the program error it raised.
+    pub fn from_transaction_error(
+        err: TransactionError,
+        ixs: &[Instruction],
+        logs: Vec<String>,
+    ) -> Self {
+        let TransactionError::InstructionError(idx, InstructionError::Custom(code)) = err else {
+            return Error::SimulationFailed {
+                err,
+                code: None,
+                logs,
+            };
+        };
+        let program_id = hints::raising_program(idx, ixs, &logs);
+        match program_id.and_then(|p| Some((p, hints::hint_for(&p, code)?.0))) {
+            Some((program_id, name)) if SLIPPAGE_ERRORS.contains(&name) => {
+                Error::SlippageExceeded {
+                    instruction: idx,
+                    program_id,
+                    name,
+                }
+            }
+            Some((program_id, "InsufficientFunds")) => Error::InsufficientFunds {
+                instruction: idx,
+                program_id,
+            },
+            _ => Error::SimulationFailed {
+                err,
+                code: Some(code),
+                logs,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Rpc(e) => write!(f, "rpc error: {}", e),
+            Error::Signing(e) => write!(f, "signing failed: {}", e),
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Decode { what, reason } => write!(f, "decode {}: {}", what, reason),
+            Error::SlippageExceeded {
+                instruction,
+                program_id,
+                name,
+            } => write!(
+                f,
+                "slippage exceeded: instruction #{} ({}) failed with {}",
+                instruction, program_id, name
+            ),
+            Error::PriceImpactExceeded {
+                impact_bps,
+                max_bps,
+            } => write!(
+                f,
+                "price impact {} bps exceeds --max-price-impact-bps {}; reduce --swap-amount-in or raise the limit",
+                impact_bps, max_bps
+            ),
+            Error::InsufficientFunds {
+                instruction,
+                program_id,
+            } => write!(
+                f,
+                "insufficient funds: instruction #{} ({}) could not debit the wallet",
+                instruction, program_id
+            ),
+            Error::SimulationFailed { err, code, logs } => {
+                write!(f, "simulation failed: {:?}", err)?;
+                if let Some(code) = code {
+                    write!(f, " (custom error 0x{:x})", code)?;
+                }
+                if let Some(last) = logs.last() {
+                    write!(f, "; last log: {}", last)?;
+                }
+                Ok(())
+            }
+            Error::DestinationNotAllowed { account, owner } => write!(
+                f,
+                "refusing to send: the transaction pays {} (account {}), which is not this wallet; allow it with --allow-destination or ALLOWED_DESTINATIONS if intended",
+                owner, account
+            ),
+            Error::BundleNotLanded {
+                bundle_id,
+                signature,
+            } => write!(
+                f,
+                "jito bundle {} not confirmed in time (tx {}); it is dropped once its blockhash expires",
+                bundle_id, signature
+            ),
+            Error::NotLanded {
+                signature,
+                attempts,
+            } => write!(
+                f,
+                "transaction not confirmed: {} signed copies expired (last {})",
+                attempts, signature
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Rpc(e) => Some(e.as_ref()),
+            Error::Signing(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ClientError> for Error {
+    fn from(e: ClientError) -> Self {
+        Error::Rpc(Box::new(e))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<SignerError> for Error {
+    fn from(e: SignerError) -> Self {
+        Error::Signing(e)
+    }
+}
@@ -0,0 +1,420 @@
+use std::str::FromStr;
+
+use solana_sdk::{
+    instruction::{Instruction, InstructionError},
+    pubkey::Pubkey,
+    transaction::TransactionError,
+};
+
+const RAYDIUM_CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+const RAYDIUM_CLMM_PROGRAM_ID_DEVNET: &str = "devi51mZmdwUJGU9hjN27vEz64Gps7uUefqxg27EAtH";
+const ORCA_WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+const METEORA_DLMM_PROGRAM_ID: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+
+/// (code, error name, remediation hint)
+type HintEntry = (u32, &'static str, &'static str);
+
+const RAYDIUM_HINTS: &[HintEntry] = &[
+    (
+        0x1774,
+        "ClosePositionErr",
+        "position still holds liquidity, fees or rewards; remove liquidity first or drop --close",
+    ),
+    (
+        0x1776,
+        "InvalidTickIndex",
+        "tick is outside the supported range; pick --lower/--upper within [-443636, 443636]",
+    ),
+    (
+        0x1777,
+        "TickInvalidOrder",
+        "--lower must be strictly below --upper",
+    ),
+    (
+        0x177A,
+        "TickAndSpacingNotMatch",
+        "--lower/--upper must be multiples of the pool's tick_spacing",
+    ),
+    (
+        0x177B,
+        "InvalidTickArray",
+        "a tick array account does not match the range; check the pool id and ticks",
+    ),
+    (
+        0x177F,
+        "LiquiditySubValueErr",
+        "trying to remove more liquidity than the position holds; re-read the position and retry",
+    ),
+    (
+        0x1781,
+        "InvalidLiquidity",
+        "position has no liquidity for this operation; it may already be emptied",
+    ),
+    (
+        0x1785,
+        "PriceSlippageCheck",
+        "price moved past the allowed amounts; raise --amount0/--amount1 or lower --min-out0/--min-out1",
+    ),
+    (
+        0x1786,
+        "TooLittleOutputReceived",
+        "swap output fell below --swap-min-out; lower it or retry when the price recovers",
+    ),
+    (
+        0x1787,
+        "TooMuchInputPaid",
+        "exact-out swap needed more input than allowed; raise the input threshold",
+    ),
+    (
+        0x1788,
+        "ZeroAmountSpecified",
+        "--swap-amount-in must be > 0",
+    ),
+    (
+        0x178B,
+        "NotEnoughTickArrayAccount",
+        "swap crossed past the provided tick arrays; use a smaller --swap-amount-in or set --swap-sqrt-price-limit",
+    ),
+    (
+        0x178C,
+        "InvalidFirstTickArrayAccount",
+        "the pool price moved into another tick array since the tx was built; rebuild and resend",
+    ),
+    (
+        0x1797,
+        "NotSupportMint",
+        "one of the mints uses a Token-2022 extension the pool does not support",
+    ),
+    (
+        0x1798,
+        "MissingTickArrayBitmapExtensionAccount",
+        "range is far from the current price and needs the tick array bitmap extension account",
+    ),
+    (
+        0x1799,
+        "InsufficientLiquidityForDirection",
+        "pool has no liquidity in the swap direction; try the other pool or a smaller amount",
+    ),
+];
+
+const ORCA_HINTS: &[HintEntry] = &[
+    (
+        0x1771,
+        "InvalidStartTick",
+        "a tick array does not cover the requested tick; check --lower/--upper and the pool",
+    ),
+    (
+        0x1774,
+        "InvalidTickSpacing",
+        "--lower/--upper must be multiples of the whirlpool's tick_spacing",
+    ),
+    (
+        0x1775,
+        "ClosePositionNotEmpty",
+        "position still holds liquidity, fees or rewards; collect them before closing",
+    ),
+    (
+        0x1779,
+        "TickNotFound",
+        "tick array for the range is not initialized; initialize it or choose a different range",
+    ),
+    (
+        0x177A,
+        "InvalidTickIndex",
+        "tick is outside the supported range or not aligned to tick_spacing",
+    ),
+    (
+        0x177C,
+        "LiquidityZero",
+        "computed liquidity is zero; increase --amount0/--amount1 or move the range closer to price",
+    ),
+    (
+        0x1781,
+        "TokenMaxExceeded",
+        "deposit needs more tokens than allowed; raise --amount0/--amount1 or retry after price moves",
+    ),
+    (
+        0x1782,
+        "TokenMinSubceeded",
+        "withdrawal returned less than the minimum; retry or lower the minimum amounts",
+    ),
+    (
+        0x1783,
+        "MissingOrInvalidDelegate",
+        "signer is neither the position owner nor an approved delegate of the position NFT",
+    ),
+    (
+        0x1784,
+        "InvalidPositionTokenAmount",
+        "signer's token account does not hold the position NFT",
+    ),
+    (
+        0x1787,
+        "InvalidTickArraySequence",
+        "swap tick arrays are out of order or the price moved; rebuild and resend",
+    ),
+    (0x1793, "ZeroTradableAmount", "--swap-amount-in must be > 0"),
+    (
+        0x1794,
+        "AmountOutBelowMinimum",
+        "swap output fell below --swap-min-out; lower it or retry when the price recovers",
+    ),
+    (
+        0x1795,
+        "AmountInAboveMaximum",
+        "exact-out swap needed more input than allowed; raise the input threshold",
+    ),
+    (
+        0x1796,
+        "TickArraySequenceInvalidIndex",
+        "swap ran past the three tick arrays; use a smaller --swap-amount-in or set --swap-sqrt-price-limit",
+    ),
+    (
+        0x179F,
+        "UnsupportedTokenMint",
+        "one of the mints uses a Token-2022 extension the whirlpool does not support",
+    ),
+];
+
+const METEORA_HINTS: &[HintEntry] = &[
+    (
+        0x1771,
+        "InvalidBinId",
+        "bin id is outside the pair's range; check --lower/--upper",
+    ),
+    (
+        0x1773,
+        "ExceededAmountSlippageTolerance",
+        "amounts moved past the allowed tolerance; retry or adjust --amount0/--amount1",
+    ),
+    (
+        0x1774,
+        "ExceededBinSlippageTolerance",
+        "active bin moved too far since the tx was built; rebuild and resend",
+    ),
+    (
+        0x1777,
+        "ZeroLiquidity",
+        "nothing to deposit or remove in the selected bins",
+    ),
+    (
+        0x1779,
+        "BinArrayNotFound",
+        "a bin array for the range is not initialized; initialize it or choose another range",
+    ),
+    (
+        0x177C,
+        "PairInsufficientLiquidity",
+        "pair has no liquidity in the swap direction; try a smaller --swap-amount-in",
+    ),
+    (
+        0x178C,
+        "NonContinuousBinArrays",
+        "bin arrays passed to the instruction are not adjacent; the range may span more than two arrays",
+    ),
+    (
+        0x178E,
+        "NonEmptyPosition",
+        "position still holds liquidity or unclaimed fees; remove and claim before closing",
+    ),
+    (
+        0x1794,
+        "BitmapExtensionAccountIsNotProvided",
+        "the bins are outside the default bitmap; the bin array bitmap extension account is required",
+    ),
+    (
+        0x1797,
+        "InsufficientOutAmount",
+        "swap output fell below --swap-min-out; lower it or retry when the price recovers",
+    ),
+    (
+        0x1798,
+        "InvalidPositionWidth",
+        "a DLMM position can span at most 70 bins; narrow --lower/--upper",
+    ),
+    (
+        0x179A,
+        "PoolDisabled",
+        "the pair is disabled for this operation",
+    ),
+];
+
+/// Framework errors raised by an Anchor program before the handler runs; the DEX programs
+/// above are all Anchor programs.
+const ANCHOR_HINTS: &[HintEntry] = &[
+    (
+        2000,
+        "ConstraintMut",
+        "an account expected to be writable was passed read-only",
+    ),
+    (
+        2003,
+        "ConstraintRaw",
+        "an account constraint failed; usually a wrong pool/position pairing or stale account",
+    ),
+    (
+        2006,
+        "ConstraintSeeds",
+        "a PDA does not match its seeds; check the pool id, ticks/bins and program id",
+    ),
+    (
+        2012,
+        "ConstraintAddress",
+        "an account address does not match what the program expects",
+    ),
+    (
+        3001,
+        "AccountDiscriminatorNotFound",
+        "an account is empty or not yet initialized",
+    ),
+    (
+        3002,
+        "AccountDiscriminatorMismatch",
+        "an account has the wrong type; check that the pool/position ids belong to this DEX",
+    ),
+    (
+        3007,
+        "AccountOwnedByWrongProgram",
+        "an account is owned by another program; check --dex and the ids you passed",
+    ),
+    (
+        3012,
+        "AccountNotInitialized",
+        "a required account (tick/bin array, ATA, position) does not exist yet",
+    ),
+];
+
+/// SPL Token / Token-2022 errors. Raised inside a DEX instruction's CPI, they are reported
+/// at the DEX instruction's index; [`failing_program`] finds the token program in the logs.
+const TOKEN_HINTS: &[HintEntry] = &[
+    (
+        1,
+        "InsufficientFunds",
+        "wallet token balance is too low; wrap more SOL or lower the amounts",
+    ),
+    (
+        4,
+        "OwnerMismatch",
+        "token account owner does not match the signer",
+    ),
+    (
+        17,
+        "AccountFrozen",
+        "token account is frozen by the mint's freeze authority",
+    ),
+];
+
+/// Look up a remediation hint for a custom program error code. Only the tables of the
+/// program that raised the code apply: the same small numbers mean other things elsewhere.
+pub fn hint_for(program_id: &Pubkey, code: u32) -> Option<(&'static str, &'static str)> {
+    let id = program_id.to_string();
+    let tables: &[&[HintEntry]] = match id.as_str() {
+        RAYDIUM_CLMM_PROGRAM_ID | RAYDIUM_CLMM_PROGRAM_ID_DEVNET => &[RAYDIUM_HINTS, ANCHOR_HINTS],
+        ORCA_WHIRLPOOL_PROGRAM_ID => &[ORCA_HINTS, ANCHOR_HINTS],
+        METEORA_DLMM_PROGRAM_ID => &[METEORA_HINTS, ANCHOR_HINTS],
+        _ if *program_id == spl_token::ID || *program_id == spl_token_2022::ID => &[TOKEN_HINTS],
+        _ => &[],
+    };
+    tables
+        .iter()
+        .flat_map(|table| table.iter())
+        .find(|(c, _, _)| *c == code)
+        .map(|(_, name, hint)| (*name, *hint))
+}
+
+/// The program that raised a transaction's error: the innermost `Program <id> failed`
+/// line of `logs`, which is the first one, as the failure unwinds through its callers.
+pub fn failing_program(logs: &[String]) -> Option<Pubkey> {
+    logs.iter().find_map(|line| {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("Program"), Some(id), Some("failed:")) => Pubkey::from_str(id).ok(),
+            _ => None,
+        }
+    })
+}
+
+/// The program that raised the custom error of instruction `idx`: the failing program of
+/// `logs` when there are logs, else the instruction's own program.
+pub fn raising_program(idx: u8, ixs: &[Instruction], logs: &[String]) -> Option<Pubkey> {
+    failing_program(logs).or_else(|| ixs.get(idx as usize).map(|ix| ix.program_id))
+}
+
+/// Describe a failed transaction in terms of the instruction and program that raised it.
+/// `logs` are the transaction's program logs, if any, to find an error raised in a CPI.
+pub fn explain(err: &TransactionError, ixs: &[Instruction], logs: &[String]) -> Option<String> {
+    let TransactionError::InstructionError(idx, InstructionError::Custom(code)) = err else {
+        return None;
+    };
+    let outer = ixs.get(*idx as usize)?.program_id;
+    let program_id = raising_program(*idx, ixs, logs)?;
+    let (name, hint) = hint_for(&program_id, *code)?;
+    let raised_by = if program_id == outer {
+        String::new()
+    } else {
+        format!(" in {}", program_id)
+    };
+    Some(format!(
+        "instruction #{} ({}) failed with {} (0x{:x}){}: {}",
+        idx, outer, name, code, raised_by, hint
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn tables_apply_to_their_own_programs_only() {
+        let raydium = Pubkey::from_str(RAYDIUM_CLMM_PROGRAM_ID).unwrap();
+        let other = Pubkey::new_unique();
+
+        assert_eq!(
+            hint_for(&raydium, 3012).map(|(name, _)| name),
+            Some("AccountNotInitialized")
+        );
+        assert_eq!(hint_for(&other, 3012), None);
+
+        assert_eq!(
+            hint_for(&spl_token::ID, 1).map(|(name, _)| name),
+            Some("InsufficientFunds")
+        );
+        assert_eq!(
+            hint_for(&spl_token_2022::ID, 1).map(|(name, _)| name),
+            Some("InsufficientFunds")
+        );
+        assert_eq!(hint_for(&raydium, 1), None);
+        assert_eq!(hint_for(&other, 1), None);
+        assert_eq!(hint_for(&spl_token::ID, 3012), None);
+    }
+
+    #[test]
+    fn token_errors_under_a_dex_instruction_get_the_token_hint() {
+        let raydium = Pubkey::from_str(RAYDIUM_CLMM_PROGRAM_ID).unwrap();
+        let ixs = [
+            Instruction::new_with_bytes(Pubkey::new_unique(), &[], Vec::new()),
+            Instruction::new_with_bytes(raydium, &[], Vec::new()),
+        ];
+        let logs: Vec<String> = [
+            format!("Program {} invoke [1]", raydium),
+            format!("Program {} invoke [2]", spl_token::ID),
+            "Program log: Error: insufficient funds".to_string(),
+            format!(
+                "Program {} failed: custom program error: 0x1",
+                spl_token::ID
+            ),
+            format!("Program {} failed: custom program error: 0x1", raydium),
+        ]
+        .into();
+        let err = TransactionError::InstructionError(1, InstructionError::Custom(1));
+
+        assert_eq!(failing_program(&logs), Some(spl_token::ID));
+        let hint = explain(&err, &ixs, &logs).unwrap();
+        assert!(hint.contains("InsufficientFunds"), "{}", hint);
+        assert!(hint.contains(&spl_token::ID.to_string()), "{}", hint);
+        // Without logs the code is read as the DEX's own, which has no code 1.
+        assert_eq!(explain(&err, &ixs, &[]), None);
+    }
+}
@@ -0,0 +1,145 @@
+use std::str::FromStr;
+
+use aes_gcm_siv::aead::{Aead, NewAead};
+use aes_gcm_siv::{Aes256GcmSiv, Key, Nonce};
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use hmac::Hmac;
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::Sha256;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Keypair, Signer};
+
+use crate::signer;
+
+const VERSION: u64 = 1;
+const KDF: &str = "pbkdf2-hmac-sha256";
+const CIPHER: &str = "aes-256-gcm-siv";
+/// PBKDF2-HMAC-SHA256 work factor for new keystores (OWASP 2023 guidance).
+const ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// `--keystore-encrypt`: encrypt the `PRIVATE_KEY_B58` keypair with a passphrase into `path`.
+/// The passphrase comes from `KEYSTORE_PASSWORD` or is prompted for twice.
+pub(crate) fn encrypt_payer(path: &str) -> Result<()> {
+    if std::path::Path::new(path).exists() {
+        bail!("{} already exists; refusing to overwrite a keystore", path);
+    }
+    let keypair = signer::env_keypair()?;
+    let passphrase = passphrase(true)?;
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+    let cipher = Aes256GcmSiv::new(&Key::from(derive_key(&passphrase, &salt, ITERATIONS)));
+    let ciphertext = cipher
+        .encrypt(&Nonce::from(nonce), keypair.to_bytes().as_ref())
+        .map_err(|_| anyhow!("encrypt keypair"))?;
+
+    let doc = serde_json::json!({
+        "version": VERSION,
+        "pubkey": keypair.pubkey().to_string(),
+        "kdf": KDF,
+        "iterations": ITERATIONS,
+        "salt": BASE64.encode(salt),
+        "cipher": CIPHER,
+        "nonce": BASE64.encode(nonce),
+        "ciphertext": BASE64.encode(ciphertext),
+    });
+    std::fs::write(path, serde_json::to_string_pretty(&doc)?)
+        .with_context(|| format!("write {}", path))?;
+    println!("✅ Encrypted {} into {}", keypair.pubkey(), path);
+    println!(
+        "   Set KEYSTORE_PATH={} and remove PRIVATE_KEY_B58 from .env",
+        path
+    );
+    Ok(())
+}
+
+/// The public key recorded in the keystore at `path`, read without the passphrase.
+pub fn pubkey(path: &str) -> Result<Pubkey> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("read keystore {}", path))?;
+    let doc: serde_json::Value =
+        serde_json::from_str(&text).with_context(|| format!("parse keystore {}", path))?;
+    let pubkey = doc["pubkey"]
+        .as_str()
+        .ok_or_else(|| anyhow!("keystore {} is missing \"pubkey\"", path))?;
+    Pubkey::from_str(pubkey).with_context(|| format!("keystore {} has a bad pubkey", path))
+}
+
+/// Decrypt the keypair in the keystore at `path`.
+pub fn load(path: &str) -> Result<Keypair> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("read keystore {}", path))?;
+    let doc: serde_json::Value =
+        serde_json::from_str(&text).with_context(|| format!("parse keystore {}", path))?;
+    let field = |name: &str| {
+        doc[name]
+            .as_str()
+            .ok_or_else(|| anyhow!("keystore {} is missing {:?}", path, name))
+    };
+    if doc["version"].as_u64() != Some(VERSION)
+        || field("kdf")? != KDF
+        || field("cipher")? != CIPHER
+    {
+        bail!("keystore {} has an unsupported format", path);
+    }
+    let iterations = doc["iterations"]
+        .as_u64()
+        .and_then(|i| u32::try_from(i).ok())
+        .ok_or_else(|| anyhow!("keystore {} has a bad iteration count", path))?;
+    let salt = BASE64
+        .decode(field("salt")?)
+        .context("decode keystore salt")?;
+    let nonce: [u8; NONCE_LEN] = BASE64
+        .decode(field("nonce")?)
+        .context("decode keystore nonce")?
+        .try_into()
+        .map_err(|_| anyhow!("keystore {} has a bad nonce", path))?;
+    let ciphertext = BASE64
+        .decode(field("ciphertext")?)
+        .context("decode keystore ciphertext")?;
+
+    let passphrase = passphrase(false)?;
+    let cipher = Aes256GcmSiv::new(&Key::from(derive_key(&passphrase, &salt, iterations)));
+    let bytes = cipher
+        .decrypt(&Nonce::from(nonce), ciphertext.as_ref())
+        .map_err(|_| anyhow!("wrong passphrase for keystore {}", path))?;
+    let keypair = Keypair::from_bytes(&bytes).context("keystore holds an invalid keypair")?;
+    if let Some(pubkey) = doc["pubkey"].as_str()
+        && pubkey != keypair.pubkey().to_string()
+    {
+        bail!(
+            "keystore {} decrypted to {}, not {}",
+            path,
+            keypair.pubkey(),
+            pubkey
+        );
+    }
+    Ok(keypair)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, iterations, &mut key);
+    key
+}
+
+/// `KEYSTORE_PASSWORD`, else a terminal prompt (asked twice when `confirm`).
+fn passphrase(confirm: bool) -> Result<String> {
+    if let Ok(p) = std::env::var("KEYSTORE_PASSWORD") {
+        return Ok(p);
+    }
+    let p = rpassword::prompt_password("Keystore passphrase: ").context("read passphrase")?;
+    if p.is_empty() {
+        bail!("empty passphrase");
+    }
+    if confirm && rpassword::prompt_password("Repeat passphrase: ").context("read passphrase")? != p
+    {
+        bail!("passphrases do not match");
+    }
+    Ok(p)
+}
@@ -0,0 +1,45 @@
+//! Raydium CLMM, Orca Whirlpools and Meteora DLMM helpers behind the `solana_liquidity_arb`
+//! CLI, usable from other programs.
+//!
+//! The instruction builders (`raydium::build_open_position_ix`, `orca::PositionAccounts`,
+//! `meteora::build_add_liquidity_ix`, `raydium::build_swap_ixs`, ...) and pool decoders take
+//! decoded accounts or an `RpcClient` and return instructions; they never read stdin, `.env`
+//! or the command line, and never print. [`simulate_preview`] shows what a transaction of
+//! them would do (balance deltas, compute units, logs and program events) without a
+//! signer, for approval screens. [`tx::Flow`] simulates, checks against the
+//! [`tx::TxConfig`] destination allowlist, and signs and sends them; [`cluster::set`]
+//! points the DEX modules at devnet; [`positions::wallet_positions`] lists a wallet's
+//! positions and [`positions::watch`] streams one position's changes. The CLI itself, with its flags, config profiles and logging, stays private
+//! behind [`run_cli`].
+
+mod app;
+mod arb;
+mod batch;
+mod cli;
+mod compare;
+mod config;
+mod devtools;
+mod log;
+mod lut;
+mod nft;
+
+pub mod amounts;
+pub mod cluster;
+pub mod error;
+pub mod hints;
+pub mod keystore;
+pub mod meteora;
+pub mod orca;
+pub mod position_ref;
+pub mod positions;
+pub mod quote;
+pub mod raydium;
+pub mod rpc;
+pub mod signer;
+pub mod tx;
+
+pub use app::run_cli;
+pub use cli::{Cluster, Dex};
+pub use tx::{
+    LamportDelta, ProgramEvent, SimulationPreview, TokenDelta, simulate_preview, simulate_units,
+};
@@ -0,0 +1,61 @@
+use std::fs::OpenOptions;
+use std::sync::Mutex;
+
+use anyhow::{Context, Result, anyhow};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::cli::Verbosity;
+
+/// Route `tracing` events at or above `verbosity` to stderr, or append them to
+/// `log_file`. Lines read `[level][span] message`, the span being the command
+/// (`swap`, `open`, `remove`, ...) that emitted them.
+pub fn init(verbosity: Verbosity, log_file: Option<&str>) -> Result<()> {
+    let builder = tracing_subscriber::fmt()
+        .with_max_level(verbosity.level())
+        .event_format(LineFormat);
+    let installed = match log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("open --log-file {}", path))?;
+            builder.with_writer(Mutex::new(file)).try_init()
+        }
+        None => builder.with_writer(std::io::stderr).try_init(),
+    };
+    installed.map_err(|e| anyhow!("install logger: {}", e))
+}
+
+/// `[level][span]... message key=value ...`, with the spans the event is in, outermost first.
+struct LineFormat;
+
+impl<S, N> FormatEvent<S, N> for LineFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        write!(
+            writer,
+            "[{}]",
+            event.metadata().level().as_str().to_lowercase()
+        )?;
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                write!(writer, "[{}]", span.name())?;
+            }
+        }
+        write!(writer, " ")?;
+        ctx.field_format().format_fields(writer.by_ref(), event)?;
+        writeln!(writer)
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::{Context, Result, anyhow, bail};
+use solana_address_lookup_table_program::instruction::{create_lookup_table, extend_lookup_table};
+use solana_address_lookup_table_program::state::{AddressLookupTable, LOOKUP_TABLE_MAX_ADDRESSES};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig, compute_budget::ComputeBudgetInstruction,
+    instruction::Instruction, pubkey::Pubkey,
+};
+use tracing::{debug, instrument};
+
+use crate::cli::{Dex, Opts};
+use crate::quote::SwapLeg;
+use crate::tx::{Flow, Report, TxConfig};
+use crate::{meteora, orca, position_ref, raydium, rpc, signer};
+
+/// Where `--lut-file` defaults to, next to the config profiles.
+const DEFAULT_LUT_FILE: &str = "~/.config/solana-liq-arb/luts.json";
+
+/// Addresses added per extend transaction, which keeps it well under the size limit.
+const EXTEND_CHUNK: usize = 20;
+
+/// `lut TARGET`: create an address lookup table for a pool (the accounts a swap either
+/// way touches) or a position (the accounts collecting it touches), or extend the table
+/// already stored for it with whatever it lacks. Table addresses are kept in
+/// `--lut-file` as a JSON map from `<dex>:<target>` to the table.
+#[instrument(name = "lut", skip_all)]
+pub async fn run(opts: &Opts, config: &TxConfig, target_str: &str) -> Result<Report> {
+    let (venue, key) = position_ref::split(target_str)?;
+    let dex = venue.unwrap_or(opts.dex);
+    let target =
+        Pubkey::from_str(key).context("invalid lut target (expected a pool or position)")?;
+    let rpc = rpc::client(opts);
+
+    let wallet = signer::load_payer(opts).await?;
+    let payer_pk = wallet.pubkey();
+    let mut flow = Flow::new(&rpc, config, wallet.as_ref());
+
+    let addresses = target_accounts(&rpc, &payer_pk, opts, dex, &target).await?;
+    debug!("{} accounts for {}", addresses.len(), target);
+
+    let path = signer::expand_home(opts.lut_file.as_deref().unwrap_or(DEFAULT_LUT_FILE));
+    let mut store = read_store(&path)?;
+    let entry = format!("{}:{}", format!("{:?}", dex).to_lowercase(), target);
+    let budget = || {
+        vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
+        ]
+    };
+
+    let (table, existing, created) = match store.get(&entry) {
+        Some(table) => {
+            let table = Pubkey::from_str(table)
+                .with_context(|| format!("bad lookup table for {} in {}", entry, path))?;
+            let acc = rpc
+                .get_account(&table)
+                .await
+                .with_context(|| format!("fetch lookup table {}", table))?;
+            let existing = AddressLookupTable::deserialize(&acc.data)
+                .map_err(|e| anyhow!("decode lookup table {}: {}", table, e))?
+                .addresses
+                .to_vec();
+            (table, existing, false)
+        }
+        None => {
+            let slot = rpc
+                .get_slot_with_commitment(CommitmentConfig::finalized())
+                .await
+                .context("fetch recent slot")?;
+            let (ix, table) = create_lookup_table(payer_pk, payer_pk, slot);
+            let mut ixs = budget();
+            ixs.push(ix);
+            let sent = flow.send(ixs, &[]).await?;
+            if let Some(sig) = sent.signature() {
+                println!("✅ Created lookup table {}. Tx: {}", table, sig);
+            }
+            (table, Vec::new(), sent.signature().is_some())
+        }
+    };
+
+    let missing: Vec<Pubkey> = addresses
+        .into_iter()
+        .filter(|a| !existing.contains(a))
+        .collect();
+    if existing.len() + missing.len() > LOOKUP_TABLE_MAX_ADDRESSES {
+        bail!(
+            "lookup table {} holds {} addresses; {} more would exceed {}",
+            table,
+            existing.len(),
+            missing.len(),
+            LOOKUP_TABLE_MAX_ADDRESSES
+        );
+    }
+    for chunk in missing.chunks(EXTEND_CHUNK) {
+        let mut ixs = budget();
+        ixs.push(extend_lookup_table(
+            table,
+            payer_pk,
+            Some(payer_pk),
+            chunk.to_vec(),
+        ));
+        if let Some(sig) = flow.send(ixs, &[]).await?.signature() {
+            println!(
+                "✅ Extended lookup table {} by {} addresses. Tx: {}",
+                table,
+                chunk.len(),
+                sig
+            );
+        }
+    }
+    let report = flow.finish().await?;
+
+    println!(
+        "Lookup table {} for {}: {} addresses ({} new)",
+        table,
+        entry,
+        existing.len() + missing.len(),
+        missing.len()
+    );
+    if created {
+        store.insert(entry, table.to_string());
+        write_store(&path, &store)?;
+        println!("Stored in {}", path);
+    }
+    Ok(report)
+}
+
+/// What a table for `target` should hold: every account and program of a swap on it
+/// either way (a Raydium or Orca pool), or of collecting it (a position on any venue),
+/// less the signers, which a transaction must list itself.
+async fn target_accounts(
+    rpc: &RpcClient,
+    payer_pk: &Pubkey,
+    opts: &Opts,
+    dex: Dex,
+    target: &Pubkey,
+) -> Result<Vec<Pubkey>> {
+    let is_pool = match dex {
+        Dex::Raydium => raydium::pool_mints(rpc, target).await.is_ok(),
+        Dex::Orca => orca::pool_mints(rpc, target).await.is_ok(),
+        Dex::Meteora => meteora::pool_mints(rpc, target).await.is_ok(),
+    };
+    let mut ixs: Vec<Instruction> = Vec::new();
+    if is_pool {
+        for a_to_b in [true, false] {
+            let leg = SwapLeg {
+                pool: *target,
+                a_to_b,
+                amount_in: 1,
+                min_out: 0,
+                sqrt_price_limit: 0,
+                slippage_bps: None,
+            };
+            match dex {
+                Dex::Raydium => raydium::build_swap_ixs(rpc, payer_pk, &leg, &mut ixs).await?,
+                Dex::Orca => orca::build_swap_ixs(rpc, payer_pk, &leg, &mut ixs).await?,
+                Dex::Meteora => {
+                    bail!("lut takes Raydium and Orca pools, or positions on any venue")
+                }
+            };
+        }
+    } else {
+        let mut collect = opts.clone();
+        collect.dex = dex;
+        collect.swap_pool = None;
+        collect.pool = None;
+        collect.remove_position = None;
+        collect.resize_position = None;
+        collect.seed_mint = None;
+        collect.collect_fees = Some(target.to_string());
+        match dex {
+            Dex::Raydium => raydium::removal_ixs(rpc, payer_pk, &collect, &mut ixs).await?,
+            Dex::Orca => orca::removal_ixs(rpc, payer_pk, &collect, &mut ixs).await?,
+            Dex::Meteora => meteora::removal_ixs(rpc, payer_pk, &collect, &mut ixs).await?,
+        }
+    }
+
+    let signers: Vec<Pubkey> = ixs
+        .iter()
+        .flat_map(|ix| &ix.accounts)
+        .filter(|meta| meta.is_signer)
+        .map(|meta| meta.pubkey)
+        .collect();
+    let mut addresses = Vec::new();
+    for ix in &ixs {
+        for key in std::iter::once(&ix.program_id).chain(ix.accounts.iter().map(|m| &m.pubkey)) {
+            if !signers.contains(key) && !addresses.contains(key) {
+                addresses.push(*key);
+            }
+        }
+    }
+    Ok(addresses)
+}
+
+fn read_store(path: &str) -> Result<BTreeMap<String, String>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text).with_context(|| format!("parse {}", path)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e).with_context(|| format!("read {}", path)),
+    }
+}
+
+fn write_store(path: &str, store: &BTreeMap<String, String>) -> Result<()> {
+    if let Some(dir) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    }
+    let text = serde_json::to_string_pretty(store)?;
+    std::fs::write(path, text).with_context(|| format!("write {}", path))
+}
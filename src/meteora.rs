@@ -1,24 +1,23 @@
 use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context, Result};
-use solana_client::rpc_client::RpcClient;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
 use solana_sdk::{
-    commitment_config::CommitmentConfig,
     compute_budget::ComputeBudgetInstruction,
     instruction::Instruction,
     pubkey::Pubkey,
-    signature::{Keypair, SeedDerivable, Signer},
-};
-use spl_associated_token_account::{
-    get_associated_token_address_with_program_id, instruction::create_associated_token_account,
+    signature::{Keypair, Signer},
 };
+use spl_associated_token_account::get_associated_token_address_with_program_id;
 use spl_token;
-use spl_token_2022;
 use solana_pubkey::Pubkey as RawPubkey;
 use solana_instruction::Instruction as MetInstruction;
 
 use meteora_sol as met;
-use met::accounts::{LbPair, Position};
+use met::accounts::{BinArray, LbPair, Oracle, Position};
 use met::instructions::{
     add_liquidity::AddLiquidityBuilder,
     initialize_position::InitializePositionBuilder,
@@ -26,42 +25,87 @@ use met::instructions::{
     swap::SwapBuilder,
 };
 use met::types::{BinLiquidityDistribution, LiquidityParameter};
+use tracing::{debug, info, instrument, warn};
+
+use crate::amounts::{self, Prefetched};
+use crate::cli::{Dex, Mode, Opts};
+use crate::position_ref::PositionRef;
+use crate::positions::{PositionState, PositionSummary};
+use crate::quote::{
+    align_range, apply_slippage, enforce_max_price_impact, index_to_price, price_range_to_indices,
+    slippage_min_out, Grid, SwapQuote,
+};
+use crate::rpc;
+use crate::signer;
+use crate::tx::{self, Flow, Report, TxConfig, build_unwrap_sol_ix, build_wrap_sol_ixs};
 
-use crate::cli::Opts;
-use crate::tx::{build_unwrap_sol_ix, build_wrap_sol_ixs, simulate_and_send};
+/// Bin id bounds of the DLMM program.
+const MIN_BIN_ID: i32 = -443636;
+const MAX_BIN_ID: i32 = 443636;
 
-pub fn run(opts: Opts) -> Result<()> {
-    let rpc_url = opts
-        .rpc
-        .clone()
-        .or_else(|| std::env::var("RPC_URL").ok())
-        .unwrap_or_else(|| "https://api.mainnet-beta.solana.com".to_string());
-    eprintln!("[debug][meteora] rpc_url={}", rpc_url);
-    let rpc = RpcClient::new_with_commitment(rpc_url.clone(), CommitmentConfig::confirmed());
+pub(crate) async fn run(opts: Opts, config: &TxConfig) -> Result<Report> {
+    let rpc = rpc::client(&opts);
 
-    let key_b58 = std::env::var("PRIVATE_KEY_B58").context("Set PRIVATE_KEY_B58 in .env")?;
-    let payer = parse_phantom_base58_key(&key_b58)?;
-    let payer_pk = payer.pubkey();
+    if let Some(pool_str) = &opts.twap_pool {
+        handle_twap(&rpc, pool_str, opts.twap_window).await?;
+        return Ok(Report::default());
+    }
+    if opts.find_positions {
+        bail!("--find-positions is only available for Raydium");
+    }
+    if opts.resize_position.is_some() {
+        bail!("--resize-position is only available for Raydium");
+    }
+    if opts.seed_mint.is_some() {
+        bail!("--seed-mint is only available for Raydium");
+    }
+    if opts.optimize_range {
+        bail!("--optimize-range is only available for Raydium");
+    }
 
-    let pool_opt = opts.pool.clone();
+    let wallet = signer::load_payer(&opts).await?;
+    let payer_pk = wallet.pubkey();
+    let mut flow = Flow::new(&rpc, config, wallet.as_ref());
 
     let mut ixs: Vec<Instruction> = vec![
         ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
-        ComputeBudgetInstruction::set_compute_unit_price(opts.cu_price),
+        ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
     ];
 
     if opts.wrap_sol > 0 {
-        eprintln!("[debug] wrapping {} lamports into WSOL", opts.wrap_sol);
-        ixs.extend(build_wrap_sol_ixs(&rpc, &payer_pk, opts.wrap_sol)?);
+        debug!("wrapping {} lamports into WSOL", opts.wrap_sol);
+        ixs.extend(build_wrap_sol_ixs(&rpc, &payer_pk, opts.wrap_sol).await?);
     }
 
-    if let Some(pool_str) = &opts.swap_pool {
-        handle_swap(&rpc, &payer, &payer_pk, pool_str, &opts, &mut ixs)?;
-    } else if let Some(position_str) = &opts.remove_position {
-        handle_remove_all(&rpc, &payer, &payer_pk, position_str, &opts, &mut ixs)?;
-    } else if let Some(pool_str) = pool_opt.as_ref() {
-        handle_open(&rpc, &payer, &payer_pk, pool_str, opts, ixs)?;
-        return Ok(());
+    match opts.mode()? {
+        Mode::Swap(pool_str) => handle_swap(&rpc, &payer_pk, &pool_str, &opts, &mut ixs).await?,
+        Mode::Collect(position_str) => {
+            handle_remove_all(&rpc, &payer_pk, &position_str, &opts, &mut ixs, true).await?
+        }
+        Mode::Remove(position_str) => {
+            // A wide range opened as several positions is removed as a comma-separated list;
+            // every position but the last goes out in its own transaction.
+            let positions: Vec<&str> = position_str.split(',').map(str::trim).collect();
+            let (last, rest) = positions.split_last().context("empty --remove-position")?;
+            for position in rest {
+                let mut batch = vec![
+                    ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
+                    ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
+                ];
+                handle_remove_all(&rpc, &payer_pk, position, &opts, &mut batch, false).await?;
+                if let Some(sig) = flow.send(batch, &[]).await?.signature() {
+                    println!("✅ Removed Meteora position {}. Tx: {}", position, sig);
+                }
+            }
+            handle_remove_all(&rpc, &payer_pk, last, &opts, &mut ixs, false).await?;
+        }
+        Mode::Open(pool_str) => {
+            handle_open(&mut flow, &payer_pk, &pool_str, opts, ixs).await?;
+            return Ok(flow.finish().await?);
+        }
+        // Rejected above.
+        Mode::Resize(_) | Mode::Seed(_) => unreachable!(),
+        Mode::Wrap => {}
     }
 
     if opts.unwrap_sol {
@@ -69,240 +113,445 @@ pub fn run(opts: Opts) -> Result<()> {
     }
 
     if ixs.len() > 2 || opts.unwrap_sol {
-        let sig = simulate_and_send(&rpc, &payer, ixs, &[&payer])?;
-        println!("✅ Submitted Meteora tx: {}", sig);
+        if let Some(sig) = flow.send(ixs, &[]).await?.signature() {
+            println!("✅ Submitted Meteora tx: {}", sig);
+        }
     } else {
         if opts.unwrap_sol {
             println!("✅ Unwrapped WSOL.");
         } else {
-            bail!("provide swap/open/remove args or wrap/unwrap flags");
+            bail!("provide an open/remove/collect/swap/wrap/unwrap subcommand (or the equivalent flags)");
         }
     }
 
-    Ok(())
+    Ok(flow.finish().await?)
 }
 
-fn handle_open(
-    rpc: &RpcClient,
-    payer: &Keypair,
+#[instrument(name = "open", skip_all)]
+async fn handle_open(
+    flow: &mut Flow<'_>,
     payer_pk: &Pubkey,
     pool_str: &str,
     opts: Opts,
     mut ixs: Vec<Instruction>,
 ) -> Result<()> {
+    let rpc = flow.rpc();
     let lb_pair_pk =
         Pubkey::from_str(pool_str).context("invalid --pool (expected Meteora lb_pair address)")?;
-    let req_lower = *opts
-        .lower
-        .as_ref()
-        .context("missing --lower (bin id)")?;
-    let req_upper = *opts
-        .upper
-        .as_ref()
-        .context("missing --upper (bin id)")?;
-    if req_upper < req_lower {
-        bail!("upper must be >= lower (bin ids)");
+    let price_range = opts.price_range()?;
+    if price_range.is_none() {
+        let lower = opts
+            .lower
+            .context("missing --lower (bin id, or --price-min/--price-max)")?;
+        let upper = opts.upper.context("missing --upper (bin id)")?;
+        if upper < lower {
+            bail!("upper must be >= lower (bin ids)");
+        }
     }
     if opts.amount0 == 0 && opts.amount1 == 0 {
         bail!("specify --amount0 and/or --amount1");
     }
-    let width = (req_upper - req_lower + 1) as i32;
+    if opts.position_owner.is_some() {
+        bail!("--position-owner is not supported on Meteora: DLMM only accepts deposits signed by the position owner or its operator");
+    }
 
     let lb_acc = rpc
-        .get_account(&lb_pair_pk)
+        .get_account(&lb_pair_pk).await
         .with_context(|| format!("[meteora::open] fetch lb_pair {}", lb_pair_pk))?;
     let lb_pair: LbPair = LbPair::from_bytes(&lb_acc.data)
         .map_err(|e| anyhow!("[meteora::open] decode LbPair: {e}"))?;
 
     let token_x_mint = to_sdk_pubkey(&lb_pair.token_x_mint);
     let token_y_mint = to_sdk_pubkey(&lb_pair.token_y_mint);
-    let reserve_x = to_sdk_pubkey(&lb_pair.reserve_x);
-    let reserve_y = to_sdk_pubkey(&lb_pair.reserve_y);
-
-    let token_x_program = detect_token_program_for_mint(rpc, &token_x_mint)?;
-    let token_y_program = detect_token_program_for_mint(rpc, &token_y_mint)?;
-
-    ensure_ata(rpc, &mut ixs, payer_pk, &token_x_mint, &token_x_program)?;
-    ensure_ata(rpc, &mut ixs, payer_pk, &token_y_mint, &token_y_program)?;
-
-    let user_token_x =
-        get_associated_token_address_with_program_id(payer_pk, &token_x_mint, &token_x_program);
-    let user_token_y =
-        get_associated_token_address_with_program_id(payer_pk, &token_y_mint, &token_y_program);
-
-    let program_id = sdk_program_id();
-    let event_authority = derive_event_authority(&program_id);
+    let token_accounts =
+        Prefetched::fetch(rpc, &amounts::token_keys(payer_pk, &[token_x_mint, token_y_mint])).await?;
+
+    let (req_lower, req_upper) = match price_range {
+        Some(range) => {
+            let decimals_x = token_accounts.mint_decimals(&token_x_mint)?;
+            let decimals_y = token_accounts.mint_decimals(&token_y_mint)?;
+            let base = Grid::dlmm(lb_pair.bin_step).base;
+            let current = index_to_price(lb_pair.active_id, decimals_x, decimals_y, base);
+            let range = range.resolve(current, None)?;
+            let (l, u) = price_range_to_indices(range, decimals_x, decimals_y, base, 1);
+            info!(
+                "price range [{}, {}] -> bins [{}, {}]",
+                range.0, range.1, l, u
+            );
+            (l, u)
+        }
+        None => (opts.lower.unwrap_or_default(), opts.upper.unwrap_or_default()),
+    };
+    let (req_lower, req_upper) = align_range(
+        req_lower,
+        req_upper,
+        1,
+        (MIN_BIN_ID, MAX_BIN_ID),
+        opts.snap,
+        Grid::dlmm(lb_pair.bin_step).base,
+    )?;
+    let width = (req_upper - req_lower + 1) as i32;
 
-    // Derive bin array PDAs for the requested range. If both ends fall into the
-    // same BinArray, nudge the upper index so that we pass two distinct accounts
-    // to the program (avoids AccountBorrowFailed on duplicate mutable accounts),
-    // while still using the original [req_lower, req_upper] for the position.
-    let bin_array_lower_index = bin_array_index_for_bin_id(req_lower);
-    let mut bin_array_upper_index = bin_array_index_for_bin_id(req_upper);
-    if bin_array_lower_index == bin_array_upper_index {
-        bin_array_upper_index = bin_array_lower_index + 1;
+    let programs = amounts::token_programs(&token_accounts, &[token_x_mint, token_y_mint])?;
+    let (token_x_program, token_y_program) = (programs[0], programs[1]);
+
+    amounts::ensure_atas(
+        &token_accounts,
+        &mut ixs,
+        payer_pk,
+        &[(token_x_mint, token_x_program), (token_y_mint, token_y_program)],
+    )?;
+
+    // A DLMM position spans at most MAX_POSITION_WIDTH bins. Wider ranges are opened as
+    // consecutive positions with the amounts split by bin count. Positions share a
+    // transaction while it still fits and simulates within --cu-limit.
+    let chunks: Vec<(i32, i32)> = (req_lower..=req_upper)
+        .step_by(MAX_POSITION_WIDTH as usize)
+        .map(|lo| (lo, (lo + MAX_POSITION_WIDTH - 1).min(req_upper)))
+        .collect();
+    if chunks.len() > 1 {
+        info!(
+            "range [{}, {}] is {} bins; splitting into {} positions",
+            req_lower,
+            req_upper,
+            width,
+            chunks.len()
+        );
     }
 
-    let bin_array_lower =
-        derive_bin_array_address(&program_id, &lb_pair_pk, bin_array_lower_index);
-    let bin_array_upper =
-        derive_bin_array_address(&program_id, &lb_pair_pk, bin_array_upper_index);
-
-    let position = Keypair::new();
-
-    let init_ix = InitializePositionBuilder::new()
-        .payer(to_raw_pubkey(payer_pk))
-        .position(to_raw_pubkey(&position.pubkey()))
-        .lb_pair(to_raw_pubkey(&lb_pair_pk))
-        .owner(to_raw_pubkey(payer_pk))
-        .event_authority(to_raw_pubkey(&event_authority))
-        .program(met::LB_CLMM_ID)
-        .lower_bin_id(req_lower)
-        .width(width)
-        .instruction();
-    ixs.push(to_sdk_instruction(init_ix));
-
-    let share = uniform_distribution(width as usize, opts.amount0, opts.amount1)?;
-    let mut dists = Vec::with_capacity(width as usize);
-    for bin_id in req_lower..=req_upper {
-        dists.push(BinLiquidityDistribution {
-            bin_id,
-            distribution_x: if opts.amount0 > 0 { share } else { 0 },
-            distribution_y: if opts.amount1 > 0 { share } else { 0 },
-        });
+    let mut remaining_x = opts.amount0;
+    let mut remaining_y = opts.amount1;
+    let mut opened = Vec::with_capacity(chunks.len());
+    // The first transaction carries the caller's budget/wrap/ATA instructions.
+    let mut tx_ixs = std::mem::take(&mut ixs);
+    let mut batch: Vec<(Keypair, i32, i32)> = Vec::new();
+    for (i, (lower, upper)) in chunks.iter().copied().enumerate() {
+        let chunk_width = upper - lower + 1;
+        let last = i + 1 == chunks.len();
+        let amount_x = if last {
+            remaining_x
+        } else {
+            (opts.amount0 as u128 * chunk_width as u128 / width as u128) as u64
+        };
+        let amount_y = if last {
+            remaining_y
+        } else {
+            (opts.amount1 as u128 * chunk_width as u128 / width as u128) as u64
+        };
+        remaining_x -= amount_x;
+        remaining_y -= amount_y;
+
+        let accounts = PositionAccounts::new(
+            &lb_pair_pk,
+            &lb_pair,
+            payer_pk,
+            (lower, upper),
+            (token_x_program, token_y_program),
+        );
+        let position = Keypair::new();
+        let position_ixs = vec![
+            build_open_position_ix(&accounts, &position.pubkey()),
+            build_add_liquidity_ix(&accounts, &position.pubkey(), (amount_x, amount_y))?,
+        ];
+
+        if !batch.is_empty() {
+            let mut packed = tx_ixs.clone();
+            packed.extend(position_ixs.iter().cloned());
+            if flow.fits_budget(&packed, opts.cu_limit).await {
+                tx_ixs = packed;
+                batch.push((position, lower, upper));
+                continue;
+            }
+            send_positions(flow, tx_ixs, &batch, &mut opened).await?;
+            batch.clear();
+            tx_ixs = vec![
+                ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
+                ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
+            ];
+        }
+        tx_ixs.extend(position_ixs);
+        batch.push((position, lower, upper));
+    }
+    send_positions(flow, tx_ixs, &batch, &mut opened).await?;
+
+    if opened.len() > 1 {
+        println!(
+            "✅ Wide range held by {} positions; remove them together with --remove-position {}",
+            opened.len(),
+            opened.join(",")
+        );
     }
-    let lp = LiquidityParameter {
-        amount_x: opts.amount0,
-        amount_y: opts.amount1,
-        bin_liquidity_dist: dists,
-    };
-
-    let add_ix = AddLiquidityBuilder::new()
-        .position(to_raw_pubkey(&position.pubkey()))
-        .lb_pair(to_raw_pubkey(&lb_pair_pk))
-        .bin_array_bitmap_extension(None)
-        .user_token_x(to_raw_pubkey(&user_token_x))
-        .user_token_y(to_raw_pubkey(&user_token_y))
-        .reserve_x(to_raw_pubkey(&reserve_x))
-        .reserve_y(to_raw_pubkey(&reserve_y))
-        .token_x_mint(lb_pair.token_x_mint)
-        .token_y_mint(lb_pair.token_y_mint)
-        .bin_array_lower(to_raw_pubkey(&bin_array_lower))
-        .bin_array_upper(to_raw_pubkey(&bin_array_upper))
-        .sender(to_raw_pubkey(payer_pk))
-        .token_x_program(to_raw_pubkey(&token_x_program))
-        .token_y_program(to_raw_pubkey(&token_y_program))
-        .event_authority(to_raw_pubkey(&event_authority))
-        .program(met::LB_CLMM_ID)
-        .liquidity_parameter(lp)
-        .instruction();
-    ixs.push(to_sdk_instruction(add_ix));
 
-    let sig = simulate_and_send(rpc, payer, ixs, &[payer, &position])?;
-    println!(
-        "✅ Opened Meteora position. Position account: {}. Tx: {}",
-        position.pubkey(),
-        sig
-    );
+    Ok(())
+}
 
+/// Send `ixs` opening `positions` (keypair, lower, upper bin), co-signed by every position
+/// keypair, and add the opened position accounts to `opened`.
+async fn send_positions(
+    flow: &mut Flow<'_>,
+    ixs: Vec<Instruction>,
+    positions: &[(Keypair, i32, i32)],
+    opened: &mut Vec<String>,
+) -> Result<()> {
+    let signers: Vec<&dyn Signer> = positions
+        .iter()
+        .map(|(position, _, _)| position as &dyn Signer)
+        .collect();
+    let sent = flow.send(ixs, &signers).await?;
+    for (position, lower, upper) in positions {
+        if let Some(sig) = sent.signature() {
+            println!(
+                "✅ Opened Meteora position [{}, {}]. Position account: {}. Tx: {}",
+                lower,
+                upper,
+                position.pubkey(),
+                sig
+            );
+        }
+        opened.push(position.pubkey().to_string());
+    }
     Ok(())
 }
 
-fn handle_remove_all(
+/// Queue the `collect`/`remove` that `opts` asks for onto `ixs` without sending, for
+/// `--positions-file` rows that share a transaction.
+pub(crate) async fn removal_ixs(
+    rpc: &RpcClient,
+    payer_pk: &Pubkey,
+    opts: &Opts,
+    ixs: &mut Vec<Instruction>,
+) -> Result<()> {
+    match opts.mode()? {
+        Mode::Collect(position) => handle_remove_all(rpc, payer_pk, &position, opts, ixs, true).await,
+        Mode::Remove(position) => handle_remove_all(rpc, payer_pk, &position, opts, ixs, false).await,
+        _ => bail!("only collect and remove can share a transaction"),
+    }
+}
+
+#[instrument(name = "remove", skip_all)]
+async fn handle_remove_all(
     rpc: &RpcClient,
-    payer: &Keypair,
     payer_pk: &Pubkey,
     position_str: &str,
     opts: &Opts,
     ixs: &mut Vec<Instruction>,
+    collect_only: bool,
 ) -> Result<()> {
     let position_pk =
-        Pubkey::from_str(position_str).context("invalid --remove-position (Position account)")?;
+        Pubkey::from_str(position_str).context("invalid position (Position account)")?;
+    if opts.remove_liquidity.is_some() || opts.remove_percent.is_some() {
+        bail!("partial removal (--remove-liquidity/--remove-percent) is only supported on Raydium");
+    }
+    if opts.nft_owner.is_some() {
+        bail!("--nft-owner is not supported on Meteora: DLMM positions are accounts, not delegable NFTs");
+    }
     let pos_acc = rpc
-        .get_account(&position_pk)
+        .get_account(&position_pk).await
         .with_context(|| format!("[meteora::remove] fetch position {}", position_pk))?;
     let pos: Position = Position::from_bytes(&pos_acc.data)
         .map_err(|e| anyhow!("[meteora::remove] decode Position: {e}"))?;
 
     let lb_pair_pk = to_sdk_pubkey(&pos.lb_pair);
-    let lower = pos.lower_bin_id;
-    let upper = pos.upper_bin_id;
 
     let lb_acc = rpc
-        .get_account(&lb_pair_pk)
+        .get_account(&lb_pair_pk).await
         .with_context(|| format!("[meteora::remove] fetch lb_pair {}", lb_pair_pk))?;
     let lb_pair: LbPair = LbPair::from_bytes(&lb_acc.data)
         .map_err(|e| anyhow!("[meteora::remove] decode LbPair: {e}"))?;
 
     let token_x_mint = to_sdk_pubkey(&lb_pair.token_x_mint);
     let token_y_mint = to_sdk_pubkey(&lb_pair.token_y_mint);
-    let reserve_x = to_sdk_pubkey(&lb_pair.reserve_x);
-    let reserve_y = to_sdk_pubkey(&lb_pair.reserve_y);
-
-    let token_x_program = detect_token_program_for_mint(rpc, &token_x_mint)?;
-    let token_y_program = detect_token_program_for_mint(rpc, &token_y_mint)?;
-
-    ensure_ata(rpc, ixs, payer_pk, &token_x_mint, &token_x_program)?;
-    ensure_ata(rpc, ixs, payer_pk, &token_y_mint, &token_y_program)?;
 
-    let user_token_x =
-        get_associated_token_address_with_program_id(payer_pk, &token_x_mint, &token_x_program);
-    let user_token_y =
-        get_associated_token_address_with_program_id(payer_pk, &token_y_mint, &token_y_program);
-
-    let program_id = sdk_program_id();
-    let event_authority = derive_event_authority(&program_id);
-
-    let bin_array_lower_index = bin_array_index_for_bin_id(lower);
-    let mut bin_array_upper_index = bin_array_index_for_bin_id(upper);
-    if bin_array_lower_index == bin_array_upper_index {
-        bin_array_upper_index = bin_array_lower_index + 1;
+    // The token accounts the removal pays into, reward ones included, and the bin arrays
+    // the slippage check reads, in one round trip.
+    let reward_mints: Vec<Pubkey> = lb_pair
+        .reward_infos
+        .iter()
+        .map(|reward| to_sdk_pubkey(&reward.mint))
+        .filter(|mint| *mint != Pubkey::default())
+        .collect();
+    let mut keys = amounts::token_keys(payer_pk, &[token_x_mint, token_y_mint]);
+    if collect_only {
+        keys.extend(amounts::token_keys(payer_pk, &reward_mints));
+    } else if opts.slippage_bps.is_some() {
+        keys.extend(position_bin_arrays(&pos, &lb_pair_pk).into_iter().map(|(_, key)| key));
     }
+    let fetched = Prefetched::fetch(rpc, &keys).await?;
+
+    let programs = amounts::token_programs(&fetched, &[token_x_mint, token_y_mint])?;
+    let (token_x_program, token_y_program) = (programs[0], programs[1]);
+
+    amounts::ensure_atas(
+        &fetched,
+        ixs,
+        payer_pk,
+        &[(token_x_mint, token_x_program), (token_y_mint, token_y_program)],
+    )?;
+    let accounts = PositionAccounts::new(
+        &lb_pair_pk,
+        &lb_pair,
+        payer_pk,
+        (pos.lower_bin_id, pos.upper_bin_id),
+        (token_x_program, token_y_program),
+    );
 
-    let bin_array_lower =
-        derive_bin_array_address(&program_id, &lb_pair_pk, bin_array_lower_index);
-    let bin_array_upper =
-        derive_bin_array_address(&program_id, &lb_pair_pk, bin_array_upper_index);
-
-    let remove_ix = RemoveAllLiquidityBuilder::new()
-        .position(to_raw_pubkey(&position_pk))
-        .lb_pair(to_raw_pubkey(&lb_pair_pk))
-        .bin_array_bitmap_extension(None)
-        .user_token_x(to_raw_pubkey(&user_token_x))
-        .user_token_y(to_raw_pubkey(&user_token_y))
-        .reserve_x(to_raw_pubkey(&reserve_x))
-        .reserve_y(to_raw_pubkey(&reserve_y))
-        .token_x_mint(lb_pair.token_x_mint)
-        .token_y_mint(lb_pair.token_y_mint)
-        .bin_array_lower(to_raw_pubkey(&bin_array_lower))
-        .bin_array_upper(to_raw_pubkey(&bin_array_upper))
-        .sender(to_raw_pubkey(payer_pk))
-        .token_x_program(to_raw_pubkey(&token_x_program))
-        .token_y_program(to_raw_pubkey(&token_y_program))
-        .event_authority(to_raw_pubkey(&event_authority))
-        .program(met::LB_CLMM_ID)
-        .instruction();
-    ixs.push(to_sdk_instruction(remove_ix));
-
-    if opts.close {
-        use met::instructions::close_position_if_empty::ClosePositionIfEmptyBuilder;
+    if collect_only {
+        use met::instructions::claim_fee::ClaimFeeBuilder;
+        use met::instructions::claim_reward::ClaimRewardBuilder;
 
-        let close_ix = ClosePositionIfEmptyBuilder::new()
+        // ClaimFee takes a single token program for both sides.
+        if token_x_program != token_y_program {
+            bail!("ClaimFee needs both pool tokens under the same token program");
+        }
+        let claim_fee_ix = ClaimFeeBuilder::new()
+            .lb_pair(to_raw_pubkey(&lb_pair_pk))
             .position(to_raw_pubkey(&position_pk))
+            .bin_array_lower(to_raw_pubkey(&accounts.bin_array_lower))
+            .bin_array_upper(to_raw_pubkey(&accounts.bin_array_upper))
             .sender(to_raw_pubkey(payer_pk))
-            .rent_receiver(to_raw_pubkey(payer_pk))
-            .event_authority(to_raw_pubkey(&event_authority))
+            .reserve_x(to_raw_pubkey(&accounts.reserve_x))
+            .reserve_y(to_raw_pubkey(&accounts.reserve_y))
+            .user_token_x(to_raw_pubkey(&accounts.user_token_x))
+            .user_token_y(to_raw_pubkey(&accounts.user_token_y))
+            .token_x_mint(lb_pair.token_x_mint)
+            .token_y_mint(lb_pair.token_y_mint)
+            .token_program(to_raw_pubkey(&token_x_program))
+            .event_authority(to_raw_pubkey(&accounts.event_authority))
             .program(met::LB_CLMM_ID)
             .instruction();
-        ixs.push(to_sdk_instruction(close_ix));
+        ixs.push(to_sdk_instruction(claim_fee_ix));
+
+        let rewards: Vec<_> = lb_pair
+            .reward_infos
+            .iter()
+            .enumerate()
+            .filter(|(_, reward)| to_sdk_pubkey(&reward.mint) != Pubkey::default())
+            .collect();
+        let reward_programs = amounts::token_programs(&fetched, &reward_mints)?;
+        let reward_accounts = amounts::ensure_atas(
+            &fetched,
+            ixs,
+            payer_pk,
+            &reward_mints.iter().copied().zip(reward_programs.iter().copied()).collect::<Vec<_>>(),
+        )?;
+        for ((((i, reward), reward_mint), reward_program), (user_reward, _)) in rewards
+            .into_iter()
+            .zip(reward_mints)
+            .zip(reward_programs)
+            .zip(reward_accounts)
+        {
+            debug!("claiming reward #{} mint={}", i, reward_mint);
+            let claim_reward_ix = ClaimRewardBuilder::new()
+                .lb_pair(to_raw_pubkey(&lb_pair_pk))
+                .position(to_raw_pubkey(&position_pk))
+                .bin_array_lower(to_raw_pubkey(&accounts.bin_array_lower))
+                .bin_array_upper(to_raw_pubkey(&accounts.bin_array_upper))
+                .sender(to_raw_pubkey(payer_pk))
+                .reward_vault(reward.vault)
+                .reward_mint(reward.mint)
+                .user_token_account(to_raw_pubkey(&user_reward))
+                .token_program(to_raw_pubkey(&reward_program))
+                .event_authority(to_raw_pubkey(&accounts.event_authority))
+                .program(met::LB_CLMM_ID)
+                .reward_index(i as u64)
+                .instruction();
+            ixs.push(to_sdk_instruction(claim_reward_ix));
+        }
+        return Ok(());
+    }
+
+    ixs.push(build_remove_all_liquidity_ix(&accounts, &position_pk));
+    if opts.slippage_bps.is_some() || opts.min_out0 > 0 || opts.min_out1 > 0 {
+        check_removal_min_outs(rpc, &fetched, payer_pk, ixs, &pos, &accounts, opts).await?;
+    }
+    if opts.close {
+        ixs.push(build_close_position_ix(&accounts, &position_pk));
     }
 
     Ok(())
 }
 
-fn handle_swap(
+/// RemoveAllLiquidity takes no min-outs, so check the simulated payout instead: it must
+/// reach `--min-out0`/`--min-out1`, and with `--slippage-bps` the position's share of its
+/// bins' reserves minus that many bps. This runs just before sending and cannot catch a
+/// price move between the simulation and the transaction landing.
+async fn check_removal_min_outs(
+    rpc: &RpcClient,
+    fetched: &Prefetched,
+    payer_pk: &Pubkey,
+    ixs: &[Instruction],
+    pos: &Position,
+    accounts: &PositionAccounts,
+    opts: &Opts,
+) -> Result<()> {
+    let (min_x, min_y) = match opts.slippage_bps {
+        Some(bps) => {
+            let (x, y) = expected_removal(fetched, pos, accounts)?;
+            let (min_x, min_y) = (apply_slippage(x, bps), apply_slippage(y, bps));
+            debug!(
+                "expected out x={} y={}; min with {} bps slippage: {} / {}",
+                x, y, bps, min_x, min_y
+            );
+            (opts.min_out0.max(min_x), opts.min_out1.max(min_y))
+        }
+        None => (opts.min_out0, opts.min_out1),
+    };
+    let preview = tx::simulate_preview(rpc, payer_pk, ixs).await?;
+    if preview.err.is_some() {
+        // Sending reports the failure with its program logs.
+        return Ok(());
+    }
+    let out_x = preview.gain(&accounts.user_token_x);
+    let out_y = preview.gain(&accounts.user_token_y);
+    if out_x < min_x || out_y < min_y {
+        bail!(
+            "[meteora::remove] removal pays {} X / {} Y, below the min-outs {} / {}",
+            out_x,
+            out_y,
+            min_x,
+            min_y
+        );
+    }
+    Ok(())
+}
+
+/// Bin arrays `(index, address)` covering the bins of `pos`.
+fn position_bin_arrays(pos: &Position, lb_pair_pk: &Pubkey) -> Vec<(i64, Pubkey)> {
+    let program_id = sdk_program_id();
+    (bin_array_index_for_bin_id(pos.lower_bin_id)..=bin_array_index_for_bin_id(pos.upper_bin_id))
+        .map(|index| (index, derive_bin_array_address(&program_id, lb_pair_pk, index)))
+        .collect()
+}
+
+/// Amounts RemoveAllLiquidity pays out for `pos` at its bins' current reserves, with the
+/// bin arrays of [`position_bin_arrays`] read into `fetched`.
+fn expected_removal(fetched: &Prefetched, pos: &Position, accounts: &PositionAccounts) -> Result<(u64, u64)> {
+    let q64 = (1u128 << 64) as f64;
+    let (mut x, mut y) = (0f64, 0f64);
+    for (index, key) in position_bin_arrays(pos, &accounts.lb_pair) {
+        // An array that was never initialized holds no liquidity.
+        let Some(acc) = fetched.get(&key)? else { continue };
+        let arr = BinArray::from_bytes(&acc.data)
+            .map_err(|e| anyhow!("[meteora::remove] decode BinArray {}: {e}", key))?;
+        let first_bin = index as i32 * BINS_PER_ARRAY;
+        for (offset, bin) in arr.bins.iter().enumerate() {
+            let bin_id = first_bin + offset as i32;
+            if bin_id < pos.lower_bin_id || bin_id > pos.upper_bin_id || bin.liquidity_supply == 0 {
+                continue;
+            }
+            // Position shares are whole units; bin liquidity carries 64 fractional bits.
+            let share = pos.liquidity_shares[(bin_id - pos.lower_bin_id) as usize] as f64 * q64
+                / bin.liquidity_supply as f64;
+            x += bin.amount_x as f64 * share.min(1.0);
+            y += bin.amount_y as f64 * share.min(1.0);
+        }
+    }
+    Ok((x as u64, y as u64))
+}
+
+#[instrument(name = "swap", skip_all)]
+async fn handle_swap(
     rpc: &RpcClient,
-    _payer: &Keypair,
     payer_pk: &Pubkey,
     pool_str: &str,
     opts: &Opts,
@@ -315,7 +564,7 @@ fn handle_swap(
     let lb_pair_pk =
         Pubkey::from_str(pool_str).context("invalid --swap-pool (lb_pair address)")?;
     let lb_acc = rpc
-        .get_account(&lb_pair_pk)
+        .get_account(&lb_pair_pk).await
         .with_context(|| format!("[meteora::swap] fetch lb_pair {}", lb_pair_pk))?;
     let lb_pair: LbPair = LbPair::from_bytes(&lb_acc.data)
         .map_err(|e| anyhow!("[meteora::swap] decode LbPair: {e}"))?;
@@ -326,11 +575,23 @@ fn handle_swap(
     let reserve_y = to_sdk_pubkey(&lb_pair.reserve_y);
     let oracle = to_sdk_pubkey(&lb_pair.oracle);
 
-    let token_x_program = detect_token_program_for_mint(rpc, &token_x_mint)?;
-    let token_y_program = detect_token_program_for_mint(rpc, &token_y_mint)?;
+    let program_id = sdk_program_id();
+    let mut bin_arrays = swap_bin_arrays(&program_id, &lb_pair_pk, &lb_pair, opts.swap_a_to_b);
+
+    // The token accounts and the bin arrays the quote walks, in one round trip.
+    let mut keys = amounts::token_keys(payer_pk, &[token_x_mint, token_y_mint]);
+    keys.extend(bin_arrays.iter().map(|(_, key)| *key));
+    let fetched = Prefetched::fetch(rpc, &keys).await?;
+
+    let programs = amounts::token_programs(&fetched, &[token_x_mint, token_y_mint])?;
+    let (token_x_program, token_y_program) = (programs[0], programs[1]);
 
-    ensure_ata(rpc, ixs, payer_pk, &token_x_mint, &token_x_program)?;
-    ensure_ata(rpc, ixs, payer_pk, &token_y_mint, &token_y_program)?;
+    amounts::ensure_atas(
+        &fetched,
+        ixs,
+        payer_pk,
+        &[(token_x_mint, token_x_program), (token_y_mint, token_y_program)],
+    )?;
 
     let user_token_x =
         get_associated_token_address_with_program_id(payer_pk, &token_x_mint, &token_x_program);
@@ -343,27 +604,32 @@ fn handle_swap(
         (user_token_y, user_token_x)
     };
 
-    let program_id = sdk_program_id();
     let event_authority = derive_event_authority(&program_id);
 
-    // Build a small window of BinArray PDAs around the active bin.
-    // DLMM expects these as remaining accounts for swap path traversal.
-    let active_id = lb_pair.active_id;
-    const BIN_ARRAY_WINDOW: usize = 3;
-    let mut indices = Vec::with_capacity(BIN_ARRAY_WINDOW);
-    indices.push(bin_array_index_for_bin_id(active_id));
-    let mut offset = 1;
-    while indices.len() < BIN_ARRAY_WINDOW {
-        indices.push(bin_array_index_for_bin_id(active_id + offset * BINS_PER_ARRAY));
-        indices.push(bin_array_index_for_bin_id(active_id - offset * BINS_PER_ARRAY));
-        offset += 1;
+    let (quote, used) = quote_swap_dlmm(
+        &fetched,
+        &lb_pair,
+        &bin_arrays,
+        opts.swap_amount_in,
+        opts.swap_a_to_b,
+    )?;
+    // Keep one spare array in case the price moves before the swap lands.
+    bin_arrays.truncate(used + 1);
+    debug!(
+        "passing {} bin arrays (quote reaches {})",
+        bin_arrays.len(),
+        used
+    );
+    enforce_max_price_impact(&quote, opts.max_price_impact_bps)?;
+    let min_out = slippage_min_out(&quote, opts.swap_min_out, opts.slippage_bps);
+    if opts.slippage_bps.is_some() {
+        debug!("min_out={} from quote", min_out);
     }
 
     let mut remaining: Vec<solana_instruction::AccountMeta> =
-        Vec::with_capacity(indices.len());
-    for idx in indices {
-        let ba_sdk = derive_bin_array_address(&program_id, &lb_pair_pk, idx);
-        let ba_raw = to_raw_pubkey(&ba_sdk);
+        Vec::with_capacity(bin_arrays.len());
+    for (_, ba_sdk) in &bin_arrays {
+        let ba_raw = to_raw_pubkey(ba_sdk);
         remaining.push(solana_instruction::AccountMeta::new(ba_raw, false));
     }
 
@@ -384,7 +650,7 @@ fn handle_swap(
         .event_authority(to_raw_pubkey(&event_authority))
         .program(met::LB_CLMM_ID)
         .amount_in(opts.swap_amount_in)
-        .min_amount_out(opts.swap_min_out)
+        .min_amount_out(min_out)
         .add_remaining_accounts(&remaining)
         .instruction();
 
@@ -393,54 +659,526 @@ fn handle_swap(
     Ok(())
 }
 
-fn parse_phantom_base58_key(s: &str) -> Result<Keypair> {
-    let bytes = bs58::decode(s.trim())
-        .into_vec()
-        .context("Invalid base58 in PRIVATE_KEY_B58")?;
-    match bytes.len() {
-        64 => Keypair::from_bytes(&bytes).context("Failed to parse 64-byte ed25519 keypair"),
-        32 => {
-            let mut seed = [0u8; 32];
-            seed.copy_from_slice(&bytes);
-            Keypair::from_seed(&seed)
-                .map_err(|e| anyhow!("Failed to derive keypair from 32-byte seed: {e}"))
+/// BinArray PDAs for swap path traversal, passed to DLMM as remaining accounts: the active
+/// array, then the initialized arrays in the swap direction according to the pair's bitmap
+/// (up to `MAX_SWAP_BIN_ARRAYS` in total). Arrays beyond the bitmap would need the bitmap
+/// extension account and are not included.
+fn swap_bin_arrays(
+    program_id: &Pubkey,
+    lb_pair_pk: &Pubkey,
+    lb_pair: &LbPair,
+    swap_for_y: bool,
+) -> Vec<(i64, Pubkey)> {
+    const MAX_SWAP_BIN_ARRAYS: usize = 8;
+    const BITMAP_HALF: i64 = 512;
+
+    let initialized = |idx: i64| {
+        let pos = idx + BITMAP_HALF;
+        (0..2 * BITMAP_HALF).contains(&pos)
+            && (lb_pair.bin_array_bitmap[(pos / 64) as usize] >> (pos % 64)) & 1 == 1
+    };
+    let step = if swap_for_y { -1 } else { 1 };
+    let active = bin_array_index_for_bin_id(lb_pair.active_id);
+    let mut indices = vec![active];
+    let mut idx = active + step;
+    while indices.len() < MAX_SWAP_BIN_ARRAYS && (-BITMAP_HALF..BITMAP_HALF).contains(&idx) {
+        if initialized(idx) {
+            indices.push(idx);
         }
-        n => bail!(
-            "Decoded private key had {} bytes; expected 32 or 64 (Phantom exports 64)",
-            n
-        ),
+        idx += step;
     }
+
+    indices
+        .iter()
+        .map(|idx| (*idx, derive_bin_array_address(program_id, lb_pair_pk, *idx)))
+        .collect()
+}
+
+/// Token mints `(x, y)` of an lb_pair.
+pub(crate) async fn pool_mints(rpc: &RpcClient, lb_pair_pk: &Pubkey) -> Result<(Pubkey, Pubkey)> {
+    let lb_acc = rpc
+        .get_account(lb_pair_pk).await
+        .with_context(|| format!("[meteora] fetch lb_pair {}", lb_pair_pk))?;
+    let lb_pair: LbPair =
+        LbPair::from_bytes(&lb_acc.data).map_err(|e| anyhow!("[meteora] decode LbPair: {e}"))?;
+    Ok((to_sdk_pubkey(&lb_pair.token_x_mint), to_sdk_pubkey(&lb_pair.token_y_mint)))
+}
+
+/// Token mints `(x, y)` and bin grid of an lb_pair.
+pub(crate) async fn pool_grid(rpc: &RpcClient, lb_pair_pk: &Pubkey) -> Result<(Pubkey, Pubkey, Grid)> {
+    let lb_acc = rpc
+        .get_account(lb_pair_pk).await
+        .with_context(|| format!("[meteora] fetch lb_pair {}", lb_pair_pk))?;
+    let lb_pair: LbPair =
+        LbPair::from_bytes(&lb_acc.data).map_err(|e| anyhow!("[meteora] decode LbPair: {e}"))?;
+    Ok((
+        to_sdk_pubkey(&lb_pair.token_x_mint),
+        to_sdk_pubkey(&lb_pair.token_y_mint),
+        Grid::dlmm(lb_pair.bin_step),
+    ))
+}
+
+/// DLMM Position accounts owned by `owner`.
+pub(crate) async fn wallet_positions(rpc: &RpcClient, owner: &Pubkey) -> Result<Vec<PositionSummary>> {
+    // Position layout: discriminator, lb_pair, owner.
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(Position::LEN as u64),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(40, owner.to_bytes().to_vec())),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc
+        .get_program_accounts_with_config(&sdk_program_id(), config).await
+        .context("[meteora::positions] fetch positions")?;
+
+    let mut positions = Vec::with_capacity(accounts.len());
+    for (position_pk, acc) in accounts {
+        let pos: Position = Position::from_bytes(&acc.data)
+            .map_err(|e| anyhow!("[meteora::positions] decode Position {}: {e}", position_pk))?;
+        positions.push((position_pk, pos));
+    }
+    let lb_pair_pks: Vec<Pubkey> = positions.iter().map(|(_, pos)| to_sdk_pubkey(&pos.lb_pair)).collect();
+    let lb_pairs = Prefetched::fetch(rpc, &lb_pair_pks).await?;
+
+    let mut out = Vec::with_capacity(positions.len());
+    for ((position_pk, pos), lb_pair_pk) in positions.into_iter().zip(lb_pair_pks) {
+        let lb_acc = lb_pairs.require(&lb_pair_pk, "[meteora::positions] lb_pair")?;
+        let lb_pair: LbPair = LbPair::from_bytes(&lb_acc.data)
+            .map_err(|e| anyhow!("[meteora::positions] decode LbPair: {e}"))?;
+        out.push(PositionSummary {
+            position: PositionRef::new(Dex::Meteora, position_pk),
+            pool: lb_pair_pk,
+            lower: pos.lower_bin_id,
+            upper: pos.upper_bin_id,
+            liquidity: pos.liquidity_shares.iter().map(|s| *s as u128).sum(),
+            fees_owed: pos.fee_infos.iter().fold((0, 0), |(x, y), f| {
+                (x + f.fee_x_pending, y + f.fee_y_pending)
+            }),
+            in_range: (pos.lower_bin_id..=pos.upper_bin_id).contains(&lb_pair.active_id),
+            bundle_index: None,
+        });
+    }
+    Ok(out)
+}
+
+/// `position_pk` and its lb_pair, for [`crate::positions::watch`].
+pub(crate) async fn watched_accounts(rpc: &RpcClient, position_pk: &Pubkey) -> Result<(Pubkey, Pubkey)> {
+    let acc = rpc
+        .get_account(position_pk).await
+        .with_context(|| format!("[meteora::watch] fetch position {}", position_pk))?;
+    let pos: Position = Position::from_bytes(&acc.data)
+        .map_err(|e| anyhow!("[meteora::watch] decode Position {}: {e}", position_pk))?;
+    Ok((*position_pk, to_sdk_pubkey(&pos.lb_pair)))
+}
+
+/// State of a position, given its account data and its lb_pair's. The token amounts are
+/// left out: they are spread over the position's bin arrays.
+pub(crate) fn position_state(position_data: &[u8], pool_data: &[u8]) -> Result<PositionState> {
+    let pos: Position =
+        Position::from_bytes(position_data).map_err(|e| anyhow!("[meteora::watch] decode Position: {e}"))?;
+    let lb_pair: LbPair =
+        LbPair::from_bytes(pool_data).map_err(|e| anyhow!("[meteora::watch] decode LbPair: {e}"))?;
+    Ok(PositionState {
+        liquidity: pos.liquidity_shares.iter().map(|s| *s as u128).sum(),
+        fees_owed: pos.fee_infos.iter().fold((0, 0), |(x, y), f| {
+            (x + f.fee_x_pending, y + f.fee_y_pending)
+        }),
+        amounts: None,
+        in_range: (pos.lower_bin_id..=pos.upper_bin_id).contains(&lb_pair.active_id),
+    })
 }
 
-fn ensure_ata(
+/// Best quote for `amount_in` of `mint_in` across every lb_pair of the pair, in either
+/// X/Y order. Pairs whose quote fails are skipped.
+pub(crate) async fn compare_quote(
     rpc: &RpcClient,
-    ixs: &mut Vec<Instruction>,
-    owner: &Pubkey,
-    mint: &Pubkey,
-    token_program: &Pubkey,
-) -> Result<()> {
-    let ata = get_associated_token_address_with_program_id(owner, mint, token_program);
-    if rpc
-        .get_account_with_commitment(&ata, CommitmentConfig::processed())?
-        .value
-        .is_none()
-    {
-        ixs.push(create_associated_token_account(
-            owner, owner, mint, token_program,
-        ));
+    mint_in: &Pubkey,
+    mint_out: &Pubkey,
+    amount_in: u64,
+) -> Result<Option<(Pubkey, SwapQuote)>> {
+    let program_id = sdk_program_id();
+    let mut best: Option<(Pubkey, SwapQuote)> = None;
+    // token_x_mint and token_y_mint sit at offsets 88 and 120 of the LbPair account.
+    for (mint_x, mint_y, swap_for_y) in [(mint_in, mint_out, true), (mint_out, mint_in, false)] {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![
+                RpcFilterType::DataSize(LbPair::LEN as u64),
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(88, mint_x.to_bytes().to_vec())),
+                RpcFilterType::Memcmp(Memcmp::new_raw_bytes(120, mint_y.to_bytes().to_vec())),
+            ]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                ..RpcAccountInfoConfig::default()
+            },
+            ..RpcProgramAccountsConfig::default()
+        };
+        let accounts = rpc
+            .get_program_accounts_with_config(&program_id, config).await
+            .context("[meteora::compare] fetch lb_pairs for mint pair")?;
+        debug!(
+            "{} lb_pairs with X={} Y={}",
+            accounts.len(),
+            mint_x,
+            mint_y
+        );
+
+        let mut pairs = Vec::with_capacity(accounts.len());
+        for (lb_pair_pk, acc) in accounts {
+            let lb_pair: LbPair = LbPair::from_bytes(&acc.data)
+                .map_err(|e| anyhow!("[meteora::compare] decode LbPair {}: {e}", lb_pair_pk))?;
+            let bin_arrays = swap_bin_arrays(&program_id, &lb_pair_pk, &lb_pair, swap_for_y);
+            pairs.push((lb_pair_pk, lb_pair, bin_arrays));
+        }
+        // Every pair's bin arrays in one round trip.
+        let keys: Vec<Pubkey> =
+            pairs.iter().flat_map(|(_, _, arrays)| arrays.iter().map(|(_, key)| *key)).collect();
+        let fetched = Prefetched::fetch(rpc, &keys).await?;
+
+        for (lb_pair_pk, lb_pair, bin_arrays) in pairs {
+            let quote = match quote_swap_dlmm(&fetched, &lb_pair, &bin_arrays, amount_in, swap_for_y) {
+                Ok((q, _)) => q,
+                Err(e) => {
+                    warn!("skipping lb_pair {}: {:#}", lb_pair_pk, e);
+                    continue;
+                }
+            };
+            if best.as_ref().is_none_or(|(_, b)| quote.amount_out > b.amount_out) {
+                best = Some((lb_pair_pk, quote));
+            }
+        }
+    }
+    Ok(best)
+}
+
+/// Approximate an exact-in swap by draining bins from the active bin outward,
+/// using only the bin arrays the swap instruction is given (in traversal order).
+/// The fee uses the pair's current volatility and ignores its growth across bins.
+/// Also returns how many of `bin_arrays` the swap reaches. The arrays are read from `fetched`.
+fn quote_swap_dlmm(
+    fetched: &Prefetched,
+    lb_pair: &LbPair,
+    bin_arrays: &[(i64, Pubkey)],
+    amount_in: u64,
+    swap_for_y: bool,
+) -> Result<(SwapQuote, usize)> {
+    let mut arrays = std::collections::HashMap::new();
+    for (idx, key) in bin_arrays {
+        if let Some(acc) = fetched.get(key)? {
+            let arr = BinArray::from_bytes(&acc.data)
+                .map_err(|e| anyhow!("[meteora::swap] decode BinArray {}: {e}", key))?;
+            arrays.insert(*idx, arr);
+        }
+    }
+
+    // Fee rates are in 1e9 precision, capped at 10%.
+    let params = &lb_pair.parameters;
+    let bin_step = lb_pair.bin_step as u128;
+    let base_fee = params.base_factor as u128
+        * bin_step
+        * 10
+        * 10u128.pow(params.base_fee_power_factor as u32);
+    let vol = lb_pair.v_parameters.volatility_accumulator as u128 * bin_step;
+    let variable_fee =
+        (params.variable_fee_control as u128 * vol * vol).div_ceil(100_000_000_000);
+    let fee_rate = ((base_fee + variable_fee).min(100_000_000)) as f64 / 1e9;
+
+    let q64 = (1u128 << 64) as f64;
+    let mut bin_id = lb_pair.active_id;
+    let mut remaining = amount_in as f64;
+    let mut amount_out = 0f64;
+    let mut fee = 0f64;
+    let mut spot = None;
+    let last_idx = bin_arrays.last().map(|(i, _)| *i).context("no bin arrays")?;
+    let mut used = 1;
+    while remaining >= 1.0 {
+        let arr_idx = bin_array_index_for_bin_id(bin_id);
+        if (swap_for_y && arr_idx < last_idx) || (!swap_for_y && arr_idx > last_idx) {
+            bail!(
+                "swap would run past the bin arrays passed to the instruction (bin {}); \
+                 use a smaller --swap-amount-in",
+                bin_id
+            );
+        }
+        // Arrays between the ones passed are uninitialized, i.e. hold no liquidity.
+        if let Some(pos) = bin_arrays.iter().position(|(i, _)| *i == arr_idx) {
+            used = used.max(pos + 1);
+        }
+        if let Some(arr) = arrays.get(&arr_idx) {
+            let bin = &arr.bins[(bin_id as i64 - arr_idx * BINS_PER_ARRAY as i64) as usize];
+            let price = bin.price as f64 / q64;
+            let rate = if swap_for_y { price } else { 1.0 / price };
+            spot.get_or_insert(rate);
+            let available = if swap_for_y { bin.amount_y } else { bin.amount_x } as f64;
+            if available > 0.0 {
+                let net_in = remaining * (1.0 - fee_rate);
+                let out = (net_in * rate).min(available);
+                let used_net = out / rate;
+                let used = (used_net / (1.0 - fee_rate)).min(remaining);
+                amount_out += out;
+                fee += used - used_net;
+                remaining -= used;
+            }
+        }
+        bin_id += if swap_for_y { -1 } else { 1 };
+    }
+
+    let quote = SwapQuote {
+        amount_in,
+        amount_out: amount_out.floor() as u64,
+        fee: fee.ceil() as u64,
+        spot_out_per_in: spot.context("active bin array is not initialized")?,
+    };
+    Ok((quote, used))
+}
+
+#[instrument(name = "twap", skip_all)]
+async fn handle_twap(rpc: &RpcClient, pool_str: &str, window_secs: u64) -> Result<()> {
+    let lb_pair_pk =
+        Pubkey::from_str(pool_str).context("invalid --twap-pool (lb_pair address)")?;
+    let lb_acc = rpc
+        .get_account(&lb_pair_pk).await
+        .with_context(|| format!("[meteora::twap] fetch lb_pair {}", lb_pair_pk))?;
+    let lb_pair: LbPair = LbPair::from_bytes(&lb_acc.data)
+        .map_err(|e| anyhow!("[meteora::twap] decode LbPair: {e}"))?;
+
+    let oracle_pk = to_sdk_pubkey(&lb_pair.oracle);
+    let token_x_mint = to_sdk_pubkey(&lb_pair.token_x_mint);
+    let token_y_mint = to_sdk_pubkey(&lb_pair.token_y_mint);
+    let fetched = Prefetched::fetch(rpc, &[oracle_pk, token_x_mint, token_y_mint]).await?;
+    let oracle_acc = fetched.require(&oracle_pk, "[meteora::twap] oracle")?;
+    let samples = decode_oracle_observations(&oracle_acc.data)?;
+    let latest = samples
+        .iter()
+        .max_by_key(|o| o.last_updated_at)
+        .context("oracle has no observations yet")?;
+
+    let target = latest.last_updated_at - window_secs as i64;
+    let older = || samples.iter().filter(|o| o.last_updated_at < latest.last_updated_at);
+    let start = older()
+        .filter(|o| o.last_updated_at <= target)
+        .max_by_key(|o| o.last_updated_at)
+        .or_else(|| older().min_by_key(|o| o.last_updated_at))
+        .context("oracle holds a single observation; TWAP needs at least two (increase oracle length)")?;
+
+    let elapsed = latest.last_updated_at - start.last_updated_at;
+    if (elapsed as u64) < window_secs {
+        warn!(
+            "observations only cover {}s of the requested {}s window",
+            elapsed, window_secs
+        );
     }
+    let twap_bin = (latest.cumulative_active_bin_id - start.cumulative_active_bin_id) as f64
+        / elapsed as f64;
+
+    let decimals_x = fetched.mint_decimals(&token_x_mint)?;
+    let decimals_y = fetched.mint_decimals(&token_y_mint)?;
+    let base = 1.0 + lb_pair.bin_step as f64 / 10_000.0;
+    let decimals_adj = 10f64.powi(decimals_x as i32 - decimals_y as i32);
+    let twap_price = base.powf(twap_bin) * decimals_adj;
+    let spot_price = base.powi(lb_pair.active_id) * decimals_adj;
+
+    println!(
+        "Meteora TWAP for {} over {}s (Y per X): twap_bin={:.2} twap_price={:.10} | active_bin={} spot_price={:.10}",
+        lb_pair_pk, elapsed, twap_bin, twap_price, lb_pair.active_id, spot_price
+    );
     Ok(())
 }
 
-fn detect_token_program_for_mint(rpc: &RpcClient, mint: &Pubkey) -> Result<Pubkey> {
-    let acc = rpc.get_account(mint)?;
-    if acc.owner == spl_token_2022::ID {
-        Ok(spl_token_2022::ID)
-    } else {
-        Ok(spl_token::ID)
+struct OracleObservation {
+    cumulative_active_bin_id: i128,
+    last_updated_at: i64,
+}
+
+// Oracle account: fixed header followed by `length` observations of
+// { cumulative_active_bin_id: i128, created_at: i64, last_updated_at: i64 }.
+fn decode_oracle_observations(data: &[u8]) -> Result<Vec<OracleObservation>> {
+    const OBSERVATION_LEN: usize = 16 + 8 + 8;
+    let header = Oracle::from_bytes(&data[..Oracle::LEN.min(data.len())])
+        .map_err(|e| anyhow!("[meteora::twap] decode Oracle: {e}"))?;
+    let mut out = Vec::new();
+    for i in 0..header.length as usize {
+        let off = Oracle::LEN + i * OBSERVATION_LEN;
+        let Some(raw) = data.get(off..off + OBSERVATION_LEN) else {
+            bail!("oracle account truncated at observation {}", i);
+        };
+        let created_at = i64::from_le_bytes(raw[16..24].try_into()?);
+        if created_at == 0 {
+            continue;
+        }
+        out.push(OracleObservation {
+            cumulative_active_bin_id: i128::from_le_bytes(raw[0..16].try_into()?),
+            last_updated_at: i64::from_le_bytes(raw[24..32].try_into()?),
+        });
+    }
+    Ok(out)
+}
+
+/// The accounts every position instruction on bins `[lower, upper]` of an lb_pair shares.
+/// The builders below take these and use no RPC, so other programs can reuse them.
+#[derive(Clone, Copy, Debug)]
+pub struct PositionAccounts {
+    pub lb_pair: Pubkey,
+    /// Signer that pays and owns the position, and owner of `user_token_x`/`user_token_y`
+    /// that the amounts move through.
+    pub payer: Pubkey,
+    pub lower: i32,
+    pub upper: i32,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub token_x_program: Pubkey,
+    pub token_y_program: Pubkey,
+    pub user_token_x: Pubkey,
+    pub user_token_y: Pubkey,
+    pub bin_array_lower: Pubkey,
+    pub bin_array_upper: Pubkey,
+    pub event_authority: Pubkey,
+}
+
+impl PositionAccounts {
+    /// `payer`'s accounts for bins `[lower, upper]` of `lb_pair`; `token_programs` are the
+    /// programs that own its X and Y mints.
+    pub fn new(
+        lb_pair_pk: &Pubkey,
+        lb_pair: &LbPair,
+        payer: &Pubkey,
+        (lower, upper): (i32, i32),
+        (token_x_program, token_y_program): (Pubkey, Pubkey),
+    ) -> Self {
+        let program_id = sdk_program_id();
+        let token_x_mint = to_sdk_pubkey(&lb_pair.token_x_mint);
+        let token_y_mint = to_sdk_pubkey(&lb_pair.token_y_mint);
+        // If both ends fall into the same BinArray, nudge the upper index so that we pass
+        // two distinct accounts to the program (avoids AccountBorrowFailed on duplicate
+        // mutable accounts), while still using the original [lower, upper] for the position.
+        let bin_array_lower_index = bin_array_index_for_bin_id(lower);
+        let mut bin_array_upper_index = bin_array_index_for_bin_id(upper);
+        if bin_array_lower_index == bin_array_upper_index {
+            bin_array_upper_index = bin_array_lower_index + 1;
+        }
+        Self {
+            lb_pair: *lb_pair_pk,
+            payer: *payer,
+            lower,
+            upper,
+            token_x_mint,
+            token_y_mint,
+            reserve_x: to_sdk_pubkey(&lb_pair.reserve_x),
+            reserve_y: to_sdk_pubkey(&lb_pair.reserve_y),
+            token_x_program,
+            token_y_program,
+            user_token_x: get_associated_token_address_with_program_id(payer, &token_x_mint, &token_x_program),
+            user_token_y: get_associated_token_address_with_program_id(payer, &token_y_mint, &token_y_program),
+            bin_array_lower: derive_bin_array_address(&program_id, lb_pair_pk, bin_array_lower_index),
+            bin_array_upper: derive_bin_array_address(&program_id, lb_pair_pk, bin_array_upper_index),
+            event_authority: derive_event_authority(&program_id),
+        }
     }
 }
 
+/// InitializePosition creating the `position` account (a fresh keypair that co-signs) over
+/// `[lower, upper]`, owned by the payer. Spans wider than `MAX_POSITION_WIDTH` bins fail.
+pub fn build_open_position_ix(accounts: &PositionAccounts, position: &Pubkey) -> Instruction {
+    let ix = InitializePositionBuilder::new()
+        .payer(to_raw_pubkey(&accounts.payer))
+        .position(to_raw_pubkey(position))
+        .lb_pair(to_raw_pubkey(&accounts.lb_pair))
+        .owner(to_raw_pubkey(&accounts.payer))
+        .event_authority(to_raw_pubkey(&accounts.event_authority))
+        .program(met::LB_CLMM_ID)
+        .lower_bin_id(accounts.lower)
+        .width(accounts.upper - accounts.lower + 1)
+        .instruction();
+    to_sdk_instruction(ix)
+}
+
+/// AddLiquidity spreading `amount_x`/`amount_y` (either may be 0) evenly over the
+/// position's bins.
+pub fn build_add_liquidity_ix(
+    accounts: &PositionAccounts,
+    position: &Pubkey,
+    (amount_x, amount_y): (u64, u64),
+) -> Result<Instruction> {
+    let width = (accounts.upper - accounts.lower + 1) as usize;
+    let share = uniform_distribution(width, amount_x, amount_y)?;
+    let dists = (accounts.lower..=accounts.upper)
+        .map(|bin_id| BinLiquidityDistribution {
+            bin_id,
+            distribution_x: if amount_x > 0 { share } else { 0 },
+            distribution_y: if amount_y > 0 { share } else { 0 },
+        })
+        .collect();
+    let lp = LiquidityParameter {
+        amount_x,
+        amount_y,
+        bin_liquidity_dist: dists,
+    };
+    let ix = AddLiquidityBuilder::new()
+        .position(to_raw_pubkey(position))
+        .lb_pair(to_raw_pubkey(&accounts.lb_pair))
+        .bin_array_bitmap_extension(None)
+        .user_token_x(to_raw_pubkey(&accounts.user_token_x))
+        .user_token_y(to_raw_pubkey(&accounts.user_token_y))
+        .reserve_x(to_raw_pubkey(&accounts.reserve_x))
+        .reserve_y(to_raw_pubkey(&accounts.reserve_y))
+        .token_x_mint(to_raw_pubkey(&accounts.token_x_mint))
+        .token_y_mint(to_raw_pubkey(&accounts.token_y_mint))
+        .bin_array_lower(to_raw_pubkey(&accounts.bin_array_lower))
+        .bin_array_upper(to_raw_pubkey(&accounts.bin_array_upper))
+        .sender(to_raw_pubkey(&accounts.payer))
+        .token_x_program(to_raw_pubkey(&accounts.token_x_program))
+        .token_y_program(to_raw_pubkey(&accounts.token_y_program))
+        .event_authority(to_raw_pubkey(&accounts.event_authority))
+        .program(met::LB_CLMM_ID)
+        .liquidity_parameter(lp)
+        .instruction();
+    Ok(to_sdk_instruction(ix))
+}
+
+/// RemoveAllLiquidity withdrawing every bin of `position` to the payer's token accounts.
+pub fn build_remove_all_liquidity_ix(accounts: &PositionAccounts, position: &Pubkey) -> Instruction {
+    let ix = RemoveAllLiquidityBuilder::new()
+        .position(to_raw_pubkey(position))
+        .lb_pair(to_raw_pubkey(&accounts.lb_pair))
+        .bin_array_bitmap_extension(None)
+        .user_token_x(to_raw_pubkey(&accounts.user_token_x))
+        .user_token_y(to_raw_pubkey(&accounts.user_token_y))
+        .reserve_x(to_raw_pubkey(&accounts.reserve_x))
+        .reserve_y(to_raw_pubkey(&accounts.reserve_y))
+        .token_x_mint(to_raw_pubkey(&accounts.token_x_mint))
+        .token_y_mint(to_raw_pubkey(&accounts.token_y_mint))
+        .bin_array_lower(to_raw_pubkey(&accounts.bin_array_lower))
+        .bin_array_upper(to_raw_pubkey(&accounts.bin_array_upper))
+        .sender(to_raw_pubkey(&accounts.payer))
+        .token_x_program(to_raw_pubkey(&accounts.token_x_program))
+        .token_y_program(to_raw_pubkey(&accounts.token_y_program))
+        .event_authority(to_raw_pubkey(&accounts.event_authority))
+        .program(met::LB_CLMM_ID)
+        .instruction();
+    to_sdk_instruction(ix)
+}
+
+/// ClosePositionIfEmpty closing `position` and refunding its rent to the payer; a no-op
+/// while the position still holds liquidity.
+pub fn build_close_position_ix(accounts: &PositionAccounts, position: &Pubkey) -> Instruction {
+    use met::instructions::close_position_if_empty::ClosePositionIfEmptyBuilder;
+
+    let ix = ClosePositionIfEmptyBuilder::new()
+        .position(to_raw_pubkey(position))
+        .sender(to_raw_pubkey(&accounts.payer))
+        .rent_receiver(to_raw_pubkey(&accounts.payer))
+        .event_authority(to_raw_pubkey(&accounts.event_authority))
+        .program(met::LB_CLMM_ID)
+        .instruction();
+    to_sdk_instruction(ix)
+}
+
 fn to_sdk_instruction(ix: MetInstruction) -> Instruction {
     let MetInstruction {
         program_id,
@@ -471,6 +1209,7 @@ fn to_sdk_pubkey(pk: &RawPubkey) -> Pubkey {
     Pubkey::new_from_array(pk.to_bytes())
 }
 
+/// The DLMM program; it has the same address on mainnet and devnet.
 fn sdk_program_id() -> Pubkey {
     Pubkey::new_from_array(met::LB_CLMM_ID.to_bytes())
 }
@@ -481,6 +1220,7 @@ fn derive_event_authority(program_id: &Pubkey) -> Pubkey {
 }
 
 const BINS_PER_ARRAY: i32 = 70;
+const MAX_POSITION_WIDTH: i32 = 70;
 
 fn bin_array_index_for_bin_id(bin_id: i32) -> i64 {
     let per = BINS_PER_ARRAY as i64;
@@ -0,0 +1,117 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow, bail};
+use mpl_token_metadata::utils::meta_deser_unchecked;
+use serde::Deserialize;
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey};
+use tracing::warn;
+
+use crate::cli::{Dex, Opts};
+use crate::{orca, position_ref, raydium, rpc};
+
+/// How long the off-chain metadata JSON may take to load.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The fields of the off-chain metadata JSON that are printed.
+#[derive(Deserialize)]
+struct OffChainMetadata {
+    name: Option<String>,
+    image: Option<String>,
+    #[serde(default)]
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Deserialize)]
+struct Attribute {
+    trait_type: Option<String>,
+    value: serde_json::Value,
+}
+
+/// Print the Metaplex metadata of a position NFT, the off-chain JSON its URI points to
+/// and the position it stands for. When the JSON cannot be loaded only the URI is shown.
+pub async fn run(opts: &Opts, mint_str: &str) -> Result<()> {
+    let (venue, mint_str) = position_ref::split(mint_str)?;
+    if venue == Some(Dex::Meteora) {
+        bail!("Meteora positions are accounts, not NFTs; --nft-info takes ray: or orca: mints");
+    }
+    let mint = Pubkey::from_str(mint_str).context("invalid --nft-info mint")?;
+    let rpc = rpc::client(opts);
+
+    let (metadata_pda, _) = mpl_token_metadata::pda::find_metadata_account(&mint);
+    match rpc
+        .get_account_with_commitment(&metadata_pda, CommitmentConfig::confirmed())
+        .await?
+        .value
+    {
+        Some(acc) => {
+            let metadata = meta_deser_unchecked(&mut acc.data.as_slice())
+                .map_err(|e| anyhow!("decode metadata {}: {e}", metadata_pda))?;
+            println!(
+                "Metadata {}: name={} symbol={} uri={}",
+                metadata_pda,
+                metadata.data.name.trim_end_matches('\0'),
+                metadata.data.symbol.trim_end_matches('\0'),
+                metadata.data.uri.trim_end_matches('\0')
+            );
+            println!(
+                "  update_authority={} mutable={}",
+                metadata.update_authority, metadata.is_mutable
+            );
+            let uri = metadata.data.uri.trim_end_matches('\0');
+            if !uri.is_empty() {
+                match fetch_offchain(uri).await {
+                    Ok(offchain) => print_offchain(&offchain),
+                    Err(e) => warn!("off-chain metadata at {} not loaded: {:#}", uri, e),
+                }
+            }
+        }
+        None => println!("No Metaplex metadata for {}", mint),
+    }
+
+    let mut found = false;
+    let described = [
+        raydium::describe_position_nft(&rpc, &mint).await?,
+        orca::describe_position_nft(&rpc, &mint).await?,
+    ];
+    for line in described.into_iter().flatten() {
+        println!("{}", line);
+        found = true;
+    }
+    if !found {
+        println!("{} is not a Raydium or Orca position NFT", mint);
+    }
+    Ok(())
+}
+
+async fn fetch_offchain(uri: &str) -> Result<OffChainMetadata> {
+    let client = reqwest::Client::builder()
+        .timeout(METADATA_TIMEOUT)
+        .build()?;
+    Ok(client
+        .get(uri)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+fn print_offchain(offchain: &OffChainMetadata) {
+    println!(
+        "  off-chain: name={} image={}",
+        offchain.name.as_deref().unwrap_or("-"),
+        offchain.image.as_deref().unwrap_or("-")
+    );
+    for attribute in &offchain.attributes {
+        let value = match &attribute.value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        println!(
+            "    {}={}",
+            attribute.trait_type.as_deref().unwrap_or("?"),
+            value
+        );
+    }
+}
@@ -3,22 +3,29 @@ use std::str::FromStr;
 
 use anyhow::{anyhow, bail, Context, Result};
 use borsh::BorshDeserialize;
-use solana_client::rpc_client::RpcClient;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
 use solana_sdk::{
     commitment_config::CommitmentConfig,
     compute_budget::ComputeBudgetInstruction,
     instruction::Instruction,
+    program_pack::Pack,
     pubkey::Pubkey,
-    signature::{Keypair, SeedDerivable, Signer},
+    signature::{Keypair, Signer},
     system_program,
 };
+use spl_token::state::Account as SplTokenAccount;
 use spl_associated_token_account::{
-    get_associated_token_address_with_program_id, instruction::create_associated_token_account,
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
 };
 use orca_whirlpools_client as owc; // low-level (IDL-generated) client crate
 use owc::{
     Whirlpool,
     Position,
+    PositionBundle,
     SwapV2,
     SwapV2InstructionArgs,
     OpenPosition,
@@ -29,70 +36,107 @@ use owc::{
     DecreaseLiquidityV2InstructionArgs,
     CollectFeesV2,
     CollectFeesV2InstructionArgs,
+    CollectRewardV2,
+    CollectRewardV2InstructionArgs,
     ClosePosition,
+    UpdateFeesAndRewards,
+    TickArray,
     get_oracle_address,
     get_tick_array_address,
     get_position_address,
+    get_position_bundle_address,
+    get_bundled_position_address,
 };
 
 use orca_whirlpools_core as ocore; // math / quoting utilities
 use ocore::{get_tick_array_start_tick_index, MAX_SQRT_PRICE, MIN_SQRT_PRICE, TICK_ARRAY_SIZE};
+use ocore::{MAX_TICK_INDEX, MIN_TICK_INDEX};
+use ocore::{TickArrayFacade, TickArrays, TickFacade, WhirlpoolFacade};
+use tracing::{debug, info, instrument, warn};
 
-use crate::cli::Opts;
-use crate::tx::{build_unwrap_sol_ix, build_wrap_sol_ixs, simulate_and_send};
+use crate::amounts::{self, Prefetched};
+use crate::cli::{Dex, Mode, Opts};
+use crate::position_ref::PositionRef;
+use crate::positions::{PositionState, PositionSummary};
+use crate::quote::{align_range, enforce_max_price_impact, index_to_price, price_range_to_indices, slippage_min_out};
+use crate::quote::{Grid, SwapLeg, SwapQuote};
+use crate::rpc;
+use crate::signer;
+use crate::tx::{Flow, Report, TxConfig, build_unwrap_sol_ix, build_wrap_sol_ixs};
 
 const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+// Same address on mainnet and devnet.
+const WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
 
-pub fn run(opts: Opts) -> Result<()> {
-    let rpc_url = opts
-        .rpc
-        .clone()
-        .or_else(|| std::env::var("RPC_URL").ok())
-        .unwrap_or_else(|| "https://api.mainnet-beta.solana.com".to_string());
-    eprintln!("[debug][orca] rpc_url={}", rpc_url);
-    let rpc = RpcClient::new_with_commitment(rpc_url.clone(), CommitmentConfig::confirmed());
+pub(crate) async fn run(opts: Opts, config: &TxConfig) -> Result<Report> {
+    let rpc = rpc::client(&opts);
 
-    let key_b58 = std::env::var("PRIVATE_KEY_B58").context("Set PRIVATE_KEY_B58 in .env")?;
-    let payer = parse_phantom_base58_key(&key_b58)?;
-    let payer_pk = payer.pubkey();
+    if opts.twap_pool.is_some() {
+        bail!("TWAP is not available for Orca: whirlpool oracle accounts do not store price observations");
+    }
+    if opts.find_positions {
+        bail!("--find-positions is only available for Raydium");
+    }
+    if opts.resize_position.is_some() {
+        bail!("--resize-position is only available for Raydium");
+    }
+    if opts.seed_mint.is_some() {
+        bail!("--seed-mint is only available for Raydium");
+    }
+    if opts.optimize_range {
+        bail!("--optimize-range is only available for Raydium");
+    }
+
+    let wallet = signer::load_payer(&opts).await?;
+    let payer_pk = wallet.pubkey();
+    let mut flow = Flow::new(&rpc, config, wallet.as_ref());
 
     // Mainnet Orca Whirlpools program id (constant).
-    let whirlpool_program_id = Pubkey::from_str("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc")?;
-    eprintln!("[debug][orca] whirlpool_program_id={}", whirlpool_program_id);
+    let whirlpool_program_id = Pubkey::from_str(WHIRLPOOL_PROGRAM_ID)?;
+    debug!("whirlpool_program_id={}", whirlpool_program_id);
 
     let memo_program_id = Pubkey::from_str(MEMO_PROGRAM_ID)?;
 
     let mut ixs: Vec<Instruction> = vec![
         ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
-        ComputeBudgetInstruction::set_compute_unit_price(opts.cu_price),
+        ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
     ];
 
     if opts.wrap_sol > 0 {
-        eprintln!("[debug] wrapping {} lamports into WSOL", opts.wrap_sol);
-        ixs.extend(build_wrap_sol_ixs(&rpc, &payer_pk, opts.wrap_sol)?);
-    }
-
-    // Mirror the Raydium flow selection:
-    // - swap if --swap-pool is provided,
-    // - remove if --remove-position is provided,
-    // - else open if --pool is provided.
-    if let Some(pool_str) = &opts.swap_pool {
-        handle_swap(&rpc, &whirlpool_program_id, &payer, &payer_pk, pool_str, &opts, &mut ixs)?;
-    } else if let Some(pos_mint_str) = &opts.remove_position {
-        handle_remove_all(
+        debug!("wrapping {} lamports into WSOL", opts.wrap_sol);
+        ixs.extend(build_wrap_sol_ixs(&rpc, &payer_pk, opts.wrap_sol).await?);
+    }
+
+    match opts.mode()? {
+        Mode::Swap(pool_str) => handle_swap(&rpc, &payer_pk, &pool_str, &opts, &mut ixs).await?,
+        Mode::Collect(pos_mint_str) => handle_remove_all(
             &rpc,
             &whirlpool_program_id,
             &memo_program_id,
-            &payer,
             &payer_pk,
-            pos_mint_str,
+            &pos_mint_str,
             &opts,
             &mut ixs,
-        )?;
-    } else if opts.pool.is_some() {
-        handle_open(&rpc, &whirlpool_program_id, &payer, &payer_pk, opts, ixs)?;
-        // handle_open internally sends the transaction (like Raydium's version).
-        return Ok(());
+            true,
+        ).await?,
+        Mode::Remove(pos_mint_str) => handle_remove_all(
+            &rpc,
+            &whirlpool_program_id,
+            &memo_program_id,
+            &payer_pk,
+            &pos_mint_str,
+            &opts,
+            &mut ixs,
+            false,
+        ).await?,
+        Mode::Open(_) => {
+            handle_open(&mut flow, &whirlpool_program_id, &payer_pk, opts, ixs).await?;
+            // handle_open internally sends the transaction (like Raydium's version).
+            return Ok(flow.finish().await?);
+        }
+        // Rejected above.
+        Mode::Resize(_) | Mode::Seed(_) => unreachable!(),
+        Mode::Wrap => {}
     }
 
     if opts.unwrap_sol {
@@ -102,8 +146,9 @@ pub fn run(opts: Opts) -> Result<()> {
     }
 
     if ixs.len() > 2 {
-        let sig = simulate_and_send(&rpc, &payer, ixs, &[&payer])?;
-        println!("✅ Submitted. Tx: {}", sig);
+        if let Some(sig) = flow.send(ixs, &[]).await?.signature() {
+            println!("✅ Submitted. Tx: {}", sig);
+        }
     } else {
         // Only compute budget ixs were configured and nothing else to do
         if opts.unwrap_sol {
@@ -111,15 +156,14 @@ pub fn run(opts: Opts) -> Result<()> {
         }
     }
 
-    Ok(())
+    Ok(flow.finish().await?)
 }
 
 // ----------------------------- Swap -----------------------------
 
-fn handle_swap(
+#[instrument(name = "swap", skip_all)]
+async fn handle_swap(
     rpc: &RpcClient,
-    program_id: &Pubkey,
-    payer: &Keypair,
     payer_pk: &Pubkey,
     pool_str: &str,
     opts: &Opts,
@@ -128,68 +172,105 @@ fn handle_swap(
     if opts.swap_amount_in == 0 {
         bail!("--swap-amount-in must be > 0");
     }
-    let pool_id = Pubkey::from_str(pool_str).context("invalid swap pool id")?;
+    let leg = SwapLeg {
+        pool: Pubkey::from_str(pool_str).context("invalid swap pool id")?,
+        a_to_b: opts.swap_a_to_b,
+        amount_in: opts.swap_amount_in,
+        min_out: opts.swap_min_out,
+        sqrt_price_limit: opts.swap_sqrt_price_limit,
+        slippage_bps: opts.slippage_bps,
+    };
+    let quote = build_swap_ixs(rpc, payer_pk, &leg, ixs).await?;
+    enforce_max_price_impact(&quote, opts.max_price_impact_bps)?;
+    Ok(())
+}
+
+/// Token mints `(a, b)` of a whirlpool.
+pub(crate) async fn pool_mints(rpc: &RpcClient, pool_id: &Pubkey) -> Result<(Pubkey, Pubkey)> {
+    let whirl = fetch_whirlpool(rpc, pool_id).await?;
+    Ok((whirl.token_mint_a, whirl.token_mint_b))
+}
+
+/// Token mints `(a, b)` and price grid of a whirlpool.
+pub(crate) async fn pool_grid(rpc: &RpcClient, pool_id: &Pubkey) -> Result<(Pubkey, Pubkey, Grid)> {
+    let whirl = fetch_whirlpool(rpc, pool_id).await?;
+    Ok((whirl.token_mint_a, whirl.token_mint_b, Grid::clmm(whirl.tick_spacing)))
+}
+
+async fn fetch_whirlpool(rpc: &RpcClient, pool_id: &Pubkey) -> Result<Whirlpool> {
     let pool_acc = rpc
-        .get_account(&pool_id)
+        .get_account(pool_id).await
         .with_context(|| format!("[orca::swap] fetch whirlpool account {}", pool_id))?;
-    eprintln!(
-        "[debug][orca::swap] whirlpool={} owner={} data_len={}",
+    debug!(
+        "whirlpool={} owner={} data_len={}",
         pool_id,
         pool_acc.owner,
         pool_acc.data.len()
     );
-    if pool_acc.owner != *program_id {
+    if pool_acc.owner != Pubkey::from_str(WHIRLPOOL_PROGRAM_ID)? {
         bail!("pool account owner mismatch (expected Orca Whirlpool program)");
     }
 
-    let whirl: Whirlpool = decode_whirlpool(&pool_acc.data).with_context(|| {
+    decode_whirlpool(&pool_acc.data).with_context(|| {
         format!(
             "[orca::swap] decode whirlpool {} (data_len={})",
             pool_id,
             pool_acc.data.len()
         )
-    })?;
+    })
+}
+
+/// Append a SwapV2 for `leg` (plus any missing ATAs) to `ixs` and return its off-chain quote.
+pub async fn build_swap_ixs(
+    rpc: &RpcClient,
+    payer_pk: &Pubkey,
+    leg: &SwapLeg,
+    ixs: &mut Vec<Instruction>,
+) -> Result<SwapQuote> {
+    let pool_id = leg.pool;
+    let whirl = fetch_whirlpool(rpc, &pool_id).await?;
     let oracle = get_oracle_address(&pool_id)?.0;
 
     // Decide swap direction & which side is input/output.
-    let a_to_b = opts.swap_a_to_b;
+    let a_to_b = leg.a_to_b;
+    let tick_arrays = swap_tick_arrays(&pool_id, &whirl, a_to_b)?;
+    let [(tick_array0, _), (tick_array1, _), (tick_array2, _)] = tick_arrays;
+
+    // Mints, owner ATAs and tick arrays in one round trip.
+    let mut keys = amounts::token_keys(payer_pk, &[whirl.token_mint_a, whirl.token_mint_b]);
+    keys.extend([tick_array0, tick_array1, tick_array2]);
+    let fetched = Prefetched::fetch(rpc, &keys).await?;
 
     // Determine token programs per mint (handles Token-2022 automatically).
-    let token_program_a = detect_token_program_for_mint(rpc, &whirl.token_mint_a)?;
-    let token_program_b = detect_token_program_for_mint(rpc, &whirl.token_mint_b)?;
+    let programs = amounts::token_programs(&fetched, &[whirl.token_mint_a, whirl.token_mint_b])?;
+    let (token_program_a, token_program_b) = (programs[0], programs[1]);
 
     // Ensure owner ATAs exist for both mints
     let ata_a = get_associated_token_address_with_program_id(payer_pk, &whirl.token_mint_a, &token_program_a);
     let ata_b = get_associated_token_address_with_program_id(payer_pk, &whirl.token_mint_b, &token_program_b);
-    ensure_ata(rpc, ixs, payer_pk, &whirl.token_mint_a, &token_program_a)?;
-    ensure_ata(rpc, ixs, payer_pk, &whirl.token_mint_b, &token_program_b)?;
-
-    // Tick arrays: take current array and two neighbors in the swap direction (standard pattern).
-    let current_tick = whirl.tick_current_index;
-    let tick_spacing = whirl.tick_spacing;
-    let ts_i32 = tick_spacing as i32;
-    let arr_span = ts_i32 * TICK_ARRAY_SIZE as i32;
-    let start0 = get_tick_array_start_tick_index(current_tick, tick_spacing);
-    let (start1, start2) = if a_to_b {
-        (start0 - arr_span, start0 - 2 * arr_span)
-    } else {
-        (start0 + arr_span, start0 + 2 * arr_span)
-    };
+    amounts::ensure_atas(
+        &fetched,
+        ixs,
+        payer_pk,
+        &[(whirl.token_mint_a, token_program_a), (whirl.token_mint_b, token_program_b)],
+    )?;
 
-    let (tick_array0, _) = get_tick_array_address(&pool_id, start0)?;
-    let (tick_array1, _) = get_tick_array_address(&pool_id, start1)?;
-    let (tick_array2, _) = get_tick_array_address(&pool_id, start2)?;
+    let quote = quote_swap_whirlpool(&fetched, &whirl, tick_arrays, leg.amount_in, a_to_b)?;
+    let min_out = slippage_min_out(&quote, leg.min_out, leg.slippage_bps);
+    if leg.slippage_bps.is_some() {
+        debug!("min_out={} from quote", min_out);
+    }
 
     // Build SwapV2 instruction.
-    let sqrt_price_limit = if opts.swap_sqrt_price_limit == 0 {
+    let sqrt_price_limit = if leg.sqrt_price_limit == 0 {
         if a_to_b { MIN_SQRT_PRICE } else { MAX_SQRT_PRICE }
     } else {
-        opts.swap_sqrt_price_limit
+        leg.sqrt_price_limit
     };
 
     let args = SwapV2InstructionArgs {
-        amount: opts.swap_amount_in,
-        other_amount_threshold: opts.swap_min_out,
+        amount: leg.amount_in,
+        other_amount_threshold: min_out,
         sqrt_price_limit,
         amount_specified_is_input: true,
         a_to_b,
@@ -216,35 +297,332 @@ fn handle_swap(
     let swap_ix = swap_accounts.instruction(args);
     ixs.push(swap_ix);
 
-    Ok(())
+    Ok(quote)
 }
 
-// ----------------------------- Open Position -----------------------------
+/// Tick arrays for a swap: the current array and two neighbors in the swap direction (standard pattern).
+fn swap_tick_arrays(pool_id: &Pubkey, whirl: &Whirlpool, a_to_b: bool) -> Result<[(Pubkey, i32); 3]> {
+    let tick_spacing = whirl.tick_spacing;
+    let arr_span = tick_spacing as i32 * TICK_ARRAY_SIZE as i32;
+    let start0 = get_tick_array_start_tick_index(whirl.tick_current_index, tick_spacing);
+    let (start1, start2) = if a_to_b {
+        (start0 - arr_span, start0 - 2 * arr_span)
+    } else {
+        (start0 + arr_span, start0 + 2 * arr_span)
+    };
 
-fn handle_open(
+    let (tick_array0, _) = get_tick_array_address(pool_id, start0)?;
+    let (tick_array1, _) = get_tick_array_address(pool_id, start1)?;
+    let (tick_array2, _) = get_tick_array_address(pool_id, start2)?;
+    Ok([(tick_array0, start0), (tick_array1, start1), (tick_array2, start2)])
+}
+
+/// Whirlpool positions whose NFT mint is among `nft_mints`, and the positions in any
+/// position bundle among them.
+pub(crate) async fn wallet_positions(rpc: &RpcClient, nft_mints: &[Pubkey]) -> Result<Vec<PositionSummary>> {
+    let program_id = Pubkey::from_str(WHIRLPOOL_PROGRAM_ID)?;
+    let mut out = Vec::new();
+    // (position account, NFT mint, bundle index)
+    let mut found = Vec::new();
+    for mints in nft_mints.chunks(100) {
+        let mut pdas = Vec::with_capacity(mints.len());
+        for mint in mints {
+            pdas.push(get_position_address(mint)?.0);
+        }
+        let accounts = rpc
+            .get_multiple_accounts(&pdas).await
+            .context("[orca::positions] fetch positions")?;
+        let mut bundle_mints = Vec::new();
+        for ((mint, pda), acc) in mints.iter().zip(pdas).zip(accounts) {
+            match acc.filter(|a| a.owner == program_id) {
+                Some(acc) => found.push((pda, acc, *mint, None)),
+                None => bundle_mints.push(*mint),
+            }
+        }
+
+        let mut bundle_pdas = Vec::with_capacity(bundle_mints.len());
+        for mint in &bundle_mints {
+            bundle_pdas.push(get_position_bundle_address(mint)?.0);
+        }
+        let bundles = rpc
+            .get_multiple_accounts(&bundle_pdas).await
+            .context("[orca::positions] fetch position bundles")?;
+        for ((mint, bundle_pda), acc) in bundle_mints.iter().zip(&bundle_pdas).zip(bundles) {
+            let Some(acc) = acc.filter(|a| a.owner == program_id) else {
+                continue;
+            };
+            let bundle = PositionBundle::from_bytes(&acc.data)
+                .with_context(|| format!("[orca] decode position bundle {}", bundle_pda))?;
+            let indices: Vec<u8> = (0..=u8::MAX)
+                .filter(|i| bundle.position_bitmap[*i as usize / 8] & (1 << (i % 8)) != 0)
+                .collect();
+            for indices in indices.chunks(100) {
+                let mut pdas = Vec::with_capacity(indices.len());
+                for i in indices {
+                    pdas.push(get_bundled_position_address(bundle_pda, *i)?.0);
+                }
+                let accounts = rpc
+                    .get_multiple_accounts(&pdas).await
+                    .context("[orca::positions] fetch bundled positions")?;
+                for ((i, pda), acc) in indices.iter().zip(pdas).zip(accounts) {
+                    if let Some(acc) = acc.filter(|a| a.owner == program_id) {
+                        found.push((pda, acc, *mint, Some(*i)));
+                    }
+                }
+            }
+        }
+    }
+
+    let mut positions = Vec::with_capacity(found.len());
+    for (pda, acc, mint, bundle_index) in found {
+        let position = decode_position(&acc.data)
+            .with_context(|| format!("[orca::positions] decode position {}", pda))?;
+        positions.push((position, mint, bundle_index));
+    }
+    let pool_ids: Vec<Pubkey> = positions.iter().map(|(position, _, _)| position.whirlpool).collect();
+    let pools = Prefetched::fetch(rpc, &pool_ids).await?;
+    for (position, mint, bundle_index) in positions {
+        let whirl = decode_whirlpool(&pools.require(&position.whirlpool, "[orca::positions] whirlpool")?.data)?;
+        out.push(PositionSummary {
+            position: PositionRef::new(Dex::Orca, mint),
+            pool: position.whirlpool,
+            lower: position.tick_lower_index,
+            upper: position.tick_upper_index,
+            liquidity: position.liquidity,
+            fees_owed: (position.fee_owed_a, position.fee_owed_b),
+            in_range: (position.tick_lower_index..position.tick_upper_index)
+                .contains(&whirl.tick_current_index),
+            bundle_index,
+        });
+    }
+    Ok(out)
+}
+
+/// The position account behind `position_mint` and its whirlpool, for
+/// [`crate::positions::watch`]. Positions in a bundle are not covered.
+pub(crate) async fn watched_accounts(rpc: &RpcClient, position_mint: &Pubkey) -> Result<(Pubkey, Pubkey)> {
+    let (position_pda, _) = get_position_address(position_mint)?;
+    let acc = rpc
+        .get_account(&position_pda).await
+        .with_context(|| format!("[orca::watch] fetch position {}", position_pda))?;
+    let position = decode_position(&acc.data)?;
+    Ok((position_pda, position.whirlpool))
+}
+
+/// State of a position, given its account data and its whirlpool's.
+pub(crate) fn position_state(position_data: &[u8], pool_data: &[u8]) -> Result<PositionState> {
+    let position = decode_position(position_data)?;
+    let whirl = decode_whirlpool(pool_data)?;
+    let (lower, upper) = (position.tick_lower_index, position.tick_upper_index);
+    let amounts = ocore::try_get_token_estimates_from_liquidity(position.liquidity, whirl.sqrt_price, lower, upper, false)
+        .map_err(|e| anyhow!("[orca::watch] token estimates: {:?}", e))?;
+    Ok(PositionState {
+        liquidity: position.liquidity,
+        fees_owed: (position.fee_owed_a, position.fee_owed_b),
+        amounts: Some(amounts),
+        in_range: (lower..upper).contains(&whirl.tick_current_index),
+    })
+}
+
+/// Describe the whirlpool position (or position bundle) behind `mint`, if it is an Orca NFT.
+pub(crate) async fn describe_position_nft(rpc: &RpcClient, mint: &Pubkey) -> Result<Option<String>> {
+    let (position_pda, _) = get_position_address(mint)?;
+    if let Some(acc) = rpc
+        .get_account_with_commitment(&position_pda, CommitmentConfig::confirmed()).await?
+        .value
+    {
+        let position = decode_position(&acc.data)?;
+        return Ok(Some(format!(
+            "Orca whirlpool position {}: pool={} ticks=[{}, {}] liquidity={}",
+            position_pda,
+            position.whirlpool,
+            position.tick_lower_index,
+            position.tick_upper_index,
+            position.liquidity
+        )));
+    }
+
+    let (bundle_pda, _) = get_position_bundle_address(mint)?;
+    let Some(acc) = rpc
+        .get_account_with_commitment(&bundle_pda, CommitmentConfig::confirmed()).await?
+        .value
+    else {
+        return Ok(None);
+    };
+    let bundle = PositionBundle::from_bytes(&acc.data)
+        .with_context(|| format!("[orca] decode position bundle {}", bundle_pda))?;
+    let indices: Vec<u8> = (0..=u8::MAX)
+        .filter(|i| bundle.position_bitmap[*i as usize / 8] & (1 << (i % 8)) != 0)
+        .collect();
+    let mut lines = vec![format!(
+        "Orca position bundle {}: {} bundled positions",
+        bundle_pda,
+        indices.len()
+    )];
+    let mut bundled_pdas = Vec::with_capacity(indices.len());
+    for i in &indices {
+        bundled_pdas.push(get_bundled_position_address(&bundle_pda, *i)?.0);
+    }
+    let bundled = Prefetched::fetch(rpc, &bundled_pdas).await?;
+    for (i, bundled_pda) in indices.into_iter().zip(bundled_pdas) {
+        let acc = bundled.require(&bundled_pda, "[orca] bundled position")?;
+        let position = decode_position(&acc.data)?;
+        lines.push(format!(
+            "  #{} {}: pool={} ticks=[{}, {}] liquidity={}",
+            i,
+            bundled_pda,
+            position.whirlpool,
+            position.tick_lower_index,
+            position.tick_upper_index,
+            position.liquidity
+        ));
+    }
+    Ok(Some(lines.join("\n")))
+}
+
+/// Best quote for `amount_in` of `mint_in` across every whirlpool of the pair.
+/// Whirlpools whose quote fails are skipped.
+pub(crate) async fn compare_quote(
     rpc: &RpcClient,
+    mint_in: &Pubkey,
+    mint_out: &Pubkey,
+    amount_in: u64,
+) -> Result<Option<(Pubkey, SwapQuote)>> {
+    let program_id = Pubkey::from_str(WHIRLPOOL_PROGRAM_ID)?;
+    // Whirlpools store token_mint_a < token_mint_b at fixed offsets 101 and 181.
+    let a_to_b = mint_in.to_bytes() < mint_out.to_bytes();
+    let (mint_a, mint_b) = if a_to_b { (mint_in, mint_out) } else { (mint_out, mint_in) };
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(Whirlpool::LEN as u64),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(101, mint_a.to_bytes().to_vec())),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(181, mint_b.to_bytes().to_vec())),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc
+        .get_program_accounts_with_config(&program_id, config).await
+        .context("[orca::compare] fetch whirlpools for mint pair")?;
+    debug!("{} whirlpools for the pair", accounts.len());
+
+    let mut pools = Vec::new();
+    for (pool_id, acc) in accounts {
+        let whirl = decode_whirlpool(&acc.data)?;
+        if whirl.liquidity == 0 {
+            continue;
+        }
+        match swap_tick_arrays(&pool_id, &whirl, a_to_b) {
+            Ok(arrays) => pools.push((pool_id, whirl, arrays)),
+            Err(e) => warn!("skipping whirlpool {}: {:#}", pool_id, e),
+        }
+    }
+    // Every pool's tick arrays in one round trip.
+    let keys: Vec<Pubkey> = pools
+        .iter()
+        .flat_map(|(_, _, arrays)| arrays.iter().map(|(key, _)| *key))
+        .collect();
+    let fetched = Prefetched::fetch(rpc, &keys).await?;
+
+    let mut best: Option<(Pubkey, SwapQuote)> = None;
+    for (pool_id, whirl, arrays) in pools {
+        let quote = match quote_swap_whirlpool(&fetched, &whirl, arrays, amount_in, a_to_b) {
+            Ok(q) => q,
+            Err(e) => {
+                warn!("skipping whirlpool {}: {:#}", pool_id, e);
+                continue;
+            }
+        };
+        if best.as_ref().is_none_or(|(_, b)| quote.amount_out > b.amount_out) {
+            best = Some((pool_id, quote));
+        }
+    }
+    Ok(best)
+}
+
+/// Quote an exact-in swap over the same three tick arrays SwapV2 is given, read from
+/// `fetched`. Tick arrays that do not exist yet are treated as empty.
+fn quote_swap_whirlpool(
+    fetched: &Prefetched,
+    whirl: &Whirlpool,
+    tick_arrays: [(Pubkey, i32); 3],
+    amount_in: u64,
+    a_to_b: bool,
+) -> Result<SwapQuote> {
+    let mut facades = Vec::with_capacity(3);
+    for (key, start) in &tick_arrays {
+        let facade = match fetched.get(key)? {
+            Some(acc) => TickArrayFacade::from(
+                TickArray::from_bytes(&acc.data)
+                    .with_context(|| format!("[orca::swap] decode tick array {}", key))?,
+            ),
+            None => TickArrayFacade {
+                start_tick_index: *start,
+                ticks: [TickFacade::default(); TICK_ARRAY_SIZE],
+            },
+        };
+        facades.push(facade);
+    }
+    let [ta0, ta1, ta2]: [TickArrayFacade; 3] = facades.try_into().expect("three tick arrays");
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs();
+    let quote = ocore::swap_quote_by_input_token(
+        amount_in,
+        a_to_b,
+        0,
+        WhirlpoolFacade::from(whirl.clone()),
+        None,
+        TickArrays::Three(ta0, ta1, ta2),
+        now,
+        None,
+        None,
+    )
+    .map_err(|e| anyhow!("swap quote failed: {:?}", e))?;
+
+    let price = ocore::sqrt_price_to_price(whirl.sqrt_price, 0, 0);
+    Ok(SwapQuote {
+        amount_in: quote.token_in,
+        amount_out: quote.token_est_out,
+        fee: quote.trade_fee,
+        spot_out_per_in: if a_to_b { price } else { 1.0 / price },
+    })
+}
+
+// ----------------------------- Open Position -----------------------------
+
+#[instrument(name = "open", skip_all)]
+async fn handle_open(
+    flow: &mut Flow<'_>,
     program_id: &Pubkey,
-    payer: &Keypair,
     payer_pk: &Pubkey,
     opts: Opts,
     mut ixs: Vec<Instruction>,
 ) -> Result<()> {
+    let rpc = flow.rpc();
     let pool_id = Pubkey::from_str(opts.pool.as_ref().context("missing --pool")?)
         .context("invalid pool id")?;
-    let lower = *opts.lower.as_ref().context("missing --lower")?;
-    let upper = *opts.upper.as_ref().context("missing --upper")?;
-    if upper <= lower {
-        bail!("upper tick must be > lower tick");
+    let price_range = opts.price_range()?;
+    if price_range.is_none() {
+        let lower = opts.lower.context("missing --lower (or --price-min/--price-max)")?;
+        let upper = opts.upper.context("missing --upper")?;
+        if upper <= lower {
+            bail!("upper tick must be > lower tick");
+        }
     }
     if opts.amount0 == 0 && opts.amount1 == 0 {
         bail!("specify --amount0 and/or --amount1");
     }
 
     let pool_acc = rpc
-        .get_account(&pool_id)
+        .get_account(&pool_id).await
         .with_context(|| format!("[orca::open] fetch whirlpool {}", pool_id))?;
-    eprintln!(
-        "[debug][orca::open] whirlpool={} owner={} data_len={}",
+    debug!(
+        "whirlpool={} owner={} data_len={}",
         pool_id,
         pool_acc.owner,
         pool_acc.data.len()
@@ -260,20 +638,53 @@ fn handle_open(
         )
     })?;
 
+    // Both mints and the payer's ATAs for them in one round trip.
+    let mints = [whirl.token_mint_a, whirl.token_mint_b];
+    let fetched = Prefetched::fetch(rpc, &amounts::token_keys(payer_pk, &mints)).await?;
+
+    let (lower, upper) = match price_range {
+        Some(range) => {
+            // Whirlpools do not store decimals; read them from the mints.
+            let decimals_a = fetched.mint_decimals(&whirl.token_mint_a)?;
+            let decimals_b = fetched.mint_decimals(&whirl.token_mint_b)?;
+            let current = index_to_price(whirl.tick_current_index, decimals_a, decimals_b, 1.0001);
+            let range = range.resolve(current, None)?;
+            let (l, u) =
+                price_range_to_indices(range, decimals_a, decimals_b, 1.0001, whirl.tick_spacing as i32);
+            info!(
+                "price range [{}, {}] -> ticks [{}, {}]",
+                range.0, range.1, l, u
+            );
+            (l, u)
+        }
+        None => (opts.lower.unwrap_or_default(), opts.upper.unwrap_or_default()),
+    };
+    // OpenPosition fails on-chain for ticks off the spacing grid; catch it before paying fees.
+    let (lower, upper) = align_range(
+        lower,
+        upper,
+        whirl.tick_spacing as i32,
+        (MIN_TICK_INDEX, MAX_TICK_INDEX),
+        opts.snap,
+        1.0001,
+    )?;
+
     // Ensure owner ATAs for both mints
-    let token_program_a = detect_token_program_for_mint(rpc, &whirl.token_mint_a)?;
-    let token_program_b = detect_token_program_for_mint(rpc, &whirl.token_mint_b)?;
-    let ata_a = get_associated_token_address_with_program_id(payer_pk, &whirl.token_mint_a, &token_program_a);
-    let ata_b = get_associated_token_address_with_program_id(payer_pk, &whirl.token_mint_b, &token_program_b);
-    ensure_ata(rpc, &mut ixs, payer_pk, &whirl.token_mint_a, &token_program_a)?;
-    ensure_ata(rpc, &mut ixs, payer_pk, &whirl.token_mint_b, &token_program_b)?;
+    let programs = amounts::token_programs(&fetched, &mints)?;
+    let (token_program_a, token_program_b) = (programs[0], programs[1]);
+    amounts::ensure_atas(
+        &fetched,
+        &mut ixs,
+        payer_pk,
+        &[(whirl.token_mint_a, token_program_a), (whirl.token_mint_b, token_program_b)],
+    )?;
+    let accounts =
+        PositionAccounts::new(&pool_id, &whirl, payer_pk, (lower, upper), (token_program_a, token_program_b))?;
 
-    // Derive tick-array PDAs for the provided ticks
-    let tick_spacing = whirl.tick_spacing;
-    let lower_start = get_tick_array_start_tick_index(lower, tick_spacing);
-    let upper_start = get_tick_array_start_tick_index(upper, tick_spacing);
-    let (tick_array_lower, _) = get_tick_array_address(&pool_id, lower_start)?;
-    let (tick_array_upper, _) = get_tick_array_address(&pool_id, upper_start)?;
+    let position_owner = match &opts.position_owner {
+        Some(s) => Pubkey::from_str(s).context("invalid --position-owner")?,
+        None => *payer_pk,
+    };
 
     // Create a fresh position NFT mint & ATA
     let position_mint = Keypair::new();
@@ -285,138 +696,121 @@ fn handle_open(
     );
 
     // OpenPosition (no metadata to keep dependencies light)
-    let open_ix = OpenPosition {
-        funder: *payer_pk,
-        owner: *payer_pk,
-        position: position_pda,
-        position_mint: position_mint.pubkey(),
-        position_token_account,
-        whirlpool: pool_id,
-        token_program: spl_token::ID,
-        system_program: system_program::id(),
-        rent: solana_sdk::sysvar::rent::id(),
-        associated_token_program: spl_associated_token_account::id(),
-    }
-    .instruction(OpenPositionInstructionArgs {
-        position_bump,
-        tick_lower_index: lower,
-        tick_upper_index: upper,
-    });
-    ixs.push(open_ix);
+    ixs.push(build_open_position_ix(&accounts, &position_mint.pubkey(), (position_pda, position_bump)));
 
     // Quote liquidity for the provided token amounts and current sqrt price.
-    let sqrt_price_x64 = whirl.sqrt_price; // u128
-    let slippage_bps: u16 = 0;
-    let liq_quote = if opts.amount0 > 0 && opts.amount1 == 0 {
-        ocore::increase_liquidity_quote_a(
-            opts.amount0,
-            slippage_bps,
-            sqrt_price_x64,
-            lower,
-            upper,
-            None,
-            None,
-        )
-        .map_err(|e| anyhow!("liquidity quote failed (token0 only): {:?}", e))?
-    } else if opts.amount1 > 0 && opts.amount0 == 0 {
-        ocore::increase_liquidity_quote_b(
-            opts.amount1,
-            slippage_bps,
-            sqrt_price_x64,
-            lower,
-            upper,
-            None,
-            None,
-        )
-        .map_err(|e| anyhow!("liquidity quote failed (token1 only): {:?}", e))?
-    } else {
-        // Both token0 and token1 provided: try token0-driven quote first, then token1-driven.
-        let quote_a = ocore::increase_liquidity_quote_a(
-            opts.amount0,
-            slippage_bps,
-            sqrt_price_x64,
-            lower,
-            upper,
-            None,
-            None,
-        )
-        .map_err(|e| anyhow!("liquidity quote failed (token0): {:?}", e))?;
-        if quote_a.token_max_b <= opts.amount1 {
-            quote_a
-        } else {
-            let quote_b = ocore::increase_liquidity_quote_b(
-                opts.amount1,
-                slippage_bps,
-                sqrt_price_x64,
-                lower,
-                upper,
-                None,
-                None,
-            )
-            .map_err(|e| anyhow!("liquidity quote failed (token1): {:?}", e))?;
-            if quote_b.token_max_a <= opts.amount0 {
-                quote_b
-            } else {
-                bail!(
-                    "provided token amounts are too low for both sides at current price (need up to token_max_a={}, token_max_b={})",
-                    quote_b.token_max_a,
-                    quote_a.token_max_b,
-                );
-            }
-        }
-    };
+    let liq_quote = liquidity_for_amounts(&whirl, (lower, upper), opts.amount0, opts.amount1)?;
+    ixs.push(build_increase_liquidity_ix(
+        &accounts,
+        (position_pda, position_token_account),
+        &Pubkey::from_str(MEMO_PROGRAM_ID)?,
+        liq_quote.liquidity_delta,
+        (liq_quote.token_max_a, liq_quote.token_max_b),
+    ));
 
-    // IncreaseLiquidityV2
-    let inc_ix = IncreaseLiquidityV2 {
-        whirlpool: pool_id,
-        token_program_a: token_program_a,
-        token_program_b: token_program_b,
-        memo_program: Pubkey::from_str(MEMO_PROGRAM_ID)?,
-        position_authority: *payer_pk,
-        position: position_pda,
-        position_token_account,
-        token_mint_a: whirl.token_mint_a,
-        token_mint_b: whirl.token_mint_b,
-        token_owner_account_a: ata_a,
-        token_owner_account_b: ata_b,
-        token_vault_a: whirl.token_vault_a,
-        token_vault_b: whirl.token_vault_b,
-        tick_array_lower,
-        tick_array_upper,
+    // IncreaseLiquidity must be signed by the NFT holder, so the payer opens and funds the
+    // position first and then hands the NFT over, closing its own emptied NFT account.
+    if position_owner != *payer_pk {
+        let owner_token_account = get_associated_token_address_with_program_id(
+            &position_owner,
+            &position_mint.pubkey(),
+            &spl_token::ID,
+        );
+        ixs.push(create_associated_token_account_idempotent(
+            payer_pk,
+            &position_owner,
+            &position_mint.pubkey(),
+            &spl_token::ID,
+        ));
+        ixs.push(spl_token::instruction::transfer(
+            &spl_token::ID,
+            &position_token_account,
+            &owner_token_account,
+            payer_pk,
+            &[],
+            1,
+        )?);
+        ixs.push(spl_token::instruction::close_account(
+            &spl_token::ID,
+            &position_token_account,
+            payer_pk,
+            payer_pk,
+            &[],
+        )?);
     }
-    .instruction(IncreaseLiquidityV2InstructionArgs {
-        liquidity_amount: liq_quote.liquidity_delta,
-        token_max_a: liq_quote.token_max_a,
-        token_max_b: liq_quote.token_max_b,
-        remaining_accounts_info: None,
-    });
-    ixs.push(inc_ix);
 
-    // Send the tx that does: (compute budget) + create ATAs + open + increase
-    let sig = simulate_and_send(rpc, payer, ixs, &[payer, &position_mint])?;
-    println!("✅ Opened Orca position. Position mint: {}. Tx: {}", position_mint.pubkey(), sig);
+    // Send the tx that does: (compute budget) + create ATAs + open + increase (+ NFT handover)
+    if let Some(sig) = flow.send(ixs, &[&position_mint]).await?.signature() {
+        println!("✅ Opened Orca position. Position mint: {}. Tx: {}", position_mint.pubkey(), sig);
+        if position_owner != *payer_pk {
+            println!("✅ Position NFT transferred to {}", position_owner);
+        }
+    }
     Ok(())
 }
 
 // ----------------------------- Remove / Close Position -----------------------------
 
-fn handle_remove_all(
+/// Queue the `collect`/`remove` that `opts` asks for onto `ixs` without sending, for
+/// `--positions-file` rows that share a transaction.
+pub(crate) async fn removal_ixs(
+    rpc: &RpcClient,
+    payer_pk: &Pubkey,
+    opts: &Opts,
+    ixs: &mut Vec<Instruction>,
+) -> Result<()> {
+    let (position, collect_only) = match opts.mode()? {
+        Mode::Collect(position) => (position, true),
+        Mode::Remove(position) => (position, false),
+        _ => bail!("only collect and remove can share a transaction"),
+    };
+    handle_remove_all(
+        rpc,
+        &Pubkey::from_str(WHIRLPOOL_PROGRAM_ID)?,
+        &Pubkey::from_str(MEMO_PROGRAM_ID)?,
+        payer_pk,
+        &position,
+        opts,
+        ixs,
+        collect_only,
+    ).await
+}
+
+#[instrument(name = "remove", skip_all)]
+async fn handle_remove_all(
     rpc: &RpcClient,
     program_id: &Pubkey,
     memo_program_id: &Pubkey,
-    payer: &Keypair,
     payer_pk: &Pubkey,
     pos_mint_str: &str,
-    _opts: &Opts,
+    opts: &Opts,
     ixs: &mut Vec<Instruction>,
+    collect_only: bool,
 ) -> Result<()> {
     let position_mint = Pubkey::from_str(pos_mint_str).context("invalid position NFT mint")?;
     let (position_pda, _) = get_position_address(&position_mint)?;
-    let pos_acc = rpc
-        .get_account(&position_pda)
-        .with_context(|| format!("[orca::remove] fetch position account {}", position_pda))?;
-    eprintln!(
-        "[debug][orca::remove] position_pda={} data_len={}",
+    if opts.remove_liquidity.is_some() || opts.remove_percent.is_some() {
+        bail!("partial removal (--remove-liquidity/--remove-percent) is only supported on Raydium");
+    }
+
+    // The NFT may sit in another wallet that delegated it to the signer.
+    let nft_owner = match &opts.nft_owner {
+        Some(s) => Pubkey::from_str(s).context("invalid --nft-owner")?,
+        None => *payer_pk,
+    };
+    let position_token_account =
+        get_associated_token_address_with_program_id(&nft_owner, &position_mint, &spl_token::ID);
+
+    // The position and the NFT account, then the pool, then the token accounts the
+    // removal pays into: each read needs the one before it.
+    let position_accounts = Prefetched::fetch(rpc, &[position_pda, position_token_account]).await?;
+    if nft_owner != *payer_pk {
+        verify_position_delegate(&position_accounts, &position_token_account, payer_pk)?;
+    }
+    let pos_acc = position_accounts
+        .require(&position_pda, "[orca::remove] position account")?;
+    debug!(
+        "position_pda={} data_len={}",
         position_pda,
         pos_acc.data.len()
     );
@@ -430,12 +824,21 @@ fn handle_remove_all(
         )
     })?;
 
+    if collect_only
+        && position.liquidity == 0
+        && position.fee_owed_a == 0
+        && position.fee_owed_b == 0
+        && position.reward_infos.iter().all(|r| r.amount_owed == 0)
+    {
+        bail!("position has no liquidity and nothing owed — nothing to collect");
+    }
+
     let pool_id = position.whirlpool;
     let pool_acc = rpc
-        .get_account(&pool_id)
+        .get_account(&pool_id).await
         .with_context(|| format!("[orca::remove] fetch whirlpool {}", pool_id))?;
-    eprintln!(
-        "[debug][orca::remove] whirlpool={} owner={} data_len={}",
+    debug!(
+        "whirlpool={} owner={} data_len={}",
         pool_id,
         pool_acc.owner,
         pool_acc.data.len()
@@ -451,69 +854,95 @@ fn handle_remove_all(
         )
     })?;
 
-    let token_program_a = detect_token_program_for_mint(rpc, &whirl.token_mint_a)?;
-    let token_program_b = detect_token_program_for_mint(rpc, &whirl.token_mint_b)?;
+    let has_liquidity = position.liquidity > 0;
+    let rewards: Vec<_> = whirl
+        .reward_infos
+        .iter()
+        .enumerate()
+        .filter(|(i, reward)| {
+            reward.mint != Pubkey::default()
+                && (has_liquidity || position.reward_infos[*i].amount_owed > 0)
+        })
+        .collect();
+    let reward_mints: Vec<Pubkey> = rewards.iter().map(|(_, reward)| reward.mint).collect();
+    let mut mints = vec![whirl.token_mint_a, whirl.token_mint_b];
+    mints.extend(&reward_mints);
+    let token_accounts = Prefetched::fetch(rpc, &amounts::token_keys(payer_pk, &mints)).await?;
 
-    let ata_a = get_associated_token_address_with_program_id(payer_pk, &whirl.token_mint_a, &token_program_a);
-    let ata_b = get_associated_token_address_with_program_id(payer_pk, &whirl.token_mint_b, &token_program_b);
-    ensure_ata(rpc, ixs, payer_pk, &whirl.token_mint_a, &token_program_a)?;
-    ensure_ata(rpc, ixs, payer_pk, &whirl.token_mint_b, &token_program_b)?;
+    let programs = amounts::token_programs(&token_accounts, &[whirl.token_mint_a, whirl.token_mint_b])?;
+    let (token_program_a, token_program_b) = (programs[0], programs[1]);
+    amounts::ensure_atas(
+        &token_accounts,
+        ixs,
+        payer_pk,
+        &[(whirl.token_mint_a, token_program_a), (whirl.token_mint_b, token_program_b)],
+    )?;
+    let accounts = PositionAccounts::new(
+        &pool_id,
+        &whirl,
+        payer_pk,
+        (position.tick_lower_index, position.tick_upper_index),
+        (token_program_a, token_program_b),
+    )?;
 
-    let tick_spacing = whirl.tick_spacing;
-    let lower_start = get_tick_array_start_tick_index(position.tick_lower_index, tick_spacing);
-    let upper_start = get_tick_array_start_tick_index(position.tick_upper_index, tick_spacing);
-    let (tick_array_lower, _) = get_tick_array_address(&pool_id, lower_start)?;
-    let (tick_array_upper, _) = get_tick_array_address(&pool_id, upper_start)?;
-
-    // If there is any liquidity, remove it.
-    if position.liquidity > 0 {
-        let dec_ix = DecreaseLiquidityV2 {
+    // In collect-only mode, refresh the owed amounts instead of touching liquidity.
+    if position.liquidity > 0 && collect_only {
+        let update_ix = UpdateFeesAndRewards {
             whirlpool: pool_id,
-            token_program_a,
-            token_program_b,
-            memo_program: *memo_program_id,
-            position_authority: *payer_pk,
             position: position_pda,
-            position_token_account: get_associated_token_address_with_program_id(
-                payer_pk,
-                &position_mint,
-                &spl_token::ID,
-            ),
-            token_mint_a: whirl.token_mint_a,
-            token_mint_b: whirl.token_mint_b,
-            token_owner_account_a: ata_a,
-            token_owner_account_b: ata_b,
-            token_vault_a: whirl.token_vault_a,
-            token_vault_b: whirl.token_vault_b,
-            tick_array_lower,
-            tick_array_upper,
+            tick_array_lower: accounts.tick_array_lower,
+            tick_array_upper: accounts.tick_array_upper,
         }
-        .instruction(DecreaseLiquidityV2InstructionArgs {
-            liquidity_amount: position.liquidity,
-            token_min_a: 0,
-            token_min_b: 0,
-            remaining_accounts_info: None,
-        });
-        ixs.push(dec_ix);
+        .instruction();
+        ixs.push(update_ix);
+    } else if position.liquidity > 0 {
+        // If there is any liquidity, remove it.
+        let (token_min_a, token_min_b) = match opts.slippage_bps {
+            Some(bps) => {
+                let q = ocore::decrease_liquidity_quote(
+                    position.liquidity,
+                    bps as u16,
+                    whirl.sqrt_price,
+                    position.tick_lower_index,
+                    position.tick_upper_index,
+                    None,
+                    None,
+                )
+                .map_err(|e| anyhow!("[orca::remove] decrease_liquidity_quote: {:?}", e))?;
+                debug!(
+                    "expected out a={} b={}; min with {} bps slippage: {} / {}",
+                    q.token_est_a, q.token_est_b, bps, q.token_min_a, q.token_min_b
+                );
+                (opts.min_out0.max(q.token_min_a), opts.min_out1.max(q.token_min_b))
+            }
+            None => (opts.min_out0, opts.min_out1),
+        };
+        ixs.push(build_decrease_liquidity_ix(
+            &accounts,
+            (position_pda, position_token_account),
+            memo_program_id,
+            position.liquidity,
+            (token_min_a, token_min_b),
+        ));
+    }
 
-        // Then collect any fees owed to the position into owner ATAs.
+    // Decreasing liquidity settles fees and rewards into the position, so collect whenever
+    // there was liquidity; an empty position can still carry fee_owed/amount_owed from an
+    // earlier partial removal, and ClosePosition fails unless both are zero.
+    if has_liquidity || position.fee_owed_a > 0 || position.fee_owed_b > 0 {
         let collect_ix = CollectFeesV2 {
             whirlpool: pool_id,
             position_authority: *payer_pk,
             position: position_pda,
-            position_token_account: get_associated_token_address_with_program_id(
-                payer_pk,
-                &position_mint,
-                &spl_token::ID,
-            ),
-            token_mint_a: whirl.token_mint_a,
-            token_mint_b: whirl.token_mint_b,
-            token_owner_account_a: ata_a,
-            token_vault_a: whirl.token_vault_a,
-            token_owner_account_b: ata_b,
-            token_vault_b: whirl.token_vault_b,
-            token_program_a,
-            token_program_b,
+            position_token_account,
+            token_mint_a: accounts.mint_a,
+            token_mint_b: accounts.mint_b,
+            token_owner_account_a: accounts.ata_a,
+            token_vault_a: accounts.vault_a,
+            token_owner_account_b: accounts.ata_b,
+            token_vault_b: accounts.vault_b,
+            token_program_a: accounts.token_program_a,
+            token_program_b: accounts.token_program_b,
             memo_program: *memo_program_id,
         }
         .instruction(CollectFeesV2InstructionArgs {
@@ -522,69 +951,292 @@ fn handle_remove_all(
         ixs.push(collect_ix);
     }
 
-    // Finally, close the position and burn the NFT from the owner's token account.
-    let close_ix = ClosePosition {
-        position_authority: *payer_pk,
-        receiver: *payer_pk,
-        position: position_pda,
-        position_mint,
-        position_token_account: get_associated_token_address_with_program_id(payer_pk, &position_mint, &spl_token::ID),
-        token_program: spl_token::ID,
+    let reward_programs = amounts::token_programs(&token_accounts, &reward_mints)?;
+    let reward_accounts = amounts::ensure_atas(
+        &token_accounts,
+        ixs,
+        payer_pk,
+        &reward_mints.iter().copied().zip(reward_programs.iter().copied()).collect::<Vec<_>>(),
+    )?;
+    for (((i, reward), reward_token_program), (reward_owner_account, _)) in
+        rewards.into_iter().zip(reward_programs).zip(reward_accounts)
+    {
+        let owed = position.reward_infos[i].amount_owed;
+        debug!(
+            "collecting reward #{} mint={} owed={}",
+            i, reward.mint, owed
+        );
+        let reward_ix = CollectRewardV2 {
+            whirlpool: pool_id,
+            position_authority: *payer_pk,
+            position: position_pda,
+            position_token_account,
+            reward_owner_account,
+            reward_mint: reward.mint,
+            reward_vault: reward.vault,
+            reward_token_program,
+            memo_program: *memo_program_id,
+        }
+        .instruction(CollectRewardV2InstructionArgs {
+            reward_index: i as u8,
+            remaining_accounts_info: None,
+        });
+        ixs.push(reward_ix);
     }
-    .instruction();
-    ixs.push(close_ix);
+
+    // Without --close the emptied position stays open, as on the other DEXes.
+    if collect_only || !opts.close {
+        return Ok(());
+    }
+
+    // A delegate cannot close the owner's token account, so leave the empty position in place.
+    if nft_owner != *payer_pk {
+        warn!(
+            "signer is a delegate of {}; skipping ClosePosition (owner must close)",
+            nft_owner
+        );
+        return Ok(());
+    }
+
+    // Finally, close the position and burn the NFT from the owner's token account.
+    ixs.push(build_close_position_ix(&accounts, &position_mint, (position_pda, position_token_account)));
 
     Ok(())
 }
 
-// ----------------------------- Helpers -----------------------------
+// ----------------------------- Instruction builders -----------------------------
 
-fn parse_phantom_base58_key(s: &str) -> Result<Keypair> {
-    let bytes = bs58::decode(s.trim())
-        .into_vec()
-        .context("Invalid base58 in PRIVATE_KEY_B58")?;
-    match bytes.len() {
-        64 => Keypair::from_bytes(&bytes).context("Failed to parse 64-byte ed25519 keypair"),
-        32 => {
-            let mut seed = [0u8; 32];
-            seed.copy_from_slice(&bytes);
-            Keypair::from_seed(&seed)
-                .map_err(|e| anyhow!("Failed to derive keypair from 32-byte seed: {e}"))
-        }
-        n => bail!(
-            "Decoded private key had {} bytes; expected 32 or 64 (Phantom exports 64)",
-            n
+/// The accounts every position instruction on `[lower, upper]` of a whirlpool shares. The
+/// builders below take these and use no RPC, so other programs can reuse them.
+#[derive(Clone, Copy, Debug)]
+pub struct PositionAccounts {
+    pub pool_id: Pubkey,
+    /// Signer that pays and acts as position authority, and owner of `ata_a`/`ata_b` that
+    /// the amounts move through.
+    pub payer: Pubkey,
+    pub lower: i32,
+    pub upper: i32,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub vault_a: Pubkey,
+    pub vault_b: Pubkey,
+    pub token_program_a: Pubkey,
+    pub token_program_b: Pubkey,
+    pub ata_a: Pubkey,
+    pub ata_b: Pubkey,
+    pub tick_array_lower: Pubkey,
+    pub tick_array_upper: Pubkey,
+}
+
+impl PositionAccounts {
+    /// `payer`'s accounts for `[lower, upper]` on `whirl`; `token_programs` are the programs
+    /// that own `token_mint_a`/`token_mint_b`.
+    pub fn new(
+        pool_id: &Pubkey,
+        whirl: &Whirlpool,
+        payer: &Pubkey,
+        (lower, upper): (i32, i32),
+        (token_program_a, token_program_b): (Pubkey, Pubkey),
+    ) -> Result<Self> {
+        let lower_start = get_tick_array_start_tick_index(lower, whirl.tick_spacing);
+        let upper_start = get_tick_array_start_tick_index(upper, whirl.tick_spacing);
+        Ok(Self {
+            pool_id: *pool_id,
+            payer: *payer,
+            lower,
+            upper,
+            mint_a: whirl.token_mint_a,
+            mint_b: whirl.token_mint_b,
+            vault_a: whirl.token_vault_a,
+            vault_b: whirl.token_vault_b,
+            token_program_a,
+            token_program_b,
+            ata_a: get_associated_token_address_with_program_id(payer, &whirl.token_mint_a, &token_program_a),
+            ata_b: get_associated_token_address_with_program_id(payer, &whirl.token_mint_b, &token_program_b),
+            tick_array_lower: get_tick_array_address(pool_id, lower_start)?.0,
+            tick_array_upper: get_tick_array_address(pool_id, upper_start)?.0,
+        })
+    }
+}
+
+/// Liquidity that `amount_a`/`amount_b` (either may be 0) buy on `[lower, upper]` at the
+/// whirlpool's current price, with the token maxima IncreaseLiquidity may take. Given both
+/// amounts, the side whose counterpart fits in the other amount sets the liquidity.
+pub fn liquidity_for_amounts(
+    whirl: &Whirlpool,
+    (lower, upper): (i32, i32),
+    amount_a: u64,
+    amount_b: u64,
+) -> Result<ocore::IncreaseLiquidityQuote> {
+    let sqrt_price_x64 = whirl.sqrt_price; // u128
+    let slippage_bps: u16 = 0;
+    let quote_a = |what: &str| {
+        ocore::increase_liquidity_quote_a(amount_a, slippage_bps, sqrt_price_x64, lower, upper, None, None)
+            .map_err(|e| anyhow!("liquidity quote failed ({}): {:?}", what, e))
+    };
+    let quote_b = |what: &str| {
+        ocore::increase_liquidity_quote_b(amount_b, slippage_bps, sqrt_price_x64, lower, upper, None, None)
+            .map_err(|e| anyhow!("liquidity quote failed ({}): {:?}", what, e))
+    };
+    if amount_a > 0 && amount_b == 0 {
+        return quote_a("token0 only");
+    }
+    if amount_b > 0 && amount_a == 0 {
+        return quote_b("token1 only");
+    }
+    // Both token0 and token1 provided: try token0-driven quote first, then token1-driven.
+    let by_a = quote_a("token0")?;
+    if by_a.token_max_b <= amount_b {
+        return Ok(by_a);
+    }
+    let by_b = quote_b("token1")?;
+    if by_b.token_max_a <= amount_a {
+        return Ok(by_b);
+    }
+    bail!(
+        "provided token amounts are too low for both sides at current price (need up to token_max_a={}, token_max_b={})",
+        by_b.token_max_a,
+        by_a.token_max_b,
+    )
+}
+
+/// OpenPosition (without metadata) minting `position_mint` into the payer's token account.
+/// `position` is the PDA `get_position_address` derives from the mint, with its bump.
+pub fn build_open_position_ix(
+    accounts: &PositionAccounts,
+    position_mint: &Pubkey,
+    (position, position_bump): (Pubkey, u8),
+) -> Instruction {
+    OpenPosition {
+        funder: accounts.payer,
+        owner: accounts.payer,
+        position,
+        position_mint: *position_mint,
+        position_token_account: get_associated_token_address_with_program_id(
+            &accounts.payer,
+            position_mint,
+            &spl_token::ID,
         ),
+        whirlpool: accounts.pool_id,
+        token_program: spl_token::ID,
+        system_program: system_program::id(),
+        rent: solana_sdk::sysvar::rent::id(),
+        associated_token_program: spl_associated_token_account::id(),
     }
+    .instruction(OpenPositionInstructionArgs {
+        position_bump,
+        tick_lower_index: accounts.lower,
+        tick_upper_index: accounts.upper,
+    })
 }
 
-fn ensure_ata(
-    rpc: &RpcClient,
-    ixs: &mut Vec<Instruction>,
-    owner: &Pubkey,
-    mint: &Pubkey,
-    token_program: &Pubkey,
-) -> Result<()> {
-    let ata = get_associated_token_address_with_program_id(owner, mint, token_program);
-    if rpc
-        .get_account_with_commitment(&ata, CommitmentConfig::processed())?
-        .value
-        .is_none()
-    {
-        ixs.push(create_associated_token_account(
-            owner, owner, mint, token_program,
-        ));
+/// IncreaseLiquidityV2 adding `liquidity` to `position`, paying at most the given amounts.
+pub fn build_increase_liquidity_ix(
+    accounts: &PositionAccounts,
+    (position, position_token_account): (Pubkey, Pubkey),
+    memo_program: &Pubkey,
+    liquidity: u128,
+    (token_max_a, token_max_b): (u64, u64),
+) -> Instruction {
+    IncreaseLiquidityV2 {
+        whirlpool: accounts.pool_id,
+        token_program_a: accounts.token_program_a,
+        token_program_b: accounts.token_program_b,
+        memo_program: *memo_program,
+        position_authority: accounts.payer,
+        position,
+        position_token_account,
+        token_mint_a: accounts.mint_a,
+        token_mint_b: accounts.mint_b,
+        token_owner_account_a: accounts.ata_a,
+        token_owner_account_b: accounts.ata_b,
+        token_vault_a: accounts.vault_a,
+        token_vault_b: accounts.vault_b,
+        tick_array_lower: accounts.tick_array_lower,
+        tick_array_upper: accounts.tick_array_upper,
     }
-    Ok(())
+    .instruction(IncreaseLiquidityV2InstructionArgs {
+        liquidity_amount: liquidity,
+        token_max_a,
+        token_max_b,
+        remaining_accounts_info: None,
+    })
 }
 
-fn detect_token_program_for_mint(rpc: &RpcClient, mint: &Pubkey) -> Result<Pubkey> {
-    let acc = rpc.get_account(mint)?;
-    if acc.owner == spl_token_2022::ID {
-        Ok(spl_token_2022::ID)
-    } else {
-        Ok(spl_token::ID)
+/// DecreaseLiquidityV2 removing `liquidity` from `position`, failing below the given amounts.
+pub fn build_decrease_liquidity_ix(
+    accounts: &PositionAccounts,
+    (position, position_token_account): (Pubkey, Pubkey),
+    memo_program: &Pubkey,
+    liquidity: u128,
+    (token_min_a, token_min_b): (u64, u64),
+) -> Instruction {
+    DecreaseLiquidityV2 {
+        whirlpool: accounts.pool_id,
+        token_program_a: accounts.token_program_a,
+        token_program_b: accounts.token_program_b,
+        memo_program: *memo_program,
+        position_authority: accounts.payer,
+        position,
+        position_token_account,
+        token_mint_a: accounts.mint_a,
+        token_mint_b: accounts.mint_b,
+        token_owner_account_a: accounts.ata_a,
+        token_owner_account_b: accounts.ata_b,
+        token_vault_a: accounts.vault_a,
+        token_vault_b: accounts.vault_b,
+        tick_array_lower: accounts.tick_array_lower,
+        tick_array_upper: accounts.tick_array_upper,
     }
+    .instruction(DecreaseLiquidityV2InstructionArgs {
+        liquidity_amount: liquidity,
+        token_min_a,
+        token_min_b,
+        remaining_accounts_info: None,
+    })
+}
+
+/// ClosePosition burning `position_mint` and refunding the rent to the payer. The position
+/// must hold no liquidity, fees or rewards.
+pub fn build_close_position_ix(
+    accounts: &PositionAccounts,
+    position_mint: &Pubkey,
+    (position, position_token_account): (Pubkey, Pubkey),
+) -> Instruction {
+    ClosePosition {
+        position_authority: accounts.payer,
+        receiver: accounts.payer,
+        position,
+        position_mint: *position_mint,
+        position_token_account,
+        token_program: spl_token::ID,
+    }
+    .instruction()
+}
+
+// ----------------------------- Helpers -----------------------------
+
+/// Check that `delegate` is approved for the position NFT held in `position_token_account`.
+fn verify_position_delegate(
+    accounts: &Prefetched,
+    position_token_account: &Pubkey,
+    delegate: &Pubkey,
+) -> Result<()> {
+    let acc = accounts.require(position_token_account, "[orca] position token account")?;
+    let state = SplTokenAccount::unpack_from_slice(&acc.data)
+        .context("[orca] decode position token account")?;
+    if state.amount != 1 {
+        bail!("position token account {} does not hold the NFT", position_token_account);
+    }
+    if Option::<Pubkey>::from(state.delegate) != Some(*delegate) || state.delegated_amount < 1 {
+        bail!(
+            "signer {} is not an approved delegate of position token account {}",
+            delegate,
+            position_token_account
+        );
+    }
+    debug!("acting as delegate of {}", state.owner);
+    Ok(())
 }
 
 // Anchor-like account decoders (skip the 8-byte discriminator)
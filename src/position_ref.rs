@@ -0,0 +1,87 @@
+use std::fmt;
+
+use anyhow::{Result, bail};
+use solana_sdk::pubkey::Pubkey;
+use tracing::debug;
+
+use crate::cli::{Dex, Opts};
+
+/// A position named together with its venue: `ray:<nft mint>`, `orca:<nft mint>` or
+/// `met:<position account>`. The long names (`raydium:`, `meteora:`) parse too.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PositionRef {
+    pub dex: Dex,
+    /// Position NFT mint (Raydium/Orca) or Position account (Meteora).
+    pub key: Pubkey,
+}
+
+impl PositionRef {
+    pub fn new(dex: Dex, key: Pubkey) -> Self {
+        Self { dex, key }
+    }
+}
+
+impl fmt::Display for PositionRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", prefix(self.dex), self.key)
+    }
+}
+
+fn prefix(dex: Dex) -> &'static str {
+    match dex {
+        Dex::Raydium => "ray",
+        Dex::Orca => "orca",
+        Dex::Meteora => "met",
+    }
+}
+
+/// Split an optional venue prefix off a position argument. Bare keys keep working
+/// and are read on the venue given by `--dex` (or the batch file's dex column).
+pub fn split(s: &str) -> Result<(Option<Dex>, &str)> {
+    let Some((venue, key)) = s.trim().split_once(':') else {
+        return Ok((None, s.trim()));
+    };
+    let dex = match venue.to_ascii_lowercase().as_str() {
+        "ray" | "raydium" => Dex::Raydium,
+        "orca" => Dex::Orca,
+        "met" | "meteora" => Dex::Meteora,
+        other => bail!("unknown venue prefix {:?} in {:?}", other, s),
+    };
+    Ok((Some(dex), key.trim()))
+}
+
+/// Resolve prefixed `--remove-position`/`--collect-fees`/`--resize-position` values: pick `opts.dex` from the
+/// prefixes and leave bare keys behind for the DEX modules. Every prefix must agree.
+pub(crate) fn apply(opts: &mut Opts) -> Result<()> {
+    let mut venue: Option<Dex> = None;
+    for value in [
+        &mut opts.remove_position,
+        &mut opts.collect_fees,
+        &mut opts.resize_position,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        let mut keys = Vec::new();
+        for part in value.split(',') {
+            let (dex, key) = split(part)?;
+            if let Some(dex) = dex {
+                match venue {
+                    Some(v) if v != dex => {
+                        bail!("positions on different venues ({:?} and {:?})", v, dex)
+                    }
+                    _ => venue = Some(dex),
+                }
+            }
+            keys.push(key.to_string());
+        }
+        *value = keys.join(",");
+    }
+    if let Some(dex) = venue {
+        if dex != opts.dex {
+            debug!("venue {:?} taken from the position reference", dex);
+        }
+        opts.dex = dex;
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result, anyhow};
+use futures_util::StreamExt;
+use futures_util::stream::{self, BoxStream};
+use solana_account_decoder::{UiAccountData, UiAccountEncoding};
+use solana_client::nonblocking::pubsub_client::{PubsubClient, PubsubClientError};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::RpcAccountInfoConfig;
+use solana_client::rpc_request::TokenAccountsFilter;
+use solana_sdk::{account::Account, commitment_config::CommitmentConfig, pubkey::Pubkey};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, instrument, warn};
+
+use crate::cli::{Dex, Opts};
+use crate::position_ref::PositionRef;
+use crate::{amounts, meteora, orca, raydium, rpc, signer};
+
+/// One open position as reported by a DEX module's `wallet_positions`.
+pub struct PositionSummary {
+    pub position: PositionRef,
+    pub pool: Pubkey,
+    /// Tick range (Raydium/Orca) or bin id range (Meteora).
+    pub lower: i32,
+    pub upper: i32,
+    pub liquidity: u128,
+    /// Fees owed in token0/token1 (X/Y) as of the position's last on-chain update.
+    pub fees_owed: (u64, u64),
+    pub in_range: bool,
+    /// Slot in the Orca position bundle whose NFT `position` names, for a bundled position.
+    pub bundle_index: Option<u8>,
+}
+
+/// A position's decoded state as of one account update.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PositionState {
+    pub liquidity: u128,
+    /// Fees owed in token0/token1 (X/Y) as of the position's last on-chain update.
+    pub fees_owed: (u64, u64),
+    /// Token0/token1 the liquidity is worth at the pool's current price. Raydium and Orca
+    /// only: Meteora keeps them per bin, in the bin arrays.
+    pub amounts: Option<(u64, u64)>,
+    pub in_range: bool,
+}
+
+/// One change of a watched position, from [`watch`].
+#[derive(Copy, Clone, Debug)]
+pub struct PositionDelta {
+    pub position: PositionRef,
+    /// Slot of the account update that made the change.
+    pub slot: u64,
+    pub before: PositionState,
+    pub after: PositionState,
+}
+
+impl PositionDelta {
+    pub fn liquidity_change(&self) -> i128 {
+        self.after.liquidity as i128 - self.before.liquidity as i128
+    }
+
+    /// Change in fees owed in token0/token1 (X/Y).
+    pub fn fees_owed_change(&self) -> (i128, i128) {
+        (
+            self.after.fees_owed.0 as i128 - self.before.fees_owed.0 as i128,
+            self.after.fees_owed.1 as i128 - self.before.fees_owed.1 as i128,
+        )
+    }
+}
+
+/// List the positions held by `wallet` (default: the PRIVATE_KEY_B58 signer) on every DEX.
+#[instrument(name = "positions", skip_all)]
+pub(crate) async fn run(opts: &Opts, wallet: Option<&str>) -> Result<()> {
+    let rpc = rpc::client(opts);
+
+    let owner = match wallet {
+        Some(w) => Pubkey::from_str(w).context("invalid --positions wallet")?,
+        None => signer::load_payer(opts).await?.pubkey(),
+    };
+
+    let positions = wallet_positions(&rpc, &owner).await?;
+    println!("{} positions for {}:", positions.len(), owner);
+    for p in &positions {
+        let (mint0, mint1) = match p.position.dex {
+            Dex::Raydium => raydium::pool_mints(&rpc, &p.pool).await?,
+            Dex::Orca => orca::pool_mints(&rpc, &p.pool).await?,
+            Dex::Meteora => meteora::pool_mints(&rpc, &p.pool).await?,
+        };
+        println!(
+            "  {:<8} position={}{} pool={} range=[{}, {}] liquidity={} fees_owed(last update)={} / {} {}",
+            format!("{:?}", p.position.dex).to_lowercase(),
+            p.position,
+            p.bundle_index
+                .map_or_else(String::new, |i| format!(" (bundle #{})", i)),
+            p.pool,
+            p.lower,
+            p.upper,
+            amounts::group_thousands(p.liquidity),
+            amounts::format_amount(&rpc, &mint0, p.fees_owed.0).await,
+            amounts::format_amount(&rpc, &mint1, p.fees_owed.1).await,
+            if p.in_range {
+                "in-range"
+            } else {
+                "OUT-OF-RANGE"
+            }
+        );
+    }
+    Ok(())
+}
+
+/// Every Raydium, Orca and Meteora position `owner` holds.
+pub async fn wallet_positions(rpc: &RpcClient, owner: &Pubkey) -> Result<Vec<PositionSummary>> {
+    let nft_mints = wallet_nft_mints(rpc, owner).await?;
+    debug!(
+        "{} token accounts holding a single unit (position NFT candidates)",
+        nft_mints.len()
+    );
+
+    let mut positions = raydium::wallet_positions(rpc, &nft_mints).await?;
+    positions.extend(orca::wallet_positions(rpc, &nft_mints).await?);
+    positions.extend(meteora::wallet_positions(rpc, owner).await?);
+    Ok(positions)
+}
+
+/// Mints of every SPL Token / Token-2022 account of `owner` that holds exactly one unit of
+/// a zero-decimal mint.
+async fn wallet_nft_mints(rpc: &RpcClient, owner: &Pubkey) -> Result<Vec<Pubkey>> {
+    let mut mints = Vec::new();
+    for token_program in [spl_token::ID, spl_token_2022::ID] {
+        let accounts = rpc
+            .get_token_accounts_by_owner(owner, TokenAccountsFilter::ProgramId(token_program))
+            .await
+            .with_context(|| format!("fetch token accounts of {} ({})", owner, token_program))?;
+        for keyed in accounts {
+            // The node returns these jsonParsed: info.mint and info.tokenAmount.
+            let UiAccountData::Json(parsed) = &keyed.account.data else {
+                continue;
+            };
+            let info = &parsed.parsed["info"];
+            let amount = &info["tokenAmount"];
+            if amount["amount"] != "1" || amount["decimals"] != 0 {
+                continue;
+            }
+            if let Some(mint) = info["mint"].as_str().and_then(|m| Pubkey::from_str(m).ok()) {
+                mints.push(mint);
+            }
+        }
+    }
+    Ok(mints)
+}
+
+/// Stream the changes of `position` over `ws_url`, the RPC's websocket endpoint: a
+/// [`PositionDelta`] for every update of the position account or its pool that changes
+/// its [`PositionState`]. Returns once both accounts are subscribed. The stream ends
+/// when the position is closed, the websocket drops or the stream is dropped. Orca
+/// positions held in a bundle cannot be watched.
+pub async fn watch(
+    rpc: &RpcClient,
+    ws_url: &str,
+    position: PositionRef,
+) -> Result<BoxStream<'static, PositionDelta>> {
+    let (account, pool) = match position.dex {
+        Dex::Raydium => raydium::watched_accounts(rpc, &position.key).await?,
+        Dex::Orca => orca::watched_accounts(rpc, &position.key).await?,
+        Dex::Meteora => meteora::watched_accounts(rpc, &position.key).await?,
+    };
+    let fetched = rpc
+        .get_multiple_accounts(&[account, pool])
+        .await
+        .context("fetch position and pool")?;
+    let [Some(position_acc), Some(pool_acc)] =
+        <[_; 2]>::try_from(fetched).map_err(|_| anyhow!("expected two accounts"))?
+    else {
+        anyhow::bail!("position {} or its pool {} not found", position, pool);
+    };
+    // Latest data of the position account and of the pool.
+    let mut data = [position_acc.data, pool_acc.data];
+    let mut state = position_state(position.dex, &data[0], &data[1])?;
+
+    let (ready_tx, ready_rx) = oneshot::channel::<Result<()>>();
+    let (delta_tx, delta_rx) = mpsc::unbounded_channel();
+    let ws_url = ws_url.to_string();
+    tokio::spawn(async move {
+        let client = match PubsubClient::new(&ws_url).await {
+            Ok(client) => client,
+            Err(e) => {
+                let _ = ready_tx.send(Err(
+                    anyhow::Error::new(e).context(format!("connect to {}", ws_url))
+                ));
+                return;
+            }
+        };
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            ..RpcAccountInfoConfig::default()
+        };
+        let subscribed = async {
+            let (position_updates, _) = client
+                .account_subscribe(&account, Some(config.clone()))
+                .await?;
+            let (pool_updates, _) = client.account_subscribe(&pool, Some(config)).await?;
+            Ok::<_, PubsubClientError>((position_updates, pool_updates))
+        };
+        let (position_updates, pool_updates) = match subscribed.await {
+            Ok(updates) => updates,
+            Err(e) => {
+                let _ = ready_tx.send(Err(
+                    anyhow::Error::new(e).context("subscribe to the position and pool")
+                ));
+                return;
+            }
+        };
+        let _ = ready_tx.send(Ok(()));
+
+        let mut updates = stream::select(
+            position_updates.map(|u| (0, u)),
+            pool_updates.map(|u| (1, u)),
+        );
+        while let Some((which, update)) = updates.next().await {
+            let Some(acc) = update.value.decode::<Account>() else {
+                warn!("undecodable update of {}", [account, pool][which]);
+                continue;
+            };
+            if which == 0 && acc.data.is_empty() {
+                debug!("position {} closed", position);
+                break;
+            }
+            data[which] = acc.data;
+            let after = match position_state(position.dex, &data[0], &data[1]) {
+                Ok(after) => after,
+                Err(e) => {
+                    warn!("decode update of {}: {:#}", position, e);
+                    continue;
+                }
+            };
+            if after == state {
+                continue;
+            }
+            let delta = PositionDelta {
+                position,
+                slot: update.context.slot,
+                before: state,
+                after,
+            };
+            state = after;
+            if delta_tx.send(delta).is_err() {
+                break;
+            }
+        }
+    });
+    ready_rx
+        .await
+        .context("the watch task ended before subscribing")??;
+    Ok(stream::unfold(delta_rx, |mut rx| async move {
+        rx.recv().await.map(|delta| (delta, rx))
+    })
+    .boxed())
+}
+
+fn position_state(dex: Dex, position: &[u8], pool: &[u8]) -> Result<PositionState> {
+    match dex {
+        Dex::Raydium => raydium::position_state(position, pool),
+        Dex::Orca => orca::position_state(position, pool),
+        Dex::Meteora => meteora::position_state(position, pool),
+    }
+}
@@ -0,0 +1,478 @@
+use solana_sdk::pubkey::Pubkey;
+use tracing::{debug, info};
+
+use crate::error::{Error, Result};
+
+/// Off-chain estimate of an exact-in swap, in base units.
+pub struct SwapQuote {
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee: u64,
+    /// Output base units per input base unit at the pool's current price.
+    pub spot_out_per_in: f64,
+}
+
+impl SwapQuote {
+    /// Shortfall of the quoted output versus trading the post-fee input at spot, in bps.
+    pub fn price_impact_bps(&self) -> u64 {
+        let ideal = self.amount_in.saturating_sub(self.fee) as f64 * self.spot_out_per_in;
+        if ideal <= 0.0 {
+            return 0;
+        }
+        let impact = 1.0 - self.amount_out as f64 / ideal;
+        (impact.max(0.0) * 10_000.0).round() as u64
+    }
+}
+
+/// One exact-in swap on a single pool.
+pub struct SwapLeg {
+    pub pool: Pubkey,
+    /// token0 -> token1 (or A -> B) when true.
+    pub a_to_b: bool,
+    pub amount_in: u64,
+    pub min_out: u64,
+    /// Q64.64 sqrt price limit; 0 uses the protocol min/max.
+    pub sqrt_price_limit: u128,
+    /// Raise `min_out` to the quoted output minus this many bps.
+    pub slippage_bps: Option<u64>,
+}
+
+/// `amount` minus `bps` basis points, rounded down.
+pub fn apply_slippage(amount: u64, bps: u64) -> u64 {
+    (amount as u128 * 10_000u128.saturating_sub(bps as u128) / 10_000) as u64
+}
+
+/// The swap's min-out: `min_out`, or the quoted output minus `slippage_bps` if that is higher.
+pub fn slippage_min_out(quote: &SwapQuote, min_out: u64, slippage_bps: Option<u64>) -> u64 {
+    match slippage_bps {
+        Some(bps) => min_out.max(apply_slippage(quote.amount_out, bps)),
+        None => min_out,
+    }
+}
+
+/// Fractional grid index of `price` (token1 per token0, UI units) on a grid where index `i`
+/// prices at `base^i` in base units: 1.0001 for CLMM ticks, 1 + bin_step / 10_000 for DLMM bins.
+pub fn price_to_index(price: f64, decimals0: u8, decimals1: u8, base: f64) -> f64 {
+    (price / 10f64.powi(decimals0 as i32 - decimals1 as i32)).ln() / base.ln()
+}
+
+/// Price (token1 per token0, UI units) at grid `index`; the inverse of `price_to_index`.
+pub fn index_to_price(index: i32, decimals0: u8, decimals1: u8, base: f64) -> f64 {
+    base.powi(index) * 10f64.powi(decimals0 as i32 - decimals1 as i32)
+}
+
+/// Narrowest `spacing`-aligned index range on that grid that covers `[price_min, price_max]`.
+pub fn price_range_to_indices(
+    (price_min, price_max): (f64, f64),
+    decimals0: u8,
+    decimals1: u8,
+    base: f64,
+    spacing: i32,
+) -> (i32, i32) {
+    let step = spacing as f64;
+    let lower = (price_to_index(price_min, decimals0, decimals1, base) / step).floor() as i32;
+    let upper = (price_to_index(price_max, decimals0, decimals1, base) / step).ceil() as i32;
+    (lower * spacing, upper.max(lower + 1) * spacing)
+}
+
+/// A pool's price grid: index `i` prices at `base^i` (token1 per token0, base units) and
+/// ranges start and end on multiples of `spacing`.
+#[derive(Clone, Copy, Debug)]
+pub struct Grid {
+    pub base: f64,
+    pub spacing: i32,
+}
+
+impl Grid {
+    /// Raydium/Orca ticks.
+    pub fn clmm(tick_spacing: u16) -> Self {
+        Self {
+            base: 1.0001,
+            spacing: tick_spacing as i32,
+        }
+    }
+
+    /// Meteora bins.
+    pub fn dlmm(bin_step: u16) -> Self {
+        Self {
+            base: 1.0 + bin_step as f64 / 10_000.0,
+            spacing: 1,
+        }
+    }
+}
+
+/// Narrowest range on grid `to` that covers the prices of `[lower, upper]` on grid `from`.
+/// `inverted` when the two pools order the pair's mints the other way round, so one
+/// pool's token0 is the other's token1. Decimals cancel out: both grids are in base units.
+pub fn convert_range(
+    (lower, upper): (i32, i32),
+    from: Grid,
+    to: Grid,
+    inverted: bool,
+) -> (i32, i32) {
+    // ln(price) of each end, in the target pool's orientation.
+    let (lo, hi) = if inverted {
+        (
+            -(upper as f64) * from.base.ln(),
+            -(lower as f64) * from.base.ln(),
+        )
+    } else {
+        (lower as f64 * from.base.ln(), upper as f64 * from.base.ln())
+    };
+    // Tolerate float noise so an exact grid match does not widen by a step.
+    const EPS: f64 = 1e-9;
+    let step = to.base.ln() * to.spacing as f64;
+    let l = (lo / step + EPS).floor() as i32;
+    let u = (hi / step - EPS).ceil() as i32;
+    (l * to.spacing, u.max(l + 1) * to.spacing)
+}
+
+/// A candidate open range scored against a recent price path.
+#[derive(Clone, Copy, Debug)]
+pub struct RangeScore {
+    pub lower: i32,
+    pub upper: i32,
+    /// Share of the path's time the price spent inside the range.
+    pub in_range: f64,
+    /// Liquidity per unit of capital relative to a full-range position at the current price.
+    pub concentration: f64,
+    /// Fees earned over the path per unit of capital.
+    pub fees: f64,
+    /// Loss against holding the opening amounts, per unit of capital, when the price moves
+    /// one observed standard deviation either way.
+    pub loss: f64,
+}
+
+impl RangeScore {
+    /// Expected return per unit of capital over the path: fees net of divergence loss.
+    pub fn score(&self) -> f64 {
+        self.fees - self.loss
+    }
+}
+
+/// Score `--optimize-range` candidates: ranges of 1, 2, 4, ... `grid.spacing` steps on each
+/// side of `current`, within `[min, max]`. `path` is the recent price as `(mean index,
+/// seconds)` intervals, oldest first, and `fee_rate` the pool's swap fee as a fraction.
+///
+/// No per-tick volume is kept on chain, so volume is read off the path: moving the price
+/// from sqrt price `s` to `s'` swaps `L * |s' - s|` of token1 through liquidity `L`, and a
+/// range earns `fee_rate` on the part of each move that crosses it. Round trips inside
+/// one interval are not seen, so fees are a lower bound. Against that the range pays the
+/// divergence loss of a move of one standard deviation of the path around `current`, which
+/// grows as the range narrows and the price is more likely to leave it.
+pub fn score_ranges(
+    path: &[(f64, u32)],
+    current: i32,
+    grid: Grid,
+    fee_rate: f64,
+    (min, max): (i32, i32),
+) -> Vec<RangeScore> {
+    let total: f64 = path.iter().map(|&(_, secs)| secs as f64).sum();
+    // sqrt prices relative to the current one, so decimals and the price level cancel out.
+    let sqrt_rel = |index: f64| grid.base.powf((index - current as f64) / 2.0);
+    let sigma = path_sigma(path, current);
+    let base = current.div_euclid(grid.spacing) * grid.spacing;
+    let mut scores = Vec::new();
+    let mut steps = 1;
+    loop {
+        let lower = base - steps * grid.spacing;
+        let upper = base + (steps + 1) * grid.spacing;
+        if lower < min || upper > max {
+            break;
+        }
+        let (sa, sb) = (sqrt_rel(lower as f64), sqrt_rel(upper as f64));
+        // Value of one unit of liquidity at the current price, against 2 for the full range.
+        let capital = 2.0 - sa - 1.0 / sb;
+        let inside: f64 = path
+            .iter()
+            .filter(|&&(index, _)| index >= lower as f64 && index < upper as f64)
+            .map(|&(_, secs)| secs as f64)
+            .sum();
+        let swapped: f64 = path
+            .windows(2)
+            .map(|w| {
+                let from = sqrt_rel(w[0].0).clamp(sa, sb);
+                let to = sqrt_rel(w[1].0).clamp(sa, sb);
+                (to - from).abs()
+            })
+            .sum();
+        let loss = (divergence_loss((sa, sb), sqrt_rel(current as f64 + sigma))
+            + divergence_loss((sa, sb), sqrt_rel(current as f64 - sigma)))
+            / 2.0;
+        scores.push(RangeScore {
+            lower,
+            upper,
+            in_range: if total > 0.0 { inside / total } else { 0.0 },
+            concentration: 2.0 / capital,
+            fees: fee_rate * swapped / capital,
+            loss: loss / capital,
+        });
+        if steps >= 1 << 14 {
+            break;
+        }
+        steps *= 2;
+    }
+    scores
+}
+
+/// Standard deviation of `path` around `current`, in indices, weighting each interval by
+/// its length; 0 for an empty path.
+pub fn path_sigma(path: &[(f64, u32)], current: i32) -> f64 {
+    let total: f64 = path.iter().map(|&(_, secs)| secs as f64).sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+    let var: f64 = path
+        .iter()
+        .map(|&(index, secs)| (index - current as f64).powi(2) * secs as f64)
+        .sum();
+    (var / total).sqrt()
+}
+
+/// What one unit of liquidity over relative sqrt prices `[sa, sb]`, opened at 1, is worth
+/// less than the amounts it opened with once the sqrt price is `s`, in token1.
+fn divergence_loss((sa, sb): (f64, f64), s: f64) -> f64 {
+    let held = (1.0 - 1.0 / sb) * s * s + (1.0 - sa);
+    let c = s.clamp(sa, sb);
+    let position = (1.0 / c - 1.0 / sb) * s * s + (c - sa);
+    held - position
+}
+
+/// Check an open's `[lower, upper]` against the pool's index `spacing` and `[min, max]`
+/// bounds before anything is sent. Misaligned ends are rounded to the nearest valid range
+/// with `--snap` and rejected otherwise, naming the values to use instead.
+pub fn align_range(
+    lower: i32,
+    upper: i32,
+    spacing: i32,
+    (min, max): (i32, i32),
+    snap: bool,
+    base: f64,
+) -> anyhow::Result<(i32, i32)> {
+    let (mut lower, mut upper) = (lower, upper);
+    if lower % spacing != 0 || upper % spacing != 0 {
+        let (l, u) = nearest_valid_range(lower, upper, spacing);
+        let describe = |from: i32, to: i32| {
+            format!(
+                "{} -> {} (price {:+.4}%)",
+                from,
+                to,
+                (base.powi(to - from) - 1.0) * 100.0
+            )
+        };
+        if !snap {
+            anyhow::bail!(
+                "range ends must be multiples of the pool spacing {}; nearest valid: --lower {}, --upper {} (pass --snap to round automatically)",
+                spacing,
+                describe(lower, l),
+                describe(upper, u)
+            );
+        }
+        info!(
+            "snapped to spacing {}: lower {}, upper {}",
+            spacing,
+            describe(lower, l),
+            describe(upper, u)
+        );
+        lower = l;
+        upper = u;
+    }
+    if upper < lower || (upper == lower && spacing > 1) {
+        anyhow::bail!("upper {} must be above lower {}", upper, lower);
+    }
+    // Bounds rounded inward to the spacing, as the programs require.
+    let (min, max) = (
+        min + (-min).rem_euclid(spacing),
+        max - max.rem_euclid(spacing),
+    );
+    if lower < min || upper > max {
+        anyhow::bail!(
+            "range [{}, {}] is outside the pool bounds [{}, {}]",
+            lower,
+            upper,
+            min,
+            max
+        );
+    }
+    Ok((lower, upper))
+}
+
+/// Round `[lower, upper]` to the nearest multiples of `spacing`, keeping the range non-empty.
+fn nearest_valid_range(lower: i32, upper: i32, spacing: i32) -> (i32, i32) {
+    let round = |t: i32| (t as f64 / spacing as f64).round() as i32 * spacing;
+    let l = round(lower);
+    let mut u = round(upper);
+    if u <= l {
+        u = l + spacing;
+    }
+    (l, u)
+}
+
+/// Print the quote and fail with `PriceImpactExceeded` above `--max-price-impact-bps`.
+pub fn enforce_max_price_impact(quote: &SwapQuote, max_bps: Option<u64>) -> Result<()> {
+    let impact = quote.price_impact_bps();
+    debug!(
+        "quote: in={} est_out={} fee={} price_impact={}bps",
+        quote.amount_in, quote.amount_out, quote.fee, impact
+    );
+    if let Some(max) = max_bps
+        && impact > max
+    {
+        return Err(Error::PriceImpactExceeded {
+            impact_bps: impact,
+            max_bps: max,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path swinging between `current - amplitude` and `current + amplitude`, 60s a sample.
+    fn swinging_path(current: i32, amplitude: f64, samples: usize) -> Vec<(f64, u32)> {
+        (0..samples)
+            .map(|i| {
+                let side = if i % 2 == 0 { 1.0 } else { -1.0 };
+                (current as f64 + side * amplitude, 60)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn score_ranges_stays_on_the_grid_and_within_bounds() {
+        let grid = Grid::clmm(10);
+        let path = swinging_path(5, 3.0, 10);
+        let scores = score_ranges(&path, 5, grid, 0.0025, (-1000, 1000));
+        assert!(!scores.is_empty());
+        for s in &scores {
+            assert_eq!(s.lower % 10, 0);
+            assert_eq!(s.upper % 10, 0);
+            assert!(s.lower <= 5 && s.upper > 5);
+            assert!(s.lower >= -1000 && s.upper <= 1000);
+        }
+        // Each candidate doubles the steps of the last one.
+        assert_eq!((scores[0].lower, scores[0].upper), (-10, 20));
+        assert_eq!((scores[1].lower, scores[1].upper), (-20, 30));
+    }
+
+    #[test]
+    fn score_ranges_favors_tight_ranges_in_calm_markets() {
+        let path = swinging_path(0, 3.0, 20);
+        let scores = score_ranges(&path, 0, Grid::clmm(10), 0.0025, (-100_000, 100_000));
+        let best = scores
+            .iter()
+            .max_by(|a, b| a.score().total_cmp(&b.score()))
+            .unwrap();
+        assert_eq!((best.lower, best.upper), (scores[0].lower, scores[0].upper));
+        assert!(scores.iter().all(|s| s.in_range == 1.0));
+        // Fees follow concentration while the path stays inside every range.
+        assert!(scores.windows(2).all(|w| w[0].fees > w[1].fees));
+    }
+
+    #[test]
+    fn score_ranges_penalizes_ranges_the_price_leaves() {
+        let path = swinging_path(0, 2000.0, 20);
+        let scores = score_ranges(&path, 0, Grid::clmm(10), 0.0025, (-100_000, 100_000));
+        let best = scores
+            .iter()
+            .max_by(|a, b| a.score().total_cmp(&b.score()))
+            .unwrap();
+        assert!(best.upper - best.lower > 4000, "picked {:?}", best);
+        // The narrowest range is out of range all the time and loses more than it earns.
+        assert_eq!(scores[0].in_range, 0.0);
+        assert!(scores[0].score() < 0.0);
+        assert!(scores[0].loss > best.loss);
+    }
+
+    #[test]
+    fn path_sigma_is_the_time_weighted_swing() {
+        assert_eq!(path_sigma(&swinging_path(100, 30.0, 8), 100), 30.0);
+        assert_eq!(path_sigma(&[], 100), 0.0);
+    }
+
+    #[test]
+    fn divergence_loss_is_zero_at_the_open_price_only() {
+        let range = (0.99, 1.01);
+        assert!(divergence_loss(range, 1.0).abs() < 1e-12);
+        for s in [0.9, 0.995, 1.005, 1.1] {
+            assert!(divergence_loss(range, s) > 0.0);
+        }
+    }
+
+    #[test]
+    fn convert_range_same_grid_round_trips() {
+        for grid in [Grid::clmm(1), Grid::clmm(64), Grid::dlmm(25)] {
+            let range = (-3 * grid.spacing, 5 * grid.spacing);
+            assert_eq!(convert_range(range, grid, grid, false), range);
+        }
+    }
+
+    #[test]
+    fn convert_range_inverted_pair_mirrors_the_range() {
+        let grid = Grid::clmm(64);
+        assert_eq!(convert_range((-640, 1280), grid, grid, true), (-1280, 640));
+        // Inverting twice gives the original range back.
+        let once = convert_range((-640, 1280), grid, grid, true);
+        assert_eq!(convert_range(once, grid, grid, true), (-640, 1280));
+    }
+
+    #[test]
+    fn convert_range_between_ticks_and_bins() {
+        // 1000 ticks of 1.0001 are a little over 100 bins of 1.001: widen to cover.
+        assert_eq!(
+            convert_range((0, 1000), Grid::clmm(1), Grid::dlmm(10), false),
+            (0, 101)
+        );
+        // 100 bins of 1.001 are a little under 1000 ticks.
+        assert_eq!(
+            convert_range((0, 100), Grid::dlmm(10), Grid::clmm(1), false),
+            (0, 1000)
+        );
+        // A DLMM pool with 1bp bins is on the tick grid.
+        assert_eq!(
+            convert_range((-120, 350), Grid::clmm(1), Grid::dlmm(1), false),
+            (-120, 350)
+        );
+    }
+
+    #[test]
+    fn convert_range_does_not_widen_on_float_noise() {
+        // -50 * ln(1.0001) / (10 * ln(1.0001)) is -5.000000000000001 in f64; without the
+        // tolerance the lower end would floor to -60.
+        let to = Grid::clmm(10);
+        assert_eq!(
+            convert_range((-50, 70), Grid::dlmm(1), to, false),
+            (-50, 70)
+        );
+        assert_eq!(
+            convert_range((-2000, 2000), Grid::dlmm(1), to, false),
+            (-2000, 2000)
+        );
+    }
+
+    #[test]
+    fn degenerate_widths_keep_one_step() {
+        let to = Grid::clmm(10);
+        assert_eq!(convert_range((10, 10), Grid::clmm(1), to, false), (10, 20));
+        assert_eq!(convert_range((13, 13), Grid::clmm(1), to, false), (10, 20));
+        // A single price exactly on a tick still opens one spacing wide.
+        assert_eq!(
+            price_range_to_indices((1.0, 1.0), 6, 6, 1.0001, 10),
+            (0, 10)
+        );
+    }
+
+    #[test]
+    fn price_range_round_trips_through_indices() {
+        let (decimals0, decimals1) = (9, 6);
+        let lower = index_to_price(-1280, decimals0, decimals1, 1.0001);
+        let upper = index_to_price(640, decimals0, decimals1, 1.0001);
+        let (l, u) = price_range_to_indices((lower, upper), decimals0, decimals1, 1.0001, 64);
+        // Float noise may widen each end by at most one spacing.
+        assert!((-1344..=-1280).contains(&l), "lower {}", l);
+        assert!((640..=704).contains(&u), "upper {}", u);
+        assert!((price_to_index(lower, decimals0, decimals1, 1.0001) + 1280.0).abs() < 1e-6);
+    }
+}
@@ -1,125 +1,194 @@
 use std::str::FromStr;
 
 use anchor_lang::{InstructionData, ToAccountMetas};
-use anyhow::{Context, Result, anyhow, bail};
+use anyhow::{Context, Result, bail};
 use raydium_amm_v3::{accounts as r_accounts, instruction as r_ix, libraries as r_libs};
 use raydium_clmm::accounts::{
+    amm_config::AmmConfig as CAmmConfig, observation_state::ObservationState as CObservationState,
     personal_position_state::PersonalPositionState as CPersonalPosition,
     pool_state::PoolState as CPoolState,
+    protocol_position_state::ProtocolPositionState as CProtocolPosition,
+    tick_array_state::TickArrayState as CTickArray,
 };
-use solana_client::rpc_client::RpcClient;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
 use solana_client::rpc_request::TokenAccountsFilter;
 use solana_pubkey::Pubkey as RawPubkey;
 use solana_sdk::{
+    account::Account,
     commitment_config::CommitmentConfig,
     compute_budget::ComputeBudgetInstruction,
     instruction::{AccountMeta, Instruction},
     program_pack::Pack,
     pubkey::Pubkey,
-    signature::{Keypair, SeedDerivable, Signer},
+    rent::Rent,
+    signature::{Keypair, Signer},
     sysvar,
 };
 use spl_associated_token_account::{
     ID as ASSOCIATED_TOKEN_PROGRAM_ID, get_associated_token_address_with_program_id,
-    instruction::create_associated_token_account,
 };
 use spl_token::state::Account as SplTokenAccount;
 use spl_token_2022::state::Account as SplToken2022Account;
+use tracing::{debug, info, instrument, warn};
 
-use crate::cli::Opts;
-use crate::tx::{build_unwrap_sol_ix, build_wrap_sol_ixs, simulate_and_send};
+use crate::amounts::{self, Prefetched};
+use crate::cli::{Cluster, Dex, Mode, Opts, PriceRange};
+use crate::cluster;
+use crate::error::{self, Error};
+use crate::position_ref::PositionRef;
+use crate::positions::{PositionState, PositionSummary};
+use crate::quote::{
+    Grid, SwapLeg, SwapQuote, align_range, apply_slippage, enforce_max_price_impact,
+    index_to_price, path_sigma, price_range_to_indices, score_ranges, slippage_min_out,
+};
+use crate::rpc;
+use crate::signer;
+use crate::tx::{Flow, Report, Sent, TxConfig, build_unwrap_sol_ix, build_wrap_sol_ixs};
 use mpl_token_metadata::ID as METADATA_PROGRAM_ID;
 
+const CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+const CLMM_PROGRAM_ID_DEVNET: &str = "devi51mZmdwUJGU9hjN27vEz64Gps7uUefqxg27EAtH";
+const MEMO_PROGRAM_ID: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+
 /// Main entry for CLI dispatch.
-pub fn run(opts: Opts) -> Result<()> {
-    let rpc_url = opts
-        .rpc
-        .clone()
-        .or_else(|| std::env::var("RPC_URL").ok())
-        .unwrap_or_else(|| "https://api.mainnet-beta.solana.com".to_string());
-    let rpc = RpcClient::new_with_commitment(rpc_url.clone(), CommitmentConfig::confirmed());
+pub(crate) async fn run(opts: Opts, config: &TxConfig) -> Result<Report> {
+    let rpc = rpc::client(&opts);
 
-    let key_b58 = std::env::var("PRIVATE_KEY_B58").context("Set PRIVATE_KEY_B58 in .env")?;
-    let payer = parse_phantom_base58_key(&key_b58)?;
-    let payer_pk = payer.pubkey();
+    let clmm_program_id = clmm_program_id()?;
+    let memo_program_id = Pubkey::from_str(MEMO_PROGRAM_ID)?;
+
+    // Read-only modes don't need the signer.
+    if let Some(pool_str) = &opts.twap_pool {
+        handle_twap(&rpc, &clmm_program_id, pool_str, opts.twap_window).await?;
+        return Ok(Report::default());
+    }
+    if opts.find_positions {
+        handle_find_positions(&rpc, &clmm_program_id, &opts).await?;
+        return Ok(Report::default());
+    }
 
-    let clmm_program_id = Pubkey::from_str("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK")?;
-    let memo_program_id = Pubkey::from_str("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr")?;
+    let wallet = signer::load_payer(&opts).await?;
+    let payer_pk = wallet.pubkey();
+    let mut flow = Flow::new(&rpc, config, wallet.as_ref());
 
     let mut ixs: Vec<Instruction> = vec![
         ComputeBudgetInstruction::set_compute_unit_limit(opts.cu_limit),
-        ComputeBudgetInstruction::set_compute_unit_price(opts.cu_price),
+        ComputeBudgetInstruction::set_compute_unit_price(opts.effective_cu_price()),
     ];
 
     if opts.wrap_sol > 0 {
-        eprintln!("[debug] wrapping {} lamports into WSOL", opts.wrap_sol);
-        ixs.extend(build_wrap_sol_ixs(&rpc, &payer_pk, opts.wrap_sol)?);
+        debug!("wrapping {} lamports into WSOL", opts.wrap_sol);
+        ixs.extend(build_wrap_sol_ixs(&rpc, &payer_pk, opts.wrap_sol).await?);
     }
 
-    if let Some(pool_str) = &opts.swap_pool {
-        handle_swap(
+    match opts.mode()? {
+        Mode::Swap(pool_str) => {
+            handle_swap(&rpc, &mut flow, &payer_pk, &pool_str, &opts, &mut ixs).await
+        }
+        Mode::Collect(pos_mint_str) => handle_remove_all(
             &rpc,
             &clmm_program_id,
-            &payer,
+            &memo_program_id,
+            &mut flow,
             &payer_pk,
-            pool_str,
+            &pos_mint_str,
             &opts,
             &mut ixs,
+            true,
         )
-    } else if let Some(pos_mint_str) = &opts.remove_position {
-        handle_remove_all(
+        .await
+        .map(drop),
+        Mode::Remove(pos_mint_str) => handle_remove_all(
             &rpc,
             &clmm_program_id,
             &memo_program_id,
-            &payer,
+            &mut flow,
             &payer_pk,
-            pos_mint_str,
+            &pos_mint_str,
             &opts,
             &mut ixs,
+            false,
         )
-    } else if opts.pool.is_some() {
-        handle_open(&rpc, &clmm_program_id, &payer, &payer_pk, opts, ixs)
-    } else {
-        if opts.unwrap_sol {
-            ixs.push(build_unwrap_sol_ix(&payer_pk));
+        .await
+        .map(drop),
+        Mode::Resize(pos_mint_str) => {
+            handle_resize(
+                &rpc,
+                &clmm_program_id,
+                &memo_program_id,
+                &mut flow,
+                &payer_pk,
+                &pos_mint_str,
+                opts,
+                ixs,
+            )
+            .await
         }
-        if ixs.len() > 2 || opts.unwrap_sol {
-            let sig = simulate_and_send(&rpc, &payer, ixs, &[&payer])?;
-            println!("✅ Submitted wrap/unwrap tx: {}", sig);
-            Ok(())
-        } else {
-            bail!("provide swap/open/remove args or wrap/unwrap flags");
+        Mode::Seed(seed_mint_str) => {
+            handle_seed_liquidity(
+                &rpc,
+                &clmm_program_id,
+                &mut flow,
+                &payer_pk,
+                &seed_mint_str,
+                opts,
+                ixs,
+            )
+            .await
         }
-    }
+        Mode::Open(_) => handle_open(&rpc, &clmm_program_id, &mut flow, &payer_pk, opts, ixs).await,
+        Mode::Wrap => {
+            if opts.unwrap_sol {
+                ixs.push(build_unwrap_sol_ix(&payer_pk));
+            }
+            if ixs.len() > 2 || opts.unwrap_sol {
+                if let Some(sig) = flow.send(ixs, &[]).await?.signature() {
+                    println!("✅ Submitted wrap/unwrap tx: {}", sig);
+                }
+                Ok(())
+            } else {
+                bail!(
+                    "provide an open/remove/collect/swap/wrap/unwrap subcommand (or the equivalent flags)"
+                );
+            }
+        }
+    }?;
+    Ok(flow.finish().await?)
 }
 
-fn parse_phantom_base58_key(s: &str) -> Result<Keypair> {
-    let bytes = bs58::decode(s.trim())
-        .into_vec()
-        .context("Invalid base58 in PRIVATE_KEY_B58")?;
-    match bytes.len() {
-        64 => Keypair::from_bytes(&bytes).context("Failed to parse 64-byte ed25519 keypair"),
-        32 => {
-            let seed: [u8; 32] = bytes
-                .as_slice()
-                .try_into()
-                .context("Seed must be 32 bytes")?;
-            Keypair::from_seed(&seed)
-                .map_err(|e| anyhow!("Failed to derive keypair from 32-byte seed: {e}"))
-        }
-        n => bail!(
-            "Decoded private key had {} bytes; expected 32 or 64 (Phantom exports 64)",
-            n
-        ),
-    }
+/// Raydium CLMM program of the `--cluster` (local validators clone the mainnet program).
+pub(crate) fn clmm_program_id() -> Result<Pubkey> {
+    let id = match cluster::current() {
+        Cluster::Devnet => CLMM_PROGRAM_ID_DEVNET,
+        Cluster::Mainnet | Cluster::Localnet => CLMM_PROGRAM_ID,
+    };
+    Ok(Pubkey::from_str(id)?)
 }
 
-fn decode_pool_clmm(data: &[u8]) -> Result<CPoolState> {
-    CPoolState::from_bytes(data).context("decode pool via raydium_clmm")
+fn decode_pool_clmm(data: &[u8]) -> error::Result<CPoolState> {
+    CPoolState::from_bytes(data).map_err(|e| decode_error("pool", e))
 }
 
-fn decode_personal_position_clmm(data: &[u8]) -> Result<CPersonalPosition> {
-    CPersonalPosition::from_bytes(data).context("decode personal position via raydium_clmm")
+fn decode_personal_position_clmm(data: &[u8]) -> error::Result<CPersonalPosition> {
+    CPersonalPosition::from_bytes(data).map_err(|e| decode_error("personal position", e))
+}
+
+fn decode_observation_clmm(data: &[u8]) -> error::Result<CObservationState> {
+    CObservationState::from_bytes(data).map_err(|e| decode_error("observation state", e))
+}
+
+fn decode_protocol_position_clmm(data: &[u8]) -> error::Result<CProtocolPosition> {
+    CProtocolPosition::from_bytes(data).map_err(|e| decode_error("protocol position", e))
+}
+
+fn decode_error(what: &'static str, e: std::io::Error) -> Error {
+    Error::Decode {
+        what,
+        reason: format!("{} (via raydium_clmm)", e),
+    }
 }
 
 fn to_sdk_pubkey(raw: &RawPubkey) -> Pubkey {
@@ -173,43 +242,153 @@ fn derive_protocol_position_pda(
     )
 }
 
-fn find_position_nft_account(
+/// Fetch every protocol position of a pool (one per distinct range somebody has opened).
+async fn fetch_pool_protocol_positions(
+    rpc: &RpcClient,
+    pool_id: &Pubkey,
+    program_id: &Pubkey,
+) -> Result<Vec<CProtocolPosition>> {
+    // Layout: 8-byte discriminator, 1-byte bump, then pool_id.
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(CProtocolPosition::LEN as u64),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(9, pool_id.to_bytes().to_vec())),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc
+        .get_program_accounts_with_config(program_id, config)
+        .await
+        .context("fetch protocol positions for pool")?;
+    let mut out = Vec::with_capacity(accounts.len());
+    for (pk, acc) in accounts {
+        match decode_protocol_position_clmm(&acc.data) {
+            Ok(p) => out.push(p),
+            Err(e) => warn!("skipping protocol position {}: {:#}", pk, e),
+        }
+    }
+    Ok(out)
+}
+
+/// Pick the live range closest to `[lower, upper]` among existing protocol positions.
+/// Only ranges overlapping the request are considered; ties go to the deepest liquidity.
+fn snap_to_existing_range(
+    existing: &[CProtocolPosition],
+    lower: i32,
+    upper: i32,
+) -> Option<(i32, i32)> {
+    existing
+        .iter()
+        .filter(|p| p.liquidity > 0)
+        .filter(|p| p.tick_lower_index < upper && p.tick_upper_index > lower)
+        .min_by_key(|p| {
+            let distance = (p.tick_lower_index - lower).unsigned_abs()
+                + (p.tick_upper_index - upper).unsigned_abs();
+            (distance, std::cmp::Reverse(p.liquidity))
+        })
+        .map(|p| (p.tick_lower_index, p.tick_upper_index))
+}
+
+/// Report which shared range accounts already exist and the rent the open will pay for
+/// the rest, from `accounts` (which holds them and the rent sysvar).
+fn report_open_rent(
+    accounts: &Prefetched,
+    protocol_position: &Pubkey,
+    tick_array_lower: &Pubkey,
+    tick_array_upper: &Pubkey,
+) -> Result<()> {
+    let mut shared = vec![
+        (
+            "protocol position",
+            *protocol_position,
+            CProtocolPosition::LEN,
+        ),
+        ("tick array (lower)", *tick_array_lower, CTickArray::LEN),
+    ];
+    if tick_array_upper != tick_array_lower {
+        shared.push(("tick array (upper)", *tick_array_upper, CTickArray::LEN));
+    }
+    let rent: Rent =
+        bincode::deserialize(&accounts.require(&sysvar::rent::id(), "rent sysvar")?.data)
+            .context("decode rent sysvar")?;
+
+    let mut rent_new = 0u64;
+    let mut rent_saved = 0u64;
+    for (label, pk, len) in &shared {
+        let rent = rent.minimum_balance(*len);
+        if accounts.get(pk)?.is_some() {
+            info!("{} {} already exists (shared range)", label, pk);
+            rent_saved += rent;
+        } else {
+            info!("{} {} will be created ({} lamports rent)", label, pk, rent);
+            rent_new += rent;
+        }
+    }
+    info!(
+        "range accounts rent: {} lamports to create, {} lamports avoided by reusing existing accounts",
+        rent_new, rent_saved
+    );
+    Ok(())
+}
+
+/// Associated token accounts `owner` may hold the position NFT `mint` in, under either
+/// token program.
+fn position_nft_atas(owner: &Pubkey, mint: &Pubkey) -> [Pubkey; 2] {
+    [spl_token::ID, spl_token_2022::ID]
+        .map(|program| get_associated_token_address_with_program_id(owner, mint, &program))
+}
+
+/// Amount of the position NFT a token account holds, under either token program.
+fn position_nft_amount(acc: &Account) -> Result<u64> {
+    if acc.owner == spl_token::ID {
+        Ok(SplTokenAccount::unpack_from_slice(&acc.data)
+            .context("decode position NFT token account")?
+            .amount)
+    } else if acc.owner == spl_token_2022::ID {
+        Ok(SplToken2022Account::unpack_from_slice(&acc.data)
+            .context("decode position NFT token account (2022)")?
+            .amount)
+    } else {
+        bail!(
+            "position NFT token account uses unsupported token program {}",
+            acc.owner
+        );
+    }
+}
+
+/// `owner`'s token account holding the position NFT `mint`, and its token program. The
+/// associated token accounts are read from `accounts` (see [`position_nft_atas`]); any
+/// other account holding the NFT is looked up by owner.
+async fn find_position_nft_account(
     rpc: &RpcClient,
+    accounts: &Prefetched,
     owner: &Pubkey,
     mint: &Pubkey,
 ) -> Result<(Pubkey, Pubkey)> {
-    let ata = get_associated_token_address_with_program_id(owner, mint, &spl_token::ID);
-    if let Some(acc) = rpc
-        .get_account_with_commitment(&ata, CommitmentConfig::processed())?
-        .value
-    {
-        let nft_state =
-            SplTokenAccount::unpack_from_slice(&acc.data).context("decode position NFT ATA")?;
-        if nft_state.amount > 0 {
+    for ata in position_nft_atas(owner, mint) {
+        if let Some(acc) = accounts.get(&ata)?
+            && position_nft_amount(acc)? > 0
+        {
             return Ok((ata, acc.owner));
         }
     }
 
-    let token_accounts =
-        rpc.get_token_accounts_by_owner(owner, TokenAccountsFilter::Mint(*mint))?;
-    for keyed in token_accounts {
-        let pk: Pubkey = keyed.pubkey.parse()?;
-        let acc = rpc.get_account(&pk)?;
-        let amount = if acc.owner == spl_token::ID {
-            SplTokenAccount::unpack_from_slice(&acc.data)
-                .context("decode position NFT token account")?
-                .amount
-        } else if acc.owner == spl_token_2022::ID {
-            SplToken2022Account::unpack_from_slice(&acc.data)
-                .context("decode position NFT token account (2022)")?
-                .amount
-        } else {
-            bail!(
-                "position NFT token account uses unsupported token program {}",
-                acc.owner
-            );
-        };
-        if amount > 0 {
+    let token_accounts = rpc
+        .get_token_accounts_by_owner(owner, TokenAccountsFilter::Mint(*mint))
+        .await?;
+    let keys = token_accounts
+        .iter()
+        .map(|keyed| keyed.pubkey.parse())
+        .collect::<Result<Vec<Pubkey>, _>>()?;
+    let token_accounts = Prefetched::fetch(rpc, &keys).await?;
+    for pk in keys {
+        if let Some(acc) = token_accounts.get(&pk)?
+            && position_nft_amount(acc)? > 0
+        {
             return Ok((pk, acc.owner));
         }
     }
@@ -217,52 +396,153 @@ fn find_position_nft_account(
     bail!("no token account holding the position NFT was found for the provided signer");
 }
 
-fn reward_remaining_accounts(
+/// Find a position NFT held by `owner` on exactly `[lower, upper]` of `pool_id`.
+/// Returns (nft mint, nft token account, personal position PDA).
+async fn find_wallet_position(
     rpc: &RpcClient,
+    owner: &Pubkey,
+    pool_id: &Pubkey,
+    lower: i32,
+    upper: i32,
+    program_id: &Pubkey,
+) -> Result<Option<(Pubkey, Pubkey, Pubkey)>> {
+    let accounts = pool_personal_positions(rpc, pool_id, Some((lower, upper)), program_id).await?;
+    debug!(
+        "{} personal positions on [{}, {}]",
+        accounts.len(),
+        lower,
+        upper
+    );
+
+    // Every candidate's NFT accounts, read in one round trip.
+    let candidates: Vec<(Pubkey, Pubkey)> = accounts
+        .iter()
+        .map(|(personal_pda, personal)| (to_sdk_pubkey(&personal.nft_mint), *personal_pda))
+        .collect();
+    let keys: Vec<Pubkey> = candidates
+        .iter()
+        .flat_map(|(nft_mint, _)| position_nft_atas(owner, nft_mint))
+        .collect();
+    let nft_accounts = Prefetched::fetch(rpc, &keys).await?;
+    for (nft_mint, personal_pda) in candidates {
+        for ata in position_nft_atas(owner, &nft_mint) {
+            if let Some(nft_acc) = nft_accounts.get(&ata)?
+                && position_nft_amount(nft_acc)? > 0
+            {
+                return Ok(Some((nft_mint, ata, personal_pda)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Every personal position on `pool_id`, or only those on exactly `range` when given.
+async fn pool_personal_positions(
+    rpc: &RpcClient,
+    pool_id: &Pubkey,
+    range: Option<(i32, i32)>,
+    program_id: &Pubkey,
+) -> Result<Vec<(Pubkey, CPersonalPosition)>> {
+    // Layout: 8-byte discriminator, 1-byte bump, nft_mint, pool_id, tick_lower, tick_upper.
+    let mut filters = vec![
+        RpcFilterType::DataSize(CPersonalPosition::LEN as u64),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(41, pool_id.to_bytes().to_vec())),
+    ];
+    if let Some((lower, upper)) = range {
+        let mut ticks = lower.to_le_bytes().to_vec();
+        ticks.extend_from_slice(&upper.to_le_bytes());
+        filters.push(RpcFilterType::Memcmp(Memcmp::new_raw_bytes(73, ticks)));
+    }
+    let config = RpcProgramAccountsConfig {
+        filters: Some(filters),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc
+        .get_program_accounts_with_config(program_id, config)
+        .await
+        .context("fetch personal positions for pool")?;
+    accounts
+        .into_iter()
+        .map(|(pda, acc)| Ok((pda, decode_personal_position_clmm(&acc.data)?)))
+        .collect()
+}
+
+/// List personal positions (anyone's) on `--pool`, optionally only on `--lower/--upper`.
+#[instrument(name = "find_positions", skip_all)]
+async fn handle_find_positions(
+    rpc: &RpcClient,
+    clmm_program_id: &Pubkey,
+    opts: &Opts,
+) -> Result<()> {
+    let pool_id = Pubkey::from_str(
+        opts.pool
+            .as_deref()
+            .context("--find-positions needs --pool")?,
+    )
+    .context("invalid pool id")?;
+    let range = match (opts.lower, opts.upper) {
+        (Some(lower), Some(upper)) => Some((lower, upper)),
+        (None, None) => None,
+        _ => bail!("pass both --lower and --upper, or neither"),
+    };
+    let mut positions = pool_personal_positions(rpc, &pool_id, range, clmm_program_id).await?;
+    positions.sort_by_key(|(_, p)| (p.tick_lower_index, p.tick_upper_index));
+
+    match range {
+        Some((lower, upper)) => println!(
+            "{} personal positions on [{}, {}] of {}:",
+            positions.len(),
+            lower,
+            upper,
+            pool_id
+        ),
+        None => println!("{} personal positions on {}:", positions.len(), pool_id),
+    }
+    for (pda, p) in &positions {
+        println!(
+            "  {} nft_mint={} range=[{}, {}] liquidity={} fees_owed={}/{}",
+            pda,
+            to_sdk_pubkey(&p.nft_mint),
+            p.tick_lower_index,
+            p.tick_upper_index,
+            p.liquidity,
+            p.token_fees_owed0,
+            p.token_fees_owed1
+        );
+    }
+    Ok(())
+}
+
+/// `(mint, vault)` of each reward the pool pays out.
+fn pool_rewards(pool: &CPoolState) -> Vec<(Pubkey, Pubkey)> {
+    pool.reward_infos
+        .iter()
+        .filter(|r| r.token_mint != RawPubkey::default() && r.token_vault != RawPubkey::default())
+        .map(|r| (to_sdk_pubkey(&r.token_mint), to_sdk_pubkey(&r.token_vault)))
+        .collect()
+}
+
+/// The vault, payer token account and mint of each of `rewards`, queueing any missing
+/// token account onto `ixs`. The reward mints and accounts are read from `accounts` (see
+/// [`amounts::token_keys`]).
+fn reward_remaining_accounts(
+    accounts: &Prefetched,
     payer: &Pubkey,
-    pool: &CPoolState,
+    rewards: &[(Pubkey, Pubkey)],
     ixs: &mut Vec<Instruction>,
 ) -> Result<Vec<AccountMeta>> {
+    let mints = rewards.iter().map(|(mint, _)| *mint).collect::<Vec<_>>();
+    let programs = amounts::token_programs(accounts, &mints)?;
+    let mints = mints.into_iter().zip(programs).collect::<Vec<_>>();
+    let atas = amounts::ensure_atas(accounts, ixs, payer, &mints)?;
+
     let mut rem: Vec<AccountMeta> = Vec::new();
-    for reward in pool.reward_infos.iter() {
-        if reward.token_mint == RawPubkey::default() || reward.token_vault == RawPubkey::default() {
-            continue;
-        }
-        let reward_mint = to_sdk_pubkey(&reward.token_mint);
-        let reward_vault = to_sdk_pubkey(&reward.token_vault);
-        eprintln!(
-            "[debug] reward slot: vault={} mint={}",
-            reward_vault, reward_mint
-        );
-        let mint_owner = rpc
-            .get_account(&reward_mint)
-            .map(|a| a.owner)
-            .unwrap_or_else(|e| {
-                eprintln!(
-                    "[warn] reward mint {} not fetchable ({}); defaulting to SPL Token",
-                    reward_mint, e
-                );
-                spl_token::ID
-            });
-        let reward_program = if mint_owner == spl_token::ID {
-            spl_token::ID
-        } else {
-            spl_token_2022::ID
-        };
-        let user_ata =
-            get_associated_token_address_with_program_id(payer, &reward_mint, &reward_program);
-        if rpc
-            .get_account_with_commitment(&user_ata, CommitmentConfig::processed())?
-            .value
-            .is_none()
-        {
-            ixs.push(create_associated_token_account(
-                payer,
-                payer,
-                &reward_mint,
-                &reward_program,
-            ));
-        }
+    for ((reward_mint, reward_vault), (user_ata, _)) in rewards.iter().copied().zip(atas) {
+        debug!("reward slot: vault={} mint={}", reward_vault, reward_mint);
         rem.push(AccountMeta::new(reward_vault, false));
         rem.push(AccountMeta::new(user_ata, false));
         rem.push(AccountMeta::new_readonly(reward_mint, false));
@@ -270,312 +550,1387 @@ fn reward_remaining_accounts(
     Ok(rem)
 }
 
-fn handle_remove_all(
+#[instrument(name = "remove", skip_all)]
+async fn handle_remove_all(
     rpc: &RpcClient,
     clmm_program_id: &Pubkey,
     memo_program_id: &Pubkey,
-    payer: &Keypair,
+    flow: &mut Flow<'_>,
     payer_pk: &Pubkey,
     pos_mint_str: &str,
     opts: &Opts,
     ixs: &mut Vec<Instruction>,
+    collect_only: bool,
+) -> Result<Sent> {
+    let removal = queue_removal(
+        rpc,
+        clmm_program_id,
+        memo_program_id,
+        payer_pk,
+        pos_mint_str,
+        opts,
+        ixs,
+        collect_only,
+    )
+    .await?;
+    let position_mint = removal.position_mint;
+    let sent = flow.send(ixs.clone(), &[]).await?;
+    if let Some(sig) = sent.signature() {
+        if collect_only {
+            println!(
+                "✅ Collected fees and rewards for position {}. Tx: {}",
+                position_mint, sig
+            );
+        } else if removal.liquidity < removal.position_liquidity {
+            println!(
+                "✅ Removed {} of {} liquidity for position {}. Tx: {}",
+                removal.liquidity, removal.position_liquidity, position_mint, sig
+            );
+        } else {
+            println!(
+                "✅ Removed all liquidity{} for position {}. Tx: {}",
+                if opts.close { " and closed" } else { "" },
+                position_mint,
+                sig
+            );
+        }
+    }
+
+    if opts.unwrap_sol
+        && let Some(sig) = flow
+            .send(vec![build_unwrap_sol_ix(payer_pk)], &[])
+            .await?
+            .signature()
+    {
+        println!("✅ Unwrapped WSOL. Tx: {}", sig);
+    }
+
+    Ok(sent)
+}
+
+/// Queue the `collect`/`remove` that `opts` asks for onto `ixs` without sending, for
+/// `--positions-file` rows that share a transaction.
+pub(crate) async fn removal_ixs(
+    rpc: &RpcClient,
+    payer_pk: &Pubkey,
+    opts: &Opts,
+    ixs: &mut Vec<Instruction>,
 ) -> Result<()> {
+    let memo_program_id = Pubkey::from_str(MEMO_PROGRAM_ID)?;
+    let (position, collect_only) = match opts.mode()? {
+        Mode::Collect(position) => (position, true),
+        Mode::Remove(position) => (position, false),
+        _ => bail!("only collect and remove can share a transaction"),
+    };
+    queue_removal(
+        rpc,
+        &clmm_program_id()?,
+        &memo_program_id,
+        payer_pk,
+        &position,
+        opts,
+        ixs,
+        collect_only,
+    )
+    .await
+    .map(drop)
+}
+
+/// What [`queue_removal`] queued.
+struct Removal {
+    position_mint: Pubkey,
+    /// Liquidity taken out (0 when only collecting).
+    liquidity: u128,
+    /// Liquidity the position held before.
+    position_liquidity: u128,
+}
+
+/// Queue the DecreaseLiquidity (and ClosePosition with `--close`) for `pos_mint_str` onto
+/// `ixs`, with any token accounts they pay into.
+async fn queue_removal(
+    rpc: &RpcClient,
+    clmm_program_id: &Pubkey,
+    memo_program_id: &Pubkey,
+    payer_pk: &Pubkey,
+    pos_mint_str: &str,
+    opts: &Opts,
+    ixs: &mut Vec<Instruction>,
+    collect_only: bool,
+) -> Result<Removal> {
     let position_mint = Pubkey::from_str(pos_mint_str).context("invalid position NFT mint")?;
+    if opts.nft_owner.is_some() {
+        bail!(
+            "--nft-owner is not supported on Raydium: the CLMM program requires the NFT owner to sign"
+        );
+    }
 
+    // The position and the payer's NFT accounts, then the pool, then the token accounts
+    // the removal pays into: each read needs the one before it.
     let (personal_position_pda, _) = derive_personal_position_pda(&position_mint, clmm_program_id);
-    let personal_acc = rpc
-        .get_account(&personal_position_pda)
-        .context("fetch personal_position")?;
+    let mut keys = vec![personal_position_pda];
+    keys.extend(position_nft_atas(payer_pk, &position_mint));
+    let position_accounts = Prefetched::fetch(rpc, &keys).await?;
+    let personal_acc = position_accounts.require(&personal_position_pda, "personal_position")?;
     if personal_acc.owner != *clmm_program_id {
         bail!("personal_position account owner mismatch (expected Raydium CLMM program)");
     }
-    eprintln!(
-        "[debug] personal_position len={} lamports={}",
+    debug!(
+        "personal_position len={} lamports={}",
         personal_acc.data.len(),
         personal_acc.lamports
     );
     let personal = decode_personal_position_clmm(&personal_acc.data)?;
-    if personal.liquidity == 0 {
+    if personal.liquidity == 0 && !collect_only {
         bail!("position has zero liquidity — nothing to remove");
     }
     let pool_id = to_sdk_pubkey(&personal.pool_id);
 
-    let pool_acc = rpc.get_account(&pool_id).context("fetch pool")?;
+    let pool_acc = rpc.get_account(&pool_id).await.context("fetch pool")?;
     if pool_acc.owner != *clmm_program_id {
         bail!("pool account owner mismatch (expected Raydium CLMM program)");
     }
-    eprintln!(
-        "[debug] pool len={} owner={}",
-        pool_acc.data.len(),
-        pool_acc.owner
-    );
+    debug!("pool len={} owner={}", pool_acc.data.len(), pool_acc.owner);
     let pool = decode_pool_clmm(&pool_acc.data)?;
     let token_mint0 = to_sdk_pubkey(&pool.token_mint0);
     let token_mint1 = to_sdk_pubkey(&pool.token_mint1);
-    let token_vault0 = to_sdk_pubkey(&pool.token_vault0);
-    let token_vault1 = to_sdk_pubkey(&pool.token_vault1);
-    eprintln!(
-        "[debug] pool tick_spacing={} tick_lo={} tick_hi={} liquidity_in_position={}",
+    debug!(
+        "pool tick_spacing={} tick_lo={} tick_hi={} liquidity_in_position={}",
         pool.tick_spacing, personal.tick_lower_index, personal.tick_upper_index, personal.liquidity
     );
 
-    let token_program0 = rpc
-        .get_account(&token_mint0)
-        .map(|a| a.owner)
-        .unwrap_or_else(|e| {
-            eprintln!(
-                "[warn] mint0 {} not fetchable ({}); defaulting to SPL Token",
-                token_mint0, e
-            );
-            spl_token::ID
-        });
-    let token_program0 = if token_program0 == spl_token::ID {
-        spl_token::ID
-    } else {
-        spl_token_2022::ID
-    };
-    let token_program1 = rpc
-        .get_account(&token_mint1)
-        .map(|a| a.owner)
-        .unwrap_or_else(|e| {
-            eprintln!(
-                "[warn] mint1 {} not fetchable ({}); defaulting to SPL Token",
-                token_mint1, e
-            );
-            spl_token::ID
-        });
-    let token_program1 = if token_program1 == spl_token::ID {
-        spl_token::ID
-    } else {
-        spl_token_2022::ID
-    };
-
-    let ata0 =
-        get_associated_token_address_with_program_id(payer_pk, &token_mint0, &token_program0);
-    let ata1 =
-        get_associated_token_address_with_program_id(payer_pk, &token_mint1, &token_program1);
-    if rpc
-        .get_account_with_commitment(&ata0, CommitmentConfig::processed())?
-        .value
-        .is_none()
-    {
-        ixs.push(create_associated_token_account(
-            payer_pk,
-            payer_pk,
-            &token_mint0,
-            &token_program0,
-        ));
-    }
-    if rpc
-        .get_account_with_commitment(&ata1, CommitmentConfig::processed())?
-        .value
-        .is_none()
-    {
-        ixs.push(create_associated_token_account(
-            payer_pk,
-            payer_pk,
-            &token_mint1,
-            &token_program1,
-        ));
-    }
+    let rewards = pool_rewards(&pool);
+    let mut mints = vec![token_mint0, token_mint1];
+    mints.extend(rewards.iter().map(|(mint, _)| *mint));
+    let token_accounts = Prefetched::fetch(rpc, &amounts::token_keys(payer_pk, &mints)).await?;
+    let programs = amounts::token_programs(&token_accounts, &[token_mint0, token_mint1])?;
+    let (token_program0, token_program1) = (programs[0], programs[1]);
 
-    let lower = personal.tick_lower_index;
-    let upper = personal.tick_upper_index;
-    let lower_start = tick_array_start_index(lower, pool.tick_spacing);
-    let upper_start = tick_array_start_index(upper, pool.tick_spacing);
-    let (tick_array_lower_pda, _) = derive_tick_array_pda(&pool_id, lower_start, clmm_program_id);
-    let (tick_array_upper_pda, _) = derive_tick_array_pda(&pool_id, upper_start, clmm_program_id);
-    let (protocol_position_pda, _) =
-        derive_protocol_position_pda(&pool_id, lower, upper, clmm_program_id);
+    let accounts = PositionAccounts::new(
+        clmm_program_id,
+        &pool_id,
+        &pool,
+        payer_pk,
+        (personal.tick_lower_index, personal.tick_upper_index),
+        (token_program0, token_program1),
+    );
+    amounts::ensure_atas(
+        &token_accounts,
+        ixs,
+        payer_pk,
+        &[(token_mint0, token_program0), (token_mint1, token_program1)],
+    )?;
 
     let (position_nft_ata, position_nft_program) =
-        find_position_nft_account(rpc, payer_pk, &position_mint)?;
-    eprintln!("[debug] position NFT account used: {}", position_nft_ata);
+        find_position_nft_account(rpc, &position_accounts, payer_pk, &position_mint).await?;
+    debug!("position NFT account used: {}", position_nft_ata);
 
-    let reward_accounts = reward_remaining_accounts(rpc, payer_pk, &pool, ixs)?;
-    eprintln!(
-        "[debug] reward groups added: {} ({} accounts)",
+    let reward_accounts = reward_remaining_accounts(&token_accounts, payer_pk, &rewards, ixs)?;
+    debug!(
+        "reward groups added: {} ({} accounts)",
         reward_accounts.len() / 3,
         reward_accounts.len()
     );
 
-    let dec_accounts = r_accounts::DecreaseLiquidityV2 {
-        nft_owner: *payer_pk,
-        nft_account: position_nft_ata,
-        personal_position: personal_position_pda,
-        pool_state: pool_id,
-        protocol_position: protocol_position_pda,
-        token_vault_0: token_vault0,
-        token_vault_1: token_vault1,
-        tick_array_lower: tick_array_lower_pda,
-        tick_array_upper: tick_array_upper_pda,
-        recipient_token_account_0: ata0,
-        recipient_token_account_1: ata1,
-        token_program: position_nft_program,
-        token_program_2022: spl_token_2022::ID,
-        memo_program: *memo_program_id,
-        vault_0_mint: token_mint0,
-        vault_1_mint: token_mint1,
+    // A zero-liquidity decrease settles the position and pays out owed fees and rewards.
+    let liquidity = if collect_only {
+        0
+    } else {
+        removal_liquidity(opts, personal.liquidity)?
     };
-    let dec_data = r_ix::DecreaseLiquidityV2 {
-        liquidity: personal.liquidity,
-        amount_0_min: opts.min_out0,
-        amount_1_min: opts.min_out1,
-    }
-    .data();
-    let mut dec_metas = dec_accounts.to_account_metas(None);
-    dec_metas.extend(reward_accounts);
-    ixs.push(Instruction {
-        program_id: *clmm_program_id,
-        accounts: dec_metas,
-        data: dec_data,
-    });
+    let partial = liquidity < personal.liquidity;
+    let (amount_0_min, amount_1_min) = if collect_only {
+        (0, 0)
+    } else if let Some(bps) = opts.slippage_bps {
+        let (amount0, amount1) = r_libs::liquidity_math::get_delta_amounts_signed(
+            pool.tick_current,
+            pool.sqrt_price_x64,
+            accounts.lower,
+            accounts.upper,
+            -(liquidity as i128),
+        )
+        .context("expected amounts for removed liquidity")?;
+        let min = |amount: u64| apply_slippage(amount, bps);
+        debug!(
+            "expected out amount0={} amount1={}; min with {} bps slippage: {} / {}",
+            amount0,
+            amount1,
+            bps,
+            min(amount0),
+            min(amount1)
+        );
+        (
+            opts.min_out0.max(min(amount0)),
+            opts.min_out1.max(min(amount1)),
+        )
+    } else {
+        (opts.min_out0, opts.min_out1)
+    };
+    ixs.push(build_decrease_liquidity_ix(
+        &accounts,
+        &position_mint,
+        (position_nft_ata, position_nft_program),
+        memo_program_id,
+        liquidity,
+        (amount_0_min, amount_1_min),
+        reward_accounts,
+    ));
 
-    if opts.close {
-        let close_accounts = r_accounts::ClosePosition {
-            nft_owner: *payer_pk,
-            position_nft_mint: position_mint,
-            position_nft_account: position_nft_ata,
-            personal_position: personal_position_pda,
-            system_program: solana_sdk::system_program::id(),
-            token_program: position_nft_program,
-        };
-        let close_ix = Instruction {
-            program_id: *clmm_program_id,
-            accounts: close_accounts.to_account_metas(None),
-            data: r_ix::ClosePosition {}.data(),
-        };
-        ixs.push(close_ix);
+    if opts.close && partial && !collect_only {
+        warn!("partial removal leaves liquidity in the position; skipping --close");
+    }
+    if opts.close && !partial {
+        ixs.push(build_close_position_ix(
+            &accounts,
+            &position_mint,
+            (position_nft_ata, position_nft_program),
+        ));
     }
 
-    let sig = simulate_and_send(rpc, payer, ixs.clone(), &[payer])?;
-    println!(
-        "✅ Removed all liquidity{} for position {}. Tx: {}",
-        if opts.close { " and closed" } else { "" },
+    Ok(Removal {
         position_mint,
-        sig
-    );
+        liquidity,
+        position_liquidity: personal.liquidity,
+    })
+}
 
-    if opts.unwrap_sol {
-        let unwrap_ix = build_unwrap_sol_ix(payer_pk);
-        let sig_unwrap = simulate_and_send(rpc, payer, vec![unwrap_ix], &[payer])?;
-        println!("✅ Unwrapped WSOL. Tx: {}", sig_unwrap);
-    }
+// ----------------------------- Instruction builders -----------------------------
 
-    Ok(())
+/// The accounts every position instruction on `[lower, upper]` of a pool shares. The
+/// builders below take these and use no RPC, so other programs can reuse them.
+#[derive(Clone, Copy, Debug)]
+pub struct PositionAccounts {
+    pub program_id: Pubkey,
+    pub pool_id: Pubkey,
+    /// Signer that pays, and owner of `ata0`/`ata1` that the amounts move through.
+    pub payer: Pubkey,
+    pub lower: i32,
+    pub upper: i32,
+    pub tick_spacing: u16,
+    pub mint0: Pubkey,
+    pub mint1: Pubkey,
+    pub vault0: Pubkey,
+    pub vault1: Pubkey,
+    pub ata0: Pubkey,
+    pub ata1: Pubkey,
 }
 
-fn fetch_token_amount(rpc: &RpcClient, ata: &Pubkey) -> Result<u64> {
-    let acc = rpc
-        .get_account(ata)
-        .with_context(|| format!("fetch token account {}", ata))?;
-    if acc.owner == spl_token::ID {
-        let state =
-            SplTokenAccount::unpack_from_slice(&acc.data).context("decode SPL token account")?;
-        return Ok(state.amount);
-    }
-    if acc.owner == spl_token_2022::ID {
-        let state = SplToken2022Account::unpack_from_slice(&acc.data)
-            .context("decode SPL token-2022 account")?;
-        return Ok(state.amount);
-    }
-    bail!(
-        "token account {} owned by unexpected program {}",
-        ata,
-        acc.owner
-    );
-}
+impl PositionAccounts {
+    /// `payer`'s accounts for `[lower, upper]` on `pool`; `token_programs` are the programs
+    /// that own `token_mint0`/`token_mint1`.
+    pub fn new(
+        program_id: &Pubkey,
+        pool_id: &Pubkey,
+        pool: &CPoolState,
+        payer: &Pubkey,
+        (lower, upper): (i32, i32),
+        (token_program0, token_program1): (Pubkey, Pubkey),
+    ) -> Self {
+        let mint0 = to_sdk_pubkey(&pool.token_mint0);
+        let mint1 = to_sdk_pubkey(&pool.token_mint1);
+        Self {
+            program_id: *program_id,
+            pool_id: *pool_id,
+            payer: *payer,
+            lower,
+            upper,
+            tick_spacing: pool.tick_spacing,
+            mint0,
+            mint1,
+            vault0: to_sdk_pubkey(&pool.token_vault0),
+            vault1: to_sdk_pubkey(&pool.token_vault1),
+            ata0: get_associated_token_address_with_program_id(payer, &mint0, &token_program0),
+            ata1: get_associated_token_address_with_program_id(payer, &mint1, &token_program1),
+        }
+    }
 
-fn handle_swap(
-    rpc: &RpcClient,
-    clmm_program_id: &Pubkey,
-    payer: &Keypair,
-    payer_pk: &Pubkey,
-    pool_str: &str,
-    opts: &Opts,
-    ixs: &mut Vec<Instruction>,
-) -> Result<()> {
-    if opts.swap_amount_in == 0 {
-        bail!("--swap-amount-in must be > 0");
+    /// Start indices and addresses of the tick arrays holding `lower` and `upper`.
+    fn tick_arrays(&self) -> ((i32, Pubkey), (i32, Pubkey)) {
+        let lower_start = tick_array_start_index(self.lower, self.tick_spacing);
+        let upper_start = tick_array_start_index(self.upper, self.tick_spacing);
+        (
+            (
+                lower_start,
+                derive_tick_array_pda(&self.pool_id, lower_start, &self.program_id).0,
+            ),
+            (
+                upper_start,
+                derive_tick_array_pda(&self.pool_id, upper_start, &self.program_id).0,
+            ),
+        )
     }
-    let pool_id = Pubkey::from_str(pool_str).context("invalid swap pool id")?;
-    let pool_acc = rpc.get_account(&pool_id).context("fetch pool account")?;
-    if pool_acc.owner != *clmm_program_id {
-        bail!("pool account owner mismatch (expected Raydium CLMM program)");
+
+    fn protocol_position(&self) -> Pubkey {
+        derive_protocol_position_pda(&self.pool_id, self.lower, self.upper, &self.program_id).0
     }
-    let pool = decode_pool_clmm(&pool_acc.data)?;
-    let token_mint0 = to_sdk_pubkey(&pool.token_mint0);
-    let token_mint1 = to_sdk_pubkey(&pool.token_mint1);
-    let token_vault0 = to_sdk_pubkey(&pool.token_vault0);
-    let token_vault1 = to_sdk_pubkey(&pool.token_vault1);
-    let amm_config = to_sdk_pubkey(&pool.amm_config);
-    let observation_state = to_sdk_pubkey(&pool.observation_key);
+}
 
-    let (input_mint, output_mint, input_vault, output_vault) = if opts.swap_a_to_b {
-        (token_mint0, token_mint1, token_vault0, token_vault1)
+/// Liquidity that `amount0`/`amount1` (either may be 0) buy on `[lower, upper]` at the
+/// pool's current price.
+pub fn liquidity_for_amounts(
+    pool: &CPoolState,
+    (lower, upper): (i32, i32),
+    amount0: u64,
+    amount1: u64,
+) -> Result<u128> {
+    let sqrt_ratio_x64 = pool.sqrt_price_x64;
+    let sqrt_a_x64 =
+        r_libs::tick_math::get_sqrt_price_at_tick(lower).context("sqrt_at_tick lower")?;
+    let sqrt_b_x64 =
+        r_libs::tick_math::get_sqrt_price_at_tick(upper).context("sqrt_at_tick upper")?;
+    let (sqrt_lo, sqrt_hi) = if sqrt_a_x64 < sqrt_b_x64 {
+        (sqrt_a_x64, sqrt_b_x64)
     } else {
-        (token_mint1, token_mint0, token_vault1, token_vault0)
+        (sqrt_b_x64, sqrt_a_x64)
     };
 
-    let input_program = rpc
-        .get_account(&input_mint)
-        .map(|a| a.owner)
-        .unwrap_or_else(|e| {
-            eprintln!(
-                "[warn] input mint {} not fetchable ({}); defaulting to SPL Token",
-                input_mint, e
+    let liquidity = if amount0 > 0 && amount1 == 0 {
+        if sqrt_ratio_x64 >= sqrt_hi {
+            bail!(
+                "Your current price is ABOVE the range; token0-only cannot open here (range needs token1). Choose a higher range or provide token1."
             );
-            spl_token::ID
-        });
-    let output_program = rpc
-        .get_account(&output_mint)
-        .map(|a| a.owner)
-        .unwrap_or_else(|e| {
-            eprintln!(
-                "[warn] output mint {} not fetchable ({}); defaulting to SPL Token",
-                output_mint, e
+        }
+        r_libs::liquidity_math::get_liquidity_from_single_amount_0(
+            sqrt_ratio_x64,
+            sqrt_lo,
+            sqrt_hi,
+            amount0,
+        )
+    } else if amount1 > 0 && amount0 == 0 {
+        if sqrt_ratio_x64 <= sqrt_lo {
+            bail!(
+                "Your current price is BELOW the range; token1-only cannot open here (range needs token0). Choose a lower range or provide token0."
             );
-            spl_token::ID
-        });
-    if input_program != spl_token::ID || output_program != spl_token::ID {
-        bail!(
-            "swap_v1 only supports SPL Token mints (no token-2022); input owner {}, output owner {}",
-            input_program,
-            output_program
-        );
+        }
+        r_libs::liquidity_math::get_liquidity_from_single_amount_1(
+            sqrt_ratio_x64,
+            sqrt_lo,
+            sqrt_hi,
+            amount1,
+        )
+    } else {
+        r_libs::liquidity_math::get_liquidity_from_amounts(
+            sqrt_ratio_x64,
+            sqrt_lo,
+            sqrt_hi,
+            amount0,
+            amount1,
+        )
+    };
+
+    if liquidity == 0 {
+        bail!(
+            "computed liquidity is zero — adjust amounts or pick a range closer to the current price"
+        );
     }
+    Ok(liquidity)
+}
 
-    let ata_in =
-        get_associated_token_address_with_program_id(payer_pk, &input_mint, &spl_token::ID);
-    let ata_out =
-        get_associated_token_address_with_program_id(payer_pk, &output_mint, &spl_token::ID);
-    if rpc
-        .get_account_with_commitment(&ata_in, CommitmentConfig::processed())?
-        .value
-        .is_none()
-    {
-        ixs.push(create_associated_token_account(
-            payer_pk,
-            payer_pk,
-            &input_mint,
+/// OpenPositionV2 minting `position_mint` (which must sign) to `owner` with `liquidity`,
+/// paying at most `amount_max` of each token.
+pub fn build_open_position_ix(
+    accounts: &PositionAccounts,
+    owner: &Pubkey,
+    position_mint: &Pubkey,
+    liquidity: u128,
+    (amount_0_max, amount_1_max): (u64, u64),
+) -> Instruction {
+    let ((lower_start, tick_array_lower), (upper_start, tick_array_upper)) = accounts.tick_arrays();
+    let (metadata_account, _bump) = mpl_token_metadata::pda::find_metadata_account(position_mint);
+    let metas = r_accounts::OpenPositionV2 {
+        payer: accounts.payer,
+        position_nft_owner: *owner,
+        position_nft_mint: *position_mint,
+        position_nft_account: get_associated_token_address_with_program_id(
+            owner,
+            position_mint,
             &spl_token::ID,
-        ));
+        ),
+        metadata_account,
+        pool_state: accounts.pool_id,
+        protocol_position: accounts.protocol_position(),
+        tick_array_lower,
+        tick_array_upper,
+        personal_position: derive_personal_position_pda(position_mint, &accounts.program_id).0,
+        token_account_0: accounts.ata0,
+        token_account_1: accounts.ata1,
+        token_vault_0: accounts.vault0,
+        token_vault_1: accounts.vault1,
+        rent: sysvar::rent::id(),
+        system_program: solana_sdk::system_program::id(),
+        token_program: spl_token::ID,
+        associated_token_program: ASSOCIATED_TOKEN_PROGRAM_ID,
+        metadata_program: METADATA_PROGRAM_ID,
+        token_program_2022: spl_token_2022::ID,
+        vault_0_mint: accounts.mint0,
+        vault_1_mint: accounts.mint1,
+    };
+    let data = r_ix::OpenPositionV2 {
+        tick_lower_index: accounts.lower,
+        tick_upper_index: accounts.upper,
+        tick_array_lower_start_index: lower_start,
+        tick_array_upper_start_index: upper_start,
+        liquidity,
+        amount_0_max,
+        amount_1_max,
+        with_matedata: true,
+        base_flag: None,
+    }
+    .data();
+    Instruction {
+        program_id: accounts.program_id,
+        accounts: metas.to_account_metas(None),
+        data,
+    }
+}
+
+/// IncreaseLiquidityV2 on the payer's position `position_mint`, whose NFT sits in `nft_account`.
+pub fn build_increase_liquidity_ix(
+    accounts: &PositionAccounts,
+    position_mint: &Pubkey,
+    nft_account: &Pubkey,
+    liquidity: u128,
+    (amount_0_max, amount_1_max): (u64, u64),
+) -> Instruction {
+    let ((_, tick_array_lower), (_, tick_array_upper)) = accounts.tick_arrays();
+    let metas = r_accounts::IncreaseLiquidityV2 {
+        nft_owner: accounts.payer,
+        nft_account: *nft_account,
+        pool_state: accounts.pool_id,
+        protocol_position: accounts.protocol_position(),
+        personal_position: derive_personal_position_pda(position_mint, &accounts.program_id).0,
+        tick_array_lower,
+        tick_array_upper,
+        token_account_0: accounts.ata0,
+        token_account_1: accounts.ata1,
+        token_vault_0: accounts.vault0,
+        token_vault_1: accounts.vault1,
+        token_program: spl_token::ID,
+        token_program_2022: spl_token_2022::ID,
+        vault_0_mint: accounts.mint0,
+        vault_1_mint: accounts.mint1,
+    };
+    let data = r_ix::IncreaseLiquidityV2 {
+        liquidity,
+        amount_0_max,
+        amount_1_max,
+        base_flag: None,
+    }
+    .data();
+    Instruction {
+        program_id: accounts.program_id,
+        accounts: metas.to_account_metas(None),
+        data,
+    }
+}
+
+/// DecreaseLiquidityV2 of `liquidity` (0 only collects fees and rewards) from the payer's
+/// position `position_mint`, receiving at least `amount_min` of each token.
+/// `reward_accounts` are the (vault, recipient, mint) metas of the pool's live rewards.
+pub fn build_decrease_liquidity_ix(
+    accounts: &PositionAccounts,
+    position_mint: &Pubkey,
+    (nft_account, nft_token_program): (Pubkey, Pubkey),
+    memo_program: &Pubkey,
+    liquidity: u128,
+    (amount_0_min, amount_1_min): (u64, u64),
+    reward_accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let ((_, tick_array_lower), (_, tick_array_upper)) = accounts.tick_arrays();
+    let metas = r_accounts::DecreaseLiquidityV2 {
+        nft_owner: accounts.payer,
+        nft_account,
+        personal_position: derive_personal_position_pda(position_mint, &accounts.program_id).0,
+        pool_state: accounts.pool_id,
+        protocol_position: accounts.protocol_position(),
+        token_vault_0: accounts.vault0,
+        token_vault_1: accounts.vault1,
+        tick_array_lower,
+        tick_array_upper,
+        recipient_token_account_0: accounts.ata0,
+        recipient_token_account_1: accounts.ata1,
+        token_program: nft_token_program,
+        token_program_2022: spl_token_2022::ID,
+        memo_program: *memo_program,
+        vault_0_mint: accounts.mint0,
+        vault_1_mint: accounts.mint1,
+    };
+    let data = r_ix::DecreaseLiquidityV2 {
+        liquidity,
+        amount_0_min,
+        amount_1_min,
+    }
+    .data();
+    let mut metas = metas.to_account_metas(None);
+    metas.extend(reward_accounts);
+    Instruction {
+        program_id: accounts.program_id,
+        accounts: metas,
+        data,
+    }
+}
+
+/// ClosePosition: burn the payer's empty position NFT `position_mint` and reclaim its rent.
+pub fn build_close_position_ix(
+    accounts: &PositionAccounts,
+    position_mint: &Pubkey,
+    (nft_account, nft_token_program): (Pubkey, Pubkey),
+) -> Instruction {
+    let metas = r_accounts::ClosePosition {
+        nft_owner: accounts.payer,
+        position_nft_mint: *position_mint,
+        position_nft_account: nft_account,
+        personal_position: derive_personal_position_pda(position_mint, &accounts.program_id).0,
+        system_program: solana_sdk::system_program::id(),
+        token_program: nft_token_program,
+    };
+    Instruction {
+        program_id: accounts.program_id,
+        accounts: metas.to_account_metas(None),
+        data: r_ix::ClosePosition {}.data(),
+    }
+}
+
+// ----------------------------- Resize Position -----------------------------
+
+/// Remove all liquidity from a position and close it, then open a new position on the same
+/// pool over the requested range with the tokens that came out (fees included).
+/// Two transactions: if the open fails, the removed tokens stay in the wallet.
+#[instrument(name = "resize", skip_all)]
+async fn handle_resize(
+    rpc: &RpcClient,
+    clmm_program_id: &Pubkey,
+    memo_program_id: &Pubkey,
+    flow: &mut Flow<'_>,
+    payer_pk: &Pubkey,
+    pos_mint_str: &str,
+    opts: Opts,
+    mut ixs: Vec<Instruction>,
+) -> Result<()> {
+    // Check the new range before anything is removed.
+    if opts.price_range()?.is_none() && (opts.lower.is_none() || opts.upper.is_none()) {
+        bail!(
+            "--resize-position needs the new range: --lower/--upper, --price-min/--price-max or --width"
+        );
+    }
+    let position_mint = Pubkey::from_str(pos_mint_str).context("invalid position NFT mint")?;
+    let (personal_position_pda, _) = derive_personal_position_pda(&position_mint, clmm_program_id);
+    let personal_acc = rpc
+        .get_account(&personal_position_pda)
+        .await
+        .context("fetch personal_position")?;
+    let personal = decode_personal_position_clmm(&personal_acc.data)?;
+    let pool_id = to_sdk_pubkey(&personal.pool_id);
+    let pool_acc = rpc.get_account(&pool_id).await.context("fetch pool")?;
+    let pool = decode_pool_clmm(&pool_acc.data)?;
+    info!(
+        "resizing position {} on pool {} from [{}, {}]",
+        position_mint, pool_id, personal.tick_lower_index, personal.tick_upper_index
+    );
+
+    let mints = [
+        to_sdk_pubkey(&pool.token_mint0),
+        to_sdk_pubkey(&pool.token_mint1),
+    ];
+    let token_accounts = Prefetched::fetch(rpc, &amounts::token_keys(payer_pk, &mints)).await?;
+    let programs = amounts::token_programs(&token_accounts, &mints)?;
+    let atas = [
+        get_associated_token_address_with_program_id(payer_pk, &mints[0], &programs[0]),
+        get_associated_token_address_with_program_id(payer_pk, &mints[1], &programs[1]),
+    ];
+    let before = [
+        token_accounts.token_balance(&atas[0])?.unwrap_or(0),
+        token_accounts.token_balance(&atas[1])?.unwrap_or(0),
+    ];
+
+    let mut remove_opts = opts.clone();
+    remove_opts.close = true;
+    remove_opts.remove_liquidity = None;
+    remove_opts.remove_percent = None;
+    // Keep WSOL wrapped so it can go straight back into the new position.
+    remove_opts.unwrap_sol = false;
+    let removed = handle_remove_all(
+        rpc,
+        clmm_program_id,
+        memo_program_id,
+        flow,
+        payer_pk,
+        pos_mint_str,
+        &remove_opts,
+        &mut ixs,
+        false,
+    )
+    .await?;
+
+    // Unless the removal landed, reopen with what its simulation paid out.
+    let (amount0, amount1) = match removed.unsent_preview() {
+        Some(preview) => (preview.gain(&atas[0]), preview.gain(&atas[1])),
+        None => {
+            flow.flush().await?;
+            let after = token_amounts(rpc, &atas).await?;
+            (
+                after[0].saturating_sub(before[0]),
+                after[1].saturating_sub(before[1]),
+            )
+        }
+    };
+    info!(
+        "removed {} token0 / {} token1; reopening on the new range",
+        amounts::format_amount(rpc, &to_sdk_pubkey(&pool.token_mint0), amount0).await,
+        amounts::format_amount(rpc, &to_sdk_pubkey(&pool.token_mint1), amount1).await
+    );
+
+    let mut open_opts = opts;
+    open_opts.pool = Some(pool_id.to_string());
+    open_opts.amount0 = amount0;
+    open_opts.amount1 = amount1;
+    let ixs = vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(open_opts.cu_limit),
+        ComputeBudgetInstruction::set_compute_unit_price(open_opts.effective_cu_price()),
+    ];
+    handle_open(rpc, clmm_program_id, flow, payer_pk, open_opts, ixs).await
+}
+
+/// Balances of token accounts, 0 for those that do not exist yet.
+async fn token_amounts(rpc: &RpcClient, atas: &[Pubkey]) -> Result<Vec<u64>> {
+    Ok(
+        amounts::fetch_token_balances(rpc, atas, CommitmentConfig::confirmed())
+            .await?
+            .into_iter()
+            .map(|b| b.unwrap_or(0))
+            .collect(),
+    )
+}
+
+/// Liquidity to take out of a position holding `liquidity`: all of it unless
+/// --remove-liquidity or --remove-percent asks for a partial exit.
+fn removal_liquidity(opts: &Opts, liquidity: u128) -> Result<u128> {
+    let amount = match (opts.remove_liquidity, opts.remove_percent) {
+        (Some(_), Some(_)) => bail!("pass either --remove-liquidity or --remove-percent, not both"),
+        (Some(l), None) => l,
+        (None, Some(p)) if p > 100 => bail!("--remove-percent must be between 1 and 100"),
+        (None, Some(p)) => {
+            let p = p as u128;
+            liquidity / 100 * p + liquidity % 100 * p / 100
+        }
+        (None, None) => liquidity,
+    };
+    if amount == 0 || amount > liquidity {
+        bail!(
+            "liquidity to remove ({}) must be between 1 and the position's {}",
+            amount,
+            liquidity
+        );
+    }
+    Ok(amount)
+}
+
+#[instrument(name = "twap", skip_all)]
+async fn handle_twap(
+    rpc: &RpcClient,
+    clmm_program_id: &Pubkey,
+    pool_str: &str,
+    window_secs: u64,
+) -> Result<()> {
+    let pool_id = Pubkey::from_str(pool_str).context("invalid --twap-pool")?;
+    let pool_acc = rpc
+        .get_account(&pool_id)
+        .await
+        .context("fetch pool account")?;
+    if pool_acc.owner != *clmm_program_id {
+        bail!("pool account owner mismatch (expected Raydium CLMM program)");
+    }
+    let pool = decode_pool_clmm(&pool_acc.data)?;
+    let observation_key = to_sdk_pubkey(&pool.observation_key);
+    let obs_acc = rpc
+        .get_account(&observation_key)
+        .await
+        .context("fetch observation state")?;
+    let obs = decode_observation_clmm(&obs_acc.data)?;
+
+    // Ring buffer: observation_index is the newest sample, zero timestamps are unused slots.
+    let samples = &obs.observations;
+    let latest = &samples[obs.observation_index as usize % samples.len()];
+    if latest.block_timestamp == 0 {
+        bail!("observation state {} has no samples yet", observation_key);
+    }
+    let older = || {
+        samples
+            .iter()
+            .filter(|o| o.block_timestamp != 0 && o.block_timestamp < latest.block_timestamp)
+    };
+    let target = latest.block_timestamp.saturating_sub(window_secs as u32);
+    let start = older()
+        .filter(|o| o.block_timestamp <= target)
+        .max_by_key(|o| o.block_timestamp)
+        .or_else(|| older().min_by_key(|o| o.block_timestamp))
+        .context("observation state holds a single sample; TWAP needs at least two")?;
+
+    let elapsed = latest.block_timestamp - start.block_timestamp;
+    if (elapsed as u64) < window_secs {
+        warn!(
+            "observations only cover {}s of the requested {}s window",
+            elapsed, window_secs
+        );
+    }
+    let twap_tick = (latest.tick_cumulative - start.tick_cumulative) as f64 / elapsed as f64;
+    let decimals_adj = 10f64.powi(pool.mint_decimals0 as i32 - pool.mint_decimals1 as i32);
+    let twap_price = 1.0001f64.powf(twap_tick) * decimals_adj;
+    let sqrt_spot = pool.sqrt_price_x64 as f64 / 2f64.powi(64);
+    let spot_price = sqrt_spot * sqrt_spot * decimals_adj;
+
+    println!(
+        "Raydium TWAP for {} over {}s (token1 per token0): twap_tick={:.2} twap_price={:.10} | spot_tick={} spot_price={:.10}",
+        pool_id, elapsed, twap_tick, twap_price, pool.tick_current, spot_price
+    );
+    Ok(())
+}
+
+/// Recent price path of `pool` from its observation ring: the mean tick and length of each
+/// interval between consecutive samples, oldest first.
+fn observed_tick_path(obs_acc: &Account) -> Result<Vec<(f64, u32)>> {
+    let obs = decode_observation_clmm(&obs_acc.data)?;
+    let mut samples: Vec<_> = obs
+        .observations
+        .iter()
+        .filter(|o| o.block_timestamp != 0)
+        .collect();
+    samples.sort_by_key(|o| o.block_timestamp);
+    Ok(samples
+        .windows(2)
+        .filter_map(|w| {
+            let secs = w[1].block_timestamp - w[0].block_timestamp;
+            let ticks = (w[1].tick_cumulative - w[0].tick_cumulative) as f64;
+            (secs > 0).then(|| (ticks / secs as f64, secs))
+        })
+        .collect())
+}
+
+/// `--width Nsigma`: the standard deviation of the pool's log price over its observation
+/// ring, around the current tick.
+async fn observed_sigma(rpc: &RpcClient, pool: &CPoolState) -> Result<f64> {
+    let observation_key = to_sdk_pubkey(&pool.observation_key);
+    let obs_acc = rpc
+        .get_account(&observation_key)
+        .await
+        .context("fetch observation state")?;
+    let path = observed_tick_path(&obs_acc)?;
+    if path.is_empty() {
+        bail!("the pool's observation state has fewer than two samples; use a bps width");
+    }
+    Ok(path_sigma(&path, pool.tick_current) * 1.0001f64.ln())
+}
+
+/// `--optimize-range`: score ranges around the current tick on the pool's observed price
+/// path and swap fee, print them and return the best.
+async fn optimize_range(rpc: &RpcClient, pool: &CPoolState) -> Result<(i32, i32)> {
+    let (observation_key, amm_config) = (
+        to_sdk_pubkey(&pool.observation_key),
+        to_sdk_pubkey(&pool.amm_config),
+    );
+    let accounts = Prefetched::fetch(rpc, &[observation_key, amm_config]).await?;
+    let path = observed_tick_path(accounts.require(&observation_key, "observation state")?)?;
+    if path.is_empty() {
+        bail!("the pool's observation state has fewer than two samples; pass --lower/--upper");
+    }
+    let fee_rate =
+        trade_fee_rate(accounts.require(&amm_config, "amm config")?)? as f64 / 1_000_000.0;
+    let secs: u32 = path.iter().map(|&(_, secs)| secs).sum();
+    let scores = score_ranges(
+        &path,
+        pool.tick_current,
+        Grid::clmm(pool.tick_spacing),
+        fee_rate,
+        (r_libs::tick_math::MIN_TICK, r_libs::tick_math::MAX_TICK),
+    );
+    let best = *scores
+        .iter()
+        .max_by(|a, b| a.score().total_cmp(&b.score()))
+        .context("no candidate range fits within the tick bounds")?;
+    let price = |tick| index_to_price(tick, pool.mint_decimals0, pool.mint_decimals1, 1.0001);
+    println!(
+        "Candidate ranges over the last {}s of observations (return per unit of capital, fees net of divergence loss):",
+        secs
+    );
+    for s in &scores {
+        println!(
+            "  {} [{}, {}] price {:.10}..{:.10} in_range={:.1}% concentration={:.1}x fees={:.4}% loss={:.4}% score={:.4}%",
+            if s.lower == best.lower { "*" } else { " " },
+            s.lower,
+            s.upper,
+            price(s.lower),
+            price(s.upper),
+            s.in_range * 100.0,
+            s.concentration,
+            s.fees * 100.0,
+            s.loss * 100.0,
+            s.score() * 100.0
+        );
+    }
+    println!("✅ Best range: [{}, {}]", best.lower, best.upper);
+    Ok((best.lower, best.upper))
+}
+
+/// Swap fee rate of an amm config account, in millionths.
+fn trade_fee_rate(amm_config_acc: &Account) -> Result<u32> {
+    Ok(CAmmConfig::from_bytes(&amm_config_acc.data)
+        .context("decode amm config via raydium_clmm")?
+        .trade_fee_rate)
+}
+
+/// Quote an exact-in swap by walking the ticks of the tick arrays the swap is given, at
+/// the pool's `fee_rate` (see [`trade_fee_rate`]).
+fn quote_swap_clmm(
+    pool: &CPoolState,
+    fee_rate: u32,
+    tick_arrays: &[(Pubkey, CTickArray)],
+    amount_in: u64,
+    zero_for_one: bool,
+    sqrt_price_limit_x64: u128,
+) -> Result<SwapQuote> {
+    // The swap can travel up to the far edge of the last tick array it is given.
+    let spacing = pool.tick_spacing as i32;
+    let array_span = raydium_amm_v3::states::tick_array::TICK_ARRAY_SIZE * spacing;
+    let last_start = tick_arrays
+        .last()
+        .map(|(_, ta)| ta.start_tick_index)
+        .context("no tick arrays")?;
+    let start = if zero_for_one {
+        last_start
+    } else {
+        tick_arrays[0].1.start_tick_index
+    };
+    let end = if zero_for_one {
+        tick_arrays[0].1.start_tick_index + array_span
+    } else {
+        last_start + array_span
+    };
+
+    let limit = match (sqrt_price_limit_x64, zero_for_one) {
+        (0, true) => r_libs::tick_math::MIN_SQRT_PRICE_X64 + 1,
+        (0, false) => r_libs::tick_math::MAX_SQRT_PRICE_X64 - 1,
+        (l, _) => l,
+    };
+
+    let mut sqrt_price = pool.sqrt_price_x64;
+    let mut tick = pool.tick_current;
+    let mut liquidity = pool.liquidity;
+    let mut remaining = amount_in;
+    let mut amount_out = 0u64;
+    let mut fee = 0u64;
+    while remaining > 0 && sqrt_price != limit {
+        // Next initialized tick in the swap direction, or the array edge.
+        let next = tick_arrays
+            .iter()
+            .flat_map(|(_, ta)| ta.ticks.iter())
+            .filter(|t| t.liquidity_gross > 0)
+            .map(|t| (t.tick, t.liquidity_net))
+            .filter(|(t, _)| if zero_for_one { *t <= tick } else { *t > tick })
+            .reduce(|a, b| {
+                if (zero_for_one && b.0 > a.0) || (!zero_for_one && b.0 < a.0) {
+                    b
+                } else {
+                    a
+                }
+            });
+        let (next_tick, liquidity_net) = match next {
+            Some(n) => (n.0, Some(n.1)),
+            None if zero_for_one => (start, None),
+            None => (end, None),
+        };
+        let sqrt_next =
+            r_libs::tick_math::get_sqrt_price_at_tick(next_tick).context("sqrt_at_tick next")?;
+        let target = if zero_for_one {
+            sqrt_next.max(limit)
+        } else {
+            sqrt_next.min(limit)
+        };
+
+        let step = r_libs::swap_math::compute_swap_step(
+            sqrt_price,
+            target,
+            liquidity,
+            remaining,
+            fee_rate,
+            true,
+            zero_for_one,
+        );
+        remaining = remaining.saturating_sub(step.amount_in + step.fee_amount);
+        amount_out += step.amount_out;
+        fee += step.fee_amount;
+        sqrt_price = step.sqrt_price_next_x64;
+
+        if sqrt_price == sqrt_next {
+            let Some(net) = liquidity_net else {
+                if remaining > 0 {
+                    bail!(
+                        "swap would leave the {} tick array(s) it is given (ticks {}..{}); \
+                         use a smaller --swap-amount-in or set --swap-sqrt-price-limit",
+                        tick_arrays.len(),
+                        start,
+                        end
+                    );
+                }
+                break;
+            };
+            let delta = if zero_for_one { -net } else { net };
+            liquidity = r_libs::liquidity_math::add_delta(liquidity, delta)
+                .context("apply liquidity_net")?;
+            tick = if zero_for_one {
+                next_tick - 1
+            } else {
+                next_tick
+            };
+        } else {
+            tick = r_libs::tick_math::get_tick_at_sqrt_price(sqrt_price)
+                .context("tick_at_sqrt_price")?;
+        }
+    }
+
+    let price = (pool.sqrt_price_x64 as f64 / (1u128 << 64) as f64).powi(2);
+    Ok(SwapQuote {
+        amount_in: amount_in - remaining,
+        amount_out,
+        fee,
+        spot_out_per_in: if zero_for_one { price } else { 1.0 / price },
+    })
+}
+
+/// Tick arrays a swap may cross: the current one first, then the next arrays in the swap
+/// direction, initialized or not. [`swap_tick_arrays`] picks from them once read.
+fn swap_tick_array_keys(
+    pool_id: &Pubkey,
+    pool: &CPoolState,
+    clmm_program_id: &Pubkey,
+    zero_for_one: bool,
+) -> Vec<Pubkey> {
+    const SCAN_ARRAYS: i32 = 10;
+
+    let start = tick_array_start_index(pool.tick_current, pool.tick_spacing);
+    let span = raydium_amm_v3::states::tick_array::TICK_ARRAY_SIZE * pool.tick_spacing as i32;
+    let step = if zero_for_one { -span } else { span };
+    (0..=SCAN_ARRAYS)
+        .map(|i| start + i * step)
+        .filter(|s| (r_libs::tick_math::MIN_TICK..=r_libs::tick_math::MAX_TICK).contains(s))
+        .map(|s| derive_tick_array_pda(pool_id, s, clmm_program_id).0)
+        .collect()
+}
+
+/// Tick arrays a swap may cross: the current one, then up to two more initialized arrays
+/// in the swap direction, from the `keys` of [`swap_tick_array_keys`] read into
+/// `accounts`. Uninitialized arrays are skipped, as the program skips them.
+fn swap_tick_arrays(
+    accounts: &Prefetched,
+    keys: &[Pubkey],
+    clmm_program_id: &Pubkey,
+) -> Result<Vec<(Pubkey, CTickArray)>> {
+    const EXTRA_ARRAYS: usize = 2;
+
+    let (current_pda, rest) = keys.split_first().context("no tick arrays")?;
+    let current =
+        CTickArray::from_bytes(&accounts.require(current_pda, "current tick array")?.data)
+            .context("decode tick array")?;
+    let mut arrays = vec![(*current_pda, current)];
+    for pda in rest {
+        if arrays.len() > EXTRA_ARRAYS {
+            break;
+        }
+        let Some(acc) = accounts.get(pda)?.filter(|a| a.owner == *clmm_program_id) else {
+            continue;
+        };
+        let tick_array = CTickArray::from_bytes(&acc.data).context("decode tick array")?;
+        if tick_array.initialized_tick_count > 0 {
+            arrays.push((*pda, tick_array));
+        }
+    }
+    Ok(arrays)
+}
+
+/// The pool's tick array bitmap extension, if any of `starts` lies outside the default bitmap.
+fn tick_array_bitmap_extension(
+    pool_id: &Pubkey,
+    tick_spacing: u16,
+    starts: impl IntoIterator<Item = i32>,
+    clmm_program_id: &Pubkey,
+) -> Option<Pubkey> {
+    let max = r_libs::tick_array_bit_map::max_tick_in_tickarray_bitmap(tick_spacing);
+    if !starts.into_iter().any(|s| s >= max || s < -max) {
+        return None;
+    }
+    let (pda, _) = Pubkey::find_program_address(
+        &[
+            raydium_amm_v3::states::POOL_TICK_ARRAY_BITMAP_SEED.as_bytes(),
+            pool_id.as_ref(),
+        ],
+        clmm_program_id,
+    );
+    Some(pda)
+}
+
+/// Raydium CLMM positions whose NFT mint is among `nft_mints`.
+pub(crate) async fn wallet_positions(
+    rpc: &RpcClient,
+    nft_mints: &[Pubkey],
+) -> Result<Vec<PositionSummary>> {
+    let clmm_program_id = clmm_program_id()?;
+    let mut out = Vec::new();
+    for mints in nft_mints.chunks(100) {
+        let pdas: Vec<Pubkey> = mints
+            .iter()
+            .map(|m| derive_personal_position_pda(m, &clmm_program_id).0)
+            .collect();
+        let accounts = rpc
+            .get_multiple_accounts(&pdas)
+            .await
+            .context("fetch personal positions")?;
+        let mut positions = Vec::new();
+        for (mint, acc) in mints.iter().zip(accounts) {
+            let Some(acc) = acc.filter(|a| a.owner == clmm_program_id) else {
+                continue;
+            };
+            positions.push((mint, decode_personal_position_clmm(&acc.data)?));
+        }
+        let pool_ids: Vec<Pubkey> = positions
+            .iter()
+            .map(|(_, personal)| to_sdk_pubkey(&personal.pool_id))
+            .collect();
+        let pools = Prefetched::fetch(rpc, &pool_ids).await?;
+        for ((mint, personal), pool_id) in positions.into_iter().zip(pool_ids) {
+            let pool = decode_pool_clmm(&pools.require(&pool_id, "pool")?.data)?;
+            out.push(PositionSummary {
+                position: PositionRef::new(Dex::Raydium, *mint),
+                pool: pool_id,
+                lower: personal.tick_lower_index,
+                upper: personal.tick_upper_index,
+                liquidity: personal.liquidity,
+                fees_owed: (personal.token_fees_owed0, personal.token_fees_owed1),
+                in_range: (personal.tick_lower_index..personal.tick_upper_index)
+                    .contains(&pool.tick_current),
+                bundle_index: None,
+            });
+        }
     }
-    if rpc
-        .get_account_with_commitment(&ata_out, CommitmentConfig::processed())?
+    Ok(out)
+}
+
+/// The personal position account behind `position_mint` and its pool, for
+/// [`crate::positions::watch`].
+pub(crate) async fn watched_accounts(
+    rpc: &RpcClient,
+    position_mint: &Pubkey,
+) -> Result<(Pubkey, Pubkey)> {
+    let (pda, _) = derive_personal_position_pda(position_mint, &clmm_program_id()?);
+    let acc = rpc
+        .get_account(&pda)
+        .await
+        .context("fetch personal_position")?;
+    let personal = decode_personal_position_clmm(&acc.data)?;
+    Ok((pda, to_sdk_pubkey(&personal.pool_id)))
+}
+
+/// State of a personal position, given its account data and its pool's.
+pub(crate) fn position_state(position_data: &[u8], pool_data: &[u8]) -> Result<PositionState> {
+    let personal = decode_personal_position_clmm(position_data)?;
+    let pool = decode_pool_clmm(pool_data)?;
+    let (lower, upper) = (personal.tick_lower_index, personal.tick_upper_index);
+    let amounts = r_libs::liquidity_math::get_delta_amounts_signed(
+        pool.tick_current,
+        pool.sqrt_price_x64,
+        lower,
+        upper,
+        personal.liquidity as i128,
+    )
+    .context("position amounts")?;
+    Ok(PositionState {
+        liquidity: personal.liquidity,
+        fees_owed: (personal.token_fees_owed0, personal.token_fees_owed1),
+        amounts: Some(amounts),
+        in_range: (lower..upper).contains(&pool.tick_current),
+    })
+}
+
+/// Describe the CLMM position behind `position_mint`, if it is a Raydium position NFT.
+pub(crate) async fn describe_position_nft(
+    rpc: &RpcClient,
+    position_mint: &Pubkey,
+) -> Result<Option<String>> {
+    let clmm_program_id = clmm_program_id()?;
+    let (personal_position_pda, _) = derive_personal_position_pda(position_mint, &clmm_program_id);
+    let Some(acc) = rpc
+        .get_account_with_commitment(&personal_position_pda, CommitmentConfig::confirmed())
+        .await?
         .value
-        .is_none()
+    else {
+        return Ok(None);
+    };
+    if acc.owner != clmm_program_id {
+        return Ok(None);
+    }
+    let personal = decode_personal_position_clmm(&acc.data)?;
+    Ok(Some(format!(
+        "Raydium CLMM position {}: pool={} ticks=[{}, {}] liquidity={}",
+        personal_position_pda,
+        to_sdk_pubkey(&personal.pool_id),
+        personal.tick_lower_index,
+        personal.tick_upper_index,
+        personal.liquidity
+    )))
+}
+
+/// Best quote for `amount_in` of `mint_in` across every Raydium CLMM pool of the pair.
+/// Pools whose quote fails (e.g. the swap leaves the current tick array) are skipped.
+pub(crate) async fn compare_quote(
+    rpc: &RpcClient,
+    mint_in: &Pubkey,
+    mint_out: &Pubkey,
+    amount_in: u64,
+) -> Result<Option<(Pubkey, SwapQuote)>> {
+    let clmm_program_id = clmm_program_id()?;
+    // Pools store token_mint0 < token_mint1; layout: discriminator, bump, amm_config, owner, mints.
+    let zero_for_one = mint_in.to_bytes() < mint_out.to_bytes();
+    let (mint0, mint1) = if zero_for_one {
+        (mint_in, mint_out)
+    } else {
+        (mint_out, mint_in)
+    };
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(CPoolState::LEN as u64),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(73, mint0.to_bytes().to_vec())),
+            RpcFilterType::Memcmp(Memcmp::new_raw_bytes(105, mint1.to_bytes().to_vec())),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let accounts = rpc
+        .get_program_accounts_with_config(&clmm_program_id, config)
+        .await
+        .context("fetch Raydium CLMM pools for mint pair")?;
+    debug!("{} Raydium CLMM pools for the pair", accounts.len());
+
+    // Every pool's amm config and tick arrays, read in one round trip.
+    let mut pools = Vec::new();
+    for (pool_id, acc) in accounts {
+        let pool = decode_pool_clmm(&acc.data)?;
+        if pool.liquidity == 0 {
+            continue;
+        }
+        let keys = swap_tick_array_keys(&pool_id, &pool, &clmm_program_id, zero_for_one);
+        pools.push((pool_id, pool, keys));
+    }
+    let keys: Vec<Pubkey> = pools
+        .iter()
+        .flat_map(|(_, pool, keys)| {
+            keys.iter()
+                .copied()
+                .chain([to_sdk_pubkey(&pool.amm_config)])
+        })
+        .collect();
+    let fetched = Prefetched::fetch(rpc, &keys).await?;
+
+    let mut best: Option<(Pubkey, SwapQuote)> = None;
+    for (pool_id, pool, keys) in pools {
+        let quote = swap_tick_arrays(&fetched, &keys, &clmm_program_id).and_then(|arrays| {
+            let fee_rate =
+                trade_fee_rate(fetched.require(&to_sdk_pubkey(&pool.amm_config), "amm config")?)?;
+            quote_swap_clmm(&pool, fee_rate, &arrays, amount_in, zero_for_one, 0)
+        });
+        let quote = match quote {
+            Ok(q) => q,
+            Err(e) => {
+                warn!("skipping Raydium pool {}: {:#}", pool_id, e);
+                continue;
+            }
+        };
+        if best
+            .as_ref()
+            .is_none_or(|(_, b)| quote.amount_out > b.amount_out)
+        {
+            best = Some((pool_id, quote));
+        }
+    }
+    Ok(best)
+}
+
+#[instrument(name = "swap", skip_all)]
+async fn handle_swap(
+    rpc: &RpcClient,
+    flow: &mut Flow<'_>,
+    payer_pk: &Pubkey,
+    pool_str: &str,
+    opts: &Opts,
+    ixs: &mut Vec<Instruction>,
+) -> Result<()> {
+    if opts.swap_amount_in == 0 {
+        bail!("--swap-amount-in must be > 0");
+    }
+    let leg = SwapLeg {
+        pool: Pubkey::from_str(pool_str).context("invalid swap pool id")?,
+        a_to_b: opts.swap_a_to_b,
+        amount_in: opts.swap_amount_in,
+        min_out: opts.swap_min_out,
+        sqrt_price_limit: opts.swap_sqrt_price_limit,
+        slippage_bps: opts.slippage_bps,
+    };
+    let quote = build_swap_ixs(rpc, payer_pk, &leg, ixs).await?;
+    enforce_max_price_impact(&quote, opts.max_price_impact_bps)?;
+    let min_out = slippage_min_out(&quote, leg.min_out, leg.slippage_bps);
+
+    if let Some(sig) = flow.send(ixs.clone(), &[]).await?.signature() {
+        let (mint0, mint1) = pool_mints(rpc, &leg.pool).await?;
+        let (mint_in, mint_out) = if leg.a_to_b {
+            (mint0, mint1)
+        } else {
+            (mint1, mint0)
+        };
+        println!(
+            "✅ Swap submitted. Tx: {} (amount_in={}, min_out={}, a_to_b={})",
+            sig,
+            amounts::format_amount(rpc, &mint_in, quote.amount_in).await,
+            amounts::format_amount(rpc, &mint_out, min_out).await,
+            opts.swap_a_to_b
+        );
+    }
+
+    if opts.unwrap_sol
+        && let Some(sig) = flow
+            .send(vec![build_unwrap_sol_ix(payer_pk)], &[])
+            .await?
+            .signature()
     {
-        ixs.push(create_associated_token_account(
-            payer_pk,
-            payer_pk,
-            &output_mint,
-            &spl_token::ID,
-        ));
+        println!("✅ Unwrapped WSOL. Tx: {}", sig);
+    }
+
+    Ok(())
+}
+
+/// Token mints `(token0, token1)` of a CLMM pool.
+pub(crate) async fn pool_mints(rpc: &RpcClient, pool_id: &Pubkey) -> Result<(Pubkey, Pubkey)> {
+    let pool_acc = rpc
+        .get_account(pool_id)
+        .await
+        .context("fetch pool account")?;
+    if pool_acc.owner != clmm_program_id()? {
+        bail!("pool account owner mismatch (expected Raydium CLMM program)");
     }
+    let pool = decode_pool_clmm(&pool_acc.data)?;
+    Ok((
+        to_sdk_pubkey(&pool.token_mint0),
+        to_sdk_pubkey(&pool.token_mint1),
+    ))
+}
 
-    let tick_start = tick_array_start_index(pool.tick_current, pool.tick_spacing);
-    let (tick_array_pda, _) = derive_tick_array_pda(&pool_id, tick_start, clmm_program_id);
+/// Token mints `(token0, token1)` and price grid of a CLMM pool.
+pub(crate) async fn pool_grid(rpc: &RpcClient, pool_id: &Pubkey) -> Result<(Pubkey, Pubkey, Grid)> {
+    let pool_acc = rpc
+        .get_account(pool_id)
+        .await
+        .context("fetch pool account")?;
+    if pool_acc.owner != clmm_program_id()? {
+        bail!("pool account owner mismatch (expected Raydium CLMM program)");
+    }
+    let pool = decode_pool_clmm(&pool_acc.data)?;
+    Ok((
+        to_sdk_pubkey(&pool.token_mint0),
+        to_sdk_pubkey(&pool.token_mint1),
+        Grid::clmm(pool.tick_spacing),
+    ))
+}
+
+/// Append a SwapSingle for `leg` (plus any missing ATAs) to `ixs` and return its off-chain quote.
+pub async fn build_swap_ixs(
+    rpc: &RpcClient,
+    payer_pk: &Pubkey,
+    leg: &SwapLeg,
+    ixs: &mut Vec<Instruction>,
+) -> Result<SwapQuote> {
+    let clmm_program_id = clmm_program_id()?;
+    let pool_id = leg.pool;
+    let pool_acc = rpc
+        .get_account(&pool_id)
+        .await
+        .context("fetch pool account")?;
+    if pool_acc.owner != clmm_program_id {
+        bail!("pool account owner mismatch (expected Raydium CLMM program)");
+    }
+    let pool = decode_pool_clmm(&pool_acc.data)?;
+    let token_mint0 = to_sdk_pubkey(&pool.token_mint0);
+    let token_mint1 = to_sdk_pubkey(&pool.token_mint1);
+    let token_vault0 = to_sdk_pubkey(&pool.token_vault0);
+    let token_vault1 = to_sdk_pubkey(&pool.token_vault1);
+    let amm_config = to_sdk_pubkey(&pool.amm_config);
+    let observation_state = to_sdk_pubkey(&pool.observation_key);
+
+    let (input_mint, output_mint, input_vault, output_vault) = if leg.a_to_b {
+        (token_mint0, token_mint1, token_vault0, token_vault1)
+    } else {
+        (token_mint1, token_mint0, token_vault1, token_vault0)
+    };
+
+    // Everything else the swap reads, in one round trip.
+    let tick_array_keys = swap_tick_array_keys(&pool_id, &pool, &clmm_program_id, leg.a_to_b);
+    let mut keys = amounts::token_keys(payer_pk, &[input_mint, output_mint]);
+    keys.push(amm_config);
+    keys.extend(&tick_array_keys);
+    let fetched = Prefetched::fetch(rpc, &keys).await?;
+
+    let programs = amounts::token_programs(&fetched, &[input_mint, output_mint])?;
+    let (input_program, output_program) = (programs[0], programs[1]);
+    if input_program != spl_token::ID || output_program != spl_token::ID {
+        bail!(
+            "swap_v1 only supports SPL Token mints (no token-2022); input owner {}, output owner {}",
+            input_program,
+            output_program
+        );
+    }
+
+    let atas = amounts::ensure_atas(
+        &fetched,
+        ixs,
+        payer_pk,
+        &[(input_mint, spl_token::ID), (output_mint, spl_token::ID)],
+    )?;
+    let (ata_in, ata_out) = (atas[0].0, atas[1].0);
+
+    let tick_arrays = swap_tick_arrays(&fetched, &tick_array_keys, &clmm_program_id)?;
+    let quote = quote_swap_clmm(
+        &pool,
+        trade_fee_rate(fetched.require(&amm_config, "amm config")?)?,
+        &tick_arrays,
+        leg.amount_in,
+        leg.a_to_b,
+        leg.sqrt_price_limit,
+    )?;
+
+    let min_out = slippage_min_out(&quote, leg.min_out, leg.slippage_bps);
+    if leg.slippage_bps.is_some() {
+        debug!("min_out={} from quote", min_out);
+    }
+
+    // SwapSingle takes the current tick array; any further arrays, and the bitmap
+    // extension when they lie outside the default bitmap, go in the remaining accounts.
+    let tick_array_pda = tick_arrays[0].0;
+    let mut remaining: Vec<AccountMeta> = Vec::with_capacity(tick_arrays.len());
+    if let Some(ext) = tick_array_bitmap_extension(
+        &pool_id,
+        pool.tick_spacing,
+        tick_arrays.iter().map(|(_, ta)| ta.start_tick_index),
+        &clmm_program_id,
+    ) {
+        remaining.push(AccountMeta::new_readonly(ext, false));
+    }
+    remaining.extend(
+        tick_arrays[1..]
+            .iter()
+            .map(|(pda, _)| AccountMeta::new(*pda, false)),
+    );
+    debug!(
+        "swap tick arrays: {} (+{} remaining accounts)",
+        tick_arrays.len(),
+        remaining.len()
+    );
 
     let accounts = r_accounts::SwapSingle {
         payer: *payer_pk,
@@ -590,59 +1945,295 @@ fn handle_swap(
         tick_array: tick_array_pda,
     };
     let data = r_ix::Swap {
-        amount: opts.swap_amount_in,
-        other_amount_threshold: opts.swap_min_out,
-        sqrt_price_limit_x64: opts.swap_sqrt_price_limit,
+        amount: leg.amount_in,
+        other_amount_threshold: min_out,
+        sqrt_price_limit_x64: leg.sqrt_price_limit,
         is_base_input: true,
     }
     .data();
 
+    let mut metas = accounts.to_account_metas(None);
+    metas.extend(remaining);
     ixs.push(Instruction {
-        program_id: *clmm_program_id,
-        accounts: accounts.to_account_metas(None),
+        program_id: clmm_program_id,
+        accounts: metas,
         data,
     });
 
-    let sig = simulate_and_send(rpc, payer, ixs.clone(), &[payer])?;
+    Ok(quote)
+}
+
+/// `--seed-mint`: create the CLMM pool of a new token against `--seed-quote-mint` at
+/// `--seed-price`, open the initial range from the usual open flags, and print a report
+/// of the live pool. The pool and the position go out in two transactions.
+#[instrument(name = "seed", skip_all)]
+async fn handle_seed_liquidity(
+    rpc: &RpcClient,
+    clmm_program_id: &Pubkey,
+    flow: &mut Flow<'_>,
+    payer_pk: &Pubkey,
+    seed_mint_str: &str,
+    opts: Opts,
+    mut ixs: Vec<Instruction>,
+) -> Result<()> {
+    let (Some(quote_mint_str), Some(seed_price), Some(amm_config_str)) =
+        (&opts.seed_quote_mint, opts.seed_price, &opts.amm_config)
+    else {
+        bail!("--seed-mint needs --seed-quote-mint, --seed-price and --amm-config");
+    };
+    if opts.price_range()?.is_none() && (opts.lower.is_none() || opts.upper.is_none()) {
+        bail!(
+            "--seed-mint needs the initial range: --lower/--upper, --price-min/--price-max or --width"
+        );
+    }
+    if !(seed_price > 0.0 && seed_price.is_finite()) {
+        bail!("--seed-price must be a positive number");
+    }
+    if let Some(PriceRange::WidthSigma(_)) = opts.price_range()? {
+        bail!("a new pool has no observed prices to size a sigma width on; use a bps width");
+    }
+    let seed_mint = Pubkey::from_str(seed_mint_str).context("invalid --seed-mint")?;
+    let quote_mint = Pubkey::from_str(quote_mint_str).context("invalid --seed-quote-mint")?;
+    let amm_config = Pubkey::from_str(amm_config_str).context("invalid --amm-config")?;
+    if seed_mint == quote_mint {
+        bail!("--seed-mint and --seed-quote-mint must differ");
+    }
+    let seed = SeedPair::new(seed_mint, quote_mint, seed_price, opts.price_range()?);
+    let (mint0, mint1, price) = (seed.mint0, seed.mint1, seed.price);
+    let pda = |seeds: &[&[u8]]| Pubkey::find_program_address(seeds, clmm_program_id).0;
+    let pool_id = pda(&[
+        raydium_amm_v3::states::POOL_SEED.as_bytes(),
+        amm_config.as_ref(),
+        mint0.as_ref(),
+        mint1.as_ref(),
+    ]);
+
+    // The config, both mints and the pool that must not exist yet, in one round trip.
+    let fetched = Prefetched::fetch(rpc, &[amm_config, mint0, mint1, pool_id]).await?;
+    if fetched.require(&amm_config, "amm config")?.owner != *clmm_program_id {
+        bail!("--amm-config {} is not a Raydium CLMM config", amm_config);
+    }
+    let [token_program0, token_program1] =
+        <[Pubkey; 2]>::try_from(amounts::token_programs(&fetched, &[mint0, mint1])?)
+            .expect("one program per mint");
+    let (decimals0, decimals1) = (
+        fetched.mint_decimals(&mint0)?,
+        fetched.mint_decimals(&mint1)?,
+    );
+    let raw_price = price * 10f64.powi(decimals1 as i32 - decimals0 as i32);
+    let sqrt_price_x64 = (raw_price.sqrt() * 2f64.powi(64)) as u128;
+    if !(r_libs::tick_math::MIN_SQRT_PRICE_X64..r_libs::tick_math::MAX_SQRT_PRICE_X64)
+        .contains(&sqrt_price_x64)
+    {
+        bail!(
+            "--seed-price {} is outside the CLMM price range",
+            seed_price
+        );
+    }
+
+    if fetched.get(&pool_id)?.is_some() {
+        bail!(
+            "pool {} already exists for this pair and config; open on it with --pool",
+            pool_id
+        );
+    }
+    let vault_seed = raydium_amm_v3::states::POOL_VAULT_SEED.as_bytes();
+    let token_vault0 = pda(&[vault_seed, pool_id.as_ref(), mint0.as_ref()]);
+    let token_vault1 = pda(&[vault_seed, pool_id.as_ref(), mint1.as_ref()]);
+    let raw = |pk: &Pubkey| RawPubkey::new_from_array(pk.to_bytes());
+    let create = raydium_clmm::instructions::CreatePool {
+        pool_creator: raw(payer_pk),
+        amm_config: raw(&amm_config),
+        pool_state: raw(&pool_id),
+        token_mint0: raw(&mint0),
+        token_mint1: raw(&mint1),
+        token_vault0: raw(&token_vault0),
+        token_vault1: raw(&token_vault1),
+        observation_state: raw(&pda(&[
+            raydium_amm_v3::states::OBSERVATION_SEED.as_bytes(),
+            pool_id.as_ref(),
+        ])),
+        tick_array_bitmap: raw(&pda(&[
+            raydium_amm_v3::states::POOL_TICK_ARRAY_BITMAP_SEED.as_bytes(),
+            pool_id.as_ref(),
+        ])),
+        token_program0: raw(&token_program0),
+        token_program1: raw(&token_program1),
+        system_program: raw(&solana_sdk::system_program::id()),
+        rent: raw(&sysvar::rent::id()),
+    }
+    .instruction(raydium_clmm::instructions::CreatePoolInstructionArgs {
+        sqrt_price_x64,
+        open_time: 0,
+    });
+    ixs.push(Instruction {
+        program_id: *clmm_program_id,
+        accounts: create
+            .accounts
+            .into_iter()
+            .map(|m| AccountMeta {
+                pubkey: to_sdk_pubkey(&m.pubkey),
+                is_signer: m.is_signer,
+                is_writable: m.is_writable,
+            })
+            .collect(),
+        data: create.data,
+    });
+    let Some(sig) = flow.send(ixs, &[]).await?.signature() else {
+        // The open reads the pool, which only exists once the create lands.
+        println!(
+            "Pool {} was not created, so its first position was not built; quote or export the open with --pool once it exists",
+            pool_id
+        );
+        return Ok(());
+    };
+    flow.flush().await?;
     println!(
-        "✅ Swap submitted. Tx: {} (amount_in={}, min_out={}, a_to_b={})",
-        sig, opts.swap_amount_in, opts.swap_min_out, opts.swap_a_to_b
+        "✅ Created Raydium CLMM pool {} (token0={} token1={}, price {} token1 per token0). Tx: {}",
+        pool_id, mint0, mint1, price, sig
     );
 
-    if opts.unwrap_sol {
-        let unwrap_ix = build_unwrap_sol_ix(payer_pk);
-        let sig_unwrap = simulate_and_send(rpc, payer, vec![unwrap_ix], &[payer])?;
-        println!("✅ Unwrapped WSOL. Tx: {}", sig_unwrap);
+    let mut open_opts = opts;
+    open_opts.pool = Some(pool_id.to_string());
+    if let Some(PriceRange::Absolute(min, max)) = seed.range {
+        (open_opts.price_min, open_opts.price_max) = (Some(min), Some(max));
     }
+    let open_ixs = vec![
+        ComputeBudgetInstruction::set_compute_unit_limit(open_opts.cu_limit),
+        ComputeBudgetInstruction::set_compute_unit_price(open_opts.effective_cu_price()),
+    ];
+    handle_open(rpc, clmm_program_id, flow, payer_pk, open_opts, open_ixs).await?;
+    flow.flush().await?;
 
+    let seeded = Prefetched::fetch(rpc, &[pool_id, token_vault0, token_vault1]).await?;
+    let pool = decode_pool_clmm(&seeded.require(&pool_id, "seeded pool")?.data)?;
+    let sqrt = pool.sqrt_price_x64 as f64 / 2f64.powi(64);
+    let live_price = sqrt * sqrt * 10f64.powi(decimals0 as i32 - decimals1 as i32);
+    println!("Seed report for pool {}:", pool_id);
+    println!(
+        "  token0={} token1={} seed token is token{}",
+        mint0,
+        mint1,
+        if mint0 == seed_mint { 0 } else { 1 }
+    );
+    println!(
+        "  price target={:.10} live={:.10} ({:+.1} bps) tick={}",
+        price,
+        live_price,
+        (live_price / price - 1.0) * 10_000.0,
+        pool.tick_current
+    );
+    println!(
+        "  liquidity={} vault0={} vault1={}",
+        amounts::group_thousands(pool.liquidity),
+        amounts::format_amount(
+            rpc,
+            &mint0,
+            seeded.token_balance(&token_vault0)?.unwrap_or(0)
+        )
+        .await,
+        amounts::format_amount(
+            rpc,
+            &mint1,
+            seeded.token_balance(&token_vault1)?.unwrap_or(0)
+        )
+        .await
+    );
     Ok(())
 }
 
-fn handle_open(
+/// The pair of a `--seed-mint` pool as the program stores it. The program orders the
+/// mints by address and prices token1 per token0, while `--seed-price` and
+/// `--price-min/--price-max` are quote tokens per seed token; both are inverted when the
+/// seed token ends up as token1.
+#[derive(Debug)]
+struct SeedPair {
+    mint0: Pubkey,
+    mint1: Pubkey,
+    /// Initial price in token1 per token0.
+    price: f64,
+    /// Initial range in token1 per token0. `--width` is relative to the live price and
+    /// is left as given.
+    range: Option<PriceRange>,
+}
+
+impl SeedPair {
+    fn new(
+        seed_mint: Pubkey,
+        quote_mint: Pubkey,
+        seed_price: f64,
+        range: Option<PriceRange>,
+    ) -> Self {
+        if seed_mint < quote_mint {
+            return Self {
+                mint0: seed_mint,
+                mint1: quote_mint,
+                price: seed_price,
+                range,
+            };
+        }
+        Self {
+            mint0: quote_mint,
+            mint1: seed_mint,
+            price: 1.0 / seed_price,
+            range: range.map(|range| match range {
+                PriceRange::Absolute(min, max) => PriceRange::Absolute(1.0 / max, 1.0 / min),
+                width => width,
+            }),
+        }
+    }
+}
+
+/// `--position-mint-keypair`: a pre-generated NFT mint keypair file (e.g. Solana CLI JSON).
+/// The mint account, read into `accounts`, must not exist yet, since the open instruction
+/// creates it.
+fn check_position_mint_keypair(accounts: &Prefetched, keypair: &Keypair, path: &str) -> Result<()> {
+    let mint = keypair.pubkey();
+    if accounts.get(&mint)?.is_some() {
+        bail!(
+            "position mint {} from {} already exists; use an unused keypair",
+            mint,
+            path
+        );
+    }
+    debug!("position NFT mint {} from {}", mint, path);
+    Ok(())
+}
+
+#[instrument(name = "open", skip_all)]
+async fn handle_open(
     rpc: &RpcClient,
     clmm_program_id: &Pubkey,
-    payer: &Keypair,
+    flow: &mut Flow<'_>,
     payer_pk: &Pubkey,
     opts: Opts,
     mut ixs: Vec<Instruction>,
 ) -> Result<()> {
     let pool_id = Pubkey::from_str(opts.pool.as_ref().context("missing --pool")?)
         .context("invalid pool id")?;
-    let lower = *opts.lower.as_ref().context("missing --lower")?;
-    let upper = *opts.upper.as_ref().context("missing --upper")?;
-    if upper <= lower {
-        bail!("upper tick must be > lower tick");
+    let price_range = opts.price_range()?;
+    if price_range.is_none() && !opts.optimize_range {
+        let lower = opts
+            .lower
+            .context("missing --lower (or --price-min/--price-max)")?;
+        let upper = opts.upper.context("missing --upper")?;
+        if upper <= lower {
+            bail!("upper tick must be > lower tick");
+        }
     }
     if opts.amount0 == 0 && opts.amount1 == 0 {
         bail!("provide at least one non-zero amount (amount0 or amount1)");
     }
 
-    let pool_acc = rpc.get_account(&pool_id).context("fetch pool account")?;
+    let pool_acc = rpc
+        .get_account(&pool_id)
+        .await
+        .context("fetch pool account")?;
     if pool_acc.owner != *clmm_program_id {
         bail!("pool account owner mismatch (expected Raydium CLMM program) — is this a CLMM pool?");
     }
-    eprintln!(
-        "[debug] pool data len={} lamports={} owner={}",
+    debug!(
+        "pool data len={} lamports={} owner={}",
         pool_acc.data.len(),
         pool_acc.lamports,
         pool_acc.owner
@@ -650,207 +2241,269 @@ fn handle_open(
     let pool = decode_pool_clmm(&pool_acc.data)?;
     let token_mint0 = to_sdk_pubkey(&pool.token_mint0);
     let token_mint1 = to_sdk_pubkey(&pool.token_mint1);
-    let token_vault0 = to_sdk_pubkey(&pool.token_vault0);
-    let token_vault1 = to_sdk_pubkey(&pool.token_vault1);
 
     let tick_spacing = pool.tick_spacing as i32;
-    if lower % tick_spacing != 0 || upper % tick_spacing != 0 {
-        bail!(
-            "ticks must be multiples of pool.tick_spacing = {}",
-            tick_spacing
-        );
-    }
-
-    let token_program0 = rpc
-        .get_account(&token_mint0)
-        .map(|a| a.owner)
-        .unwrap_or_else(|e| {
-            eprintln!(
-                "[warn] mint0 {} not fetchable ({}); defaulting to SPL Token",
-                token_mint0, e
+    let (mut lower, mut upper) = match price_range {
+        _ if opts.optimize_range => optimize_range(rpc, &pool).await?,
+        Some(range) => {
+            let sigma = match range {
+                PriceRange::WidthSigma(_) => Some(observed_sigma(rpc, &pool).await?),
+                _ => None,
+            };
+            let range = range.resolve(
+                index_to_price(
+                    pool.tick_current,
+                    pool.mint_decimals0,
+                    pool.mint_decimals1,
+                    1.0001,
+                ),
+                sigma,
+            )?;
+            let (l, u) = price_range_to_indices(
+                range,
+                pool.mint_decimals0,
+                pool.mint_decimals1,
+                1.0001,
+                tick_spacing,
             );
-            spl_token::ID
-        });
-    let token_program0 = if token_program0 == spl_token::ID {
-        spl_token::ID
-    } else {
-        spl_token_2022::ID
-    };
-    let token_program1 = rpc
-        .get_account(&token_mint1)
-        .map(|a| a.owner)
-        .unwrap_or_else(|e| {
-            eprintln!(
-                "[warn] mint1 {} not fetchable ({}); defaulting to SPL Token",
-                token_mint1, e
+            info!(
+                "price range [{}, {}] -> ticks [{}, {}]",
+                range.0, range.1, l, u
             );
-            spl_token::ID
-        });
-    let token_program1 = if token_program1 == spl_token::ID {
-        spl_token::ID
-    } else {
-        spl_token_2022::ID
+            (l, u)
+        }
+        None => (
+            opts.lower.unwrap_or_default(),
+            opts.upper.unwrap_or_default(),
+        ),
     };
+    (lower, upper) = align_range(
+        lower,
+        upper,
+        tick_spacing,
+        (r_libs::tick_math::MIN_TICK, r_libs::tick_math::MAX_TICK),
+        opts.snap,
+        1.0001,
+    )?;
 
-    let ata0 =
-        get_associated_token_address_with_program_id(payer_pk, &token_mint0, &token_program0);
-    let ata1 =
-        get_associated_token_address_with_program_id(payer_pk, &token_mint1, &token_program1);
-
-    if rpc
-        .get_account_with_commitment(&ata0, CommitmentConfig::processed())?
-        .value
-        .is_none()
-    {
-        ixs.push(create_associated_token_account(
-            payer_pk,
-            payer_pk,
-            &token_mint0,
-            &token_program0,
-        ));
+    if opts.snap_to_existing {
+        let (exact_pda, _) = derive_protocol_position_pda(&pool_id, lower, upper, clmm_program_id);
+        if rpc
+            .get_account_with_commitment(&exact_pda, CommitmentConfig::processed())
+            .await?
+            .value
+            .is_some()
+        {
+            info!(
+                "range [{}, {}] already has a protocol position; no snap needed",
+                lower, upper
+            );
+        } else {
+            let existing = fetch_pool_protocol_positions(rpc, &pool_id, clmm_program_id).await?;
+            debug!("pool has {} protocol positions", existing.len());
+            match snap_to_existing_range(&existing, lower, upper) {
+                Some((l, u)) => {
+                    info!(
+                        "snapping range [{}, {}] -> existing [{}, {}]",
+                        lower, upper, l, u
+                    );
+                    lower = l;
+                    upper = u;
+                }
+                None => info!(
+                    "no live range overlaps [{}, {}]; keeping requested ticks",
+                    lower, upper
+                ),
+            }
+        }
     }
-    if rpc
-        .get_account_with_commitment(&ata1, CommitmentConfig::processed())?
-        .value
-        .is_none()
-    {
-        ixs.push(create_associated_token_account(
-            payer_pk,
-            payer_pk,
-            &token_mint1,
-            &token_program1,
-        ));
+
+    let position_owner = match &opts.position_owner {
+        Some(s) => Pubkey::from_str(s).context("invalid --position-owner")?,
+        None => *payer_pk,
+    };
+    let position_mint = match &opts.position_mint_keypair {
+        Some(path) => signer::read_keypair(path).context("read --position-mint-keypair")?,
+        None => Keypair::new(),
+    };
+    let tick_array_start = |tick| tick_array_start_index(tick, pool.tick_spacing);
+    let tick_array_lower =
+        derive_tick_array_pda(&pool_id, tick_array_start(lower), clmm_program_id).0;
+    let tick_array_upper =
+        derive_tick_array_pda(&pool_id, tick_array_start(upper), clmm_program_id).0;
+    let protocol_position = derive_protocol_position_pda(&pool_id, lower, upper, clmm_program_id).0;
+
+    // The token accounts, the shared range accounts, the position mint and the rent
+    // sysvar in one round trip, alongside the scan for a position already on the range.
+    // Reusing a position needs the NFT holder's signature, so only look in the payer's
+    // wallet. Without --reuse-existing the lookup only feeds a hint, so a failed scan must
+    // not stop the open.
+    let mut keys = amounts::token_keys(payer_pk, &[token_mint0, token_mint1]);
+    keys.extend([
+        protocol_position,
+        tick_array_lower,
+        tick_array_upper,
+        position_mint.pubkey(),
+        sysvar::rent::id(),
+    ]);
+    let wallet_position = async {
+        if position_owner != *payer_pk {
+            return Ok(None);
+        }
+        find_wallet_position(rpc, payer_pk, &pool_id, lower, upper, clmm_program_id).await
+    };
+    let (fetched, existing_position) = tokio::join!(Prefetched::fetch(rpc, &keys), wallet_position);
+    let fetched = fetched?;
+    let existing_position = match existing_position {
+        Ok(found) => found,
+        Err(e) if !opts.reuse_existing => {
+            warn!(
+                "could not look for an existing position on the range: {:#}",
+                e
+            );
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    if let Some(path) = &opts.position_mint_keypair {
+        check_position_mint_keypair(&fetched, &position_mint, path)?;
     }
 
-    let bal0 = fetch_token_amount(rpc, &ata0).unwrap_or(0);
-    let bal1 = fetch_token_amount(rpc, &ata1).unwrap_or(0);
-    eprintln!(
-        "[debug] user balances before open: token0 {} ({}), token1 {} ({})",
-        token_mint0, bal0, token_mint1, bal1
+    let programs = amounts::token_programs(&fetched, &[token_mint0, token_mint1])?;
+    let (token_program0, token_program1) = (programs[0], programs[1]);
+
+    let accounts = PositionAccounts::new(
+        clmm_program_id,
+        &pool_id,
+        &pool,
+        payer_pk,
+        (lower, upper),
+        (token_program0, token_program1),
     );
 
-    let position_mint = Keypair::new();
-    let (metadata_pda, _bump) =
-        mpl_token_metadata::pda::find_metadata_account(&position_mint.pubkey());
-    let position_nft_ata = get_associated_token_address_with_program_id(
+    let balances = amounts::ensure_atas(
+        &fetched,
+        &mut ixs,
         payer_pk,
-        &position_mint.pubkey(),
-        &spl_token::ID,
+        &[(token_mint0, token_program0), (token_mint1, token_program1)],
+    )?;
+    let (bal0, bal1) = (balances[0].1, balances[1].1);
+    debug!(
+        "user balances before open: token0 {} ({}), token1 {} ({})",
+        token_mint0, bal0, token_mint1, bal1
     );
 
-    let lower_start = tick_array_start_index(lower, pool.tick_spacing);
-    let upper_start = tick_array_start_index(upper, pool.tick_spacing);
-    let (tick_array_lower_pda, _) = derive_tick_array_pda(&pool_id, lower_start, clmm_program_id);
-    let (tick_array_upper_pda, _) = derive_tick_array_pda(&pool_id, upper_start, clmm_program_id);
-    let (personal_position_pda, _) =
-        derive_personal_position_pda(&position_mint.pubkey(), clmm_program_id);
-    let (protocol_position_pda, _) =
-        derive_protocol_position_pda(&pool_id, lower, upper, clmm_program_id);
+    if let Some((nft_mint, _, _)) = &existing_position
+        && !opts.reuse_existing
+    {
+        warn!(
+            "hint: you already hold position {} on [{}, {}]; pass --reuse-existing to add liquidity to it instead of minting another NFT",
+            nft_mint, lower, upper
+        );
+    }
 
-    let sqrt_ratio_x64 = pool.sqrt_price_x64;
-    let sqrt_a_x64 =
-        r_libs::tick_math::get_sqrt_price_at_tick(lower).context("sqrt_at_tick lower")?;
-    let sqrt_b_x64 =
-        r_libs::tick_math::get_sqrt_price_at_tick(upper).context("sqrt_at_tick upper")?;
-    let (sqrt_lo, sqrt_hi) = if sqrt_a_x64 < sqrt_b_x64 {
-        (sqrt_a_x64, sqrt_b_x64)
-    } else {
-        (sqrt_b_x64, sqrt_a_x64)
-    };
+    report_open_rent(
+        &fetched,
+        &protocol_position,
+        &tick_array_lower,
+        &tick_array_upper,
+    )?;
 
-    let liquidity = if opts.amount0 > 0 && opts.amount1 == 0 {
-        if sqrt_ratio_x64 >= sqrt_hi {
-            bail!(
-                "Your current price is ABOVE the range; token0-only cannot open here (range needs token1). Choose a higher range or provide token1."
+    let liquidity = liquidity_for_amounts(&pool, (lower, upper), opts.amount0, opts.amount1)?;
+
+    if let Some((nft_mint, nft_account, _)) = existing_position
+        && opts.reuse_existing
+    {
+        ixs.push(build_increase_liquidity_ix(
+            &accounts,
+            &nft_mint,
+            &nft_account,
+            liquidity,
+            (opts.amount0, opts.amount1),
+        ));
+
+        if let Some(sig) = flow.send(ixs.clone(), &[]).await?.signature() {
+            println!(
+                "✅ Increased liquidity on existing position {}. Tx: {}",
+                nft_mint, sig
             );
         }
-        r_libs::liquidity_math::get_liquidity_from_single_amount_0(
-            sqrt_ratio_x64,
-            sqrt_lo,
-            sqrt_hi,
-            opts.amount0,
-        )
-    } else if opts.amount1 > 0 && opts.amount0 == 0 {
-        if sqrt_ratio_x64 <= sqrt_lo {
-            bail!(
-                "Your current price is BELOW the range; token1-only cannot open here (range needs token0). Choose a lower range or provide token0."
+
+        if opts.unwrap_sol
+            && let Some(sig) = flow
+                .send(vec![build_unwrap_sol_ix(payer_pk)], &[])
+                .await?
+                .signature()
+        {
+            println!("✅ Unwrapped WSOL. Tx: {}", sig);
+        }
+        return Ok(());
+    }
+
+    ixs.push(build_open_position_ix(
+        &accounts,
+        &position_owner,
+        &position_mint.pubkey(),
+        liquidity,
+        (opts.amount0, opts.amount1),
+    ));
+
+    if let Some(sig) = flow.send(ixs.clone(), &[&position_mint]).await?.signature() {
+        println!("✅ Submitted. Tx: {}", sig);
+        if position_owner != *payer_pk {
+            println!(
+                "✅ Position NFT {} minted to {}",
+                position_mint.pubkey(),
+                position_owner
             );
         }
-        r_libs::liquidity_math::get_liquidity_from_single_amount_1(
-            sqrt_ratio_x64,
-            sqrt_lo,
-            sqrt_hi,
-            opts.amount1,
-        )
-    } else {
-        r_libs::liquidity_math::get_liquidity_from_amounts(
-            sqrt_ratio_x64,
-            sqrt_lo,
-            sqrt_hi,
-            opts.amount0,
-            opts.amount1,
-        )
-    };
+    }
 
-    if liquidity == 0 {
-        bail!(
-            "computed liquidity is zero — adjust amounts or pick a range closer to the current price"
-        );
+    if opts.unwrap_sol
+        && let Some(sig) = flow
+            .send(vec![build_unwrap_sol_ix(payer_pk)], &[])
+            .await?
+            .signature()
+    {
+        println!("✅ Unwrapped WSOL. Tx: {}", sig);
     }
 
-    let accounts = r_accounts::OpenPositionV2 {
-        payer: *payer_pk,
-        position_nft_owner: *payer_pk,
-        position_nft_mint: position_mint.pubkey(),
-        position_nft_account: position_nft_ata,
-        metadata_account: metadata_pda,
-        pool_state: pool_id,
-        protocol_position: protocol_position_pda,
-        tick_array_lower: tick_array_lower_pda,
-        tick_array_upper: tick_array_upper_pda,
-        personal_position: personal_position_pda,
-        token_account_0: ata0,
-        token_account_1: ata1,
-        token_vault_0: token_vault0,
-        token_vault_1: token_vault1,
-        rent: sysvar::rent::id(),
-        system_program: solana_sdk::system_program::id(),
-        token_program: spl_token::ID,
-        associated_token_program: ASSOCIATED_TOKEN_PROGRAM_ID,
-        metadata_program: METADATA_PROGRAM_ID,
-        token_program_2022: spl_token_2022::ID,
-        vault_0_mint: token_mint0,
-        vault_1_mint: token_mint1,
-    };
+    Ok(())
+}
 
-    let data = r_ix::OpenPositionV2 {
-        tick_lower_index: lower,
-        tick_upper_index: upper,
-        tick_array_lower_start_index: lower_start,
-        tick_array_upper_start_index: upper_start,
-        liquidity,
-        amount_0_max: opts.amount0,
-        amount_1_max: opts.amount1,
-        with_matedata: true,
-        base_flag: None,
-    }
-    .data();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let ix = Instruction {
-        program_id: *clmm_program_id,
-        accounts: accounts.to_account_metas(None),
-        data,
-    };
-    ixs.push(ix);
+    const LOW: Pubkey = Pubkey::new_from_array([1; 32]);
+    const HIGH: Pubkey = Pubkey::new_from_array([2; 32]);
 
-    let sig = simulate_and_send(rpc, payer, ixs.clone(), &[payer, &position_mint])?;
-    println!("✅ Submitted. Tx: {}", sig);
+    #[test]
+    fn seed_token0_keeps_price_and_range() {
+        let seed = SeedPair::new(LOW, HIGH, 4.0, Some(PriceRange::Absolute(2.0, 8.0)));
+        assert_eq!((seed.mint0, seed.mint1), (LOW, HIGH));
+        assert_eq!(seed.price, 4.0);
+        assert!(matches!(seed.range, Some(PriceRange::Absolute(2.0, 8.0))));
+    }
 
-    if opts.unwrap_sol {
-        let unwrap_ix = build_unwrap_sol_ix(payer_pk);
-        let sig_unwrap = simulate_and_send(rpc, payer, vec![unwrap_ix], &[payer])?;
-        println!("✅ Unwrapped WSOL. Tx: {}", sig_unwrap);
+    #[test]
+    fn seed_token1_inverts_price_and_range() {
+        let seed = SeedPair::new(HIGH, LOW, 4.0, Some(PriceRange::Absolute(2.0, 8.0)));
+        assert_eq!((seed.mint0, seed.mint1), (LOW, HIGH));
+        assert_eq!(seed.price, 0.25);
+        // Still min < max, and the seed price stays inside the range.
+        let Some(PriceRange::Absolute(min, max)) = seed.range else {
+            panic!("expected an absolute range, got {:?}", seed.range);
+        };
+        assert_eq!((min, max), (0.125, 0.5));
+        assert!(min < seed.price && seed.price < max);
     }
 
-    Ok(())
+    #[test]
+    fn seed_width_is_left_as_given() {
+        for (seed_mint, quote_mint) in [(LOW, HIGH), (HIGH, LOW)] {
+            let seed = SeedPair::new(seed_mint, quote_mint, 4.0, Some(PriceRange::WidthBps(150)));
+            assert!(matches!(seed.range, Some(PriceRange::WidthBps(150))));
+        }
+    }
 }
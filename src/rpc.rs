@@ -0,0 +1,312 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use solana_client::client_error::{ClientErrorKind, Result as ClientResult};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::RpcClientConfig;
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_rpc_client::http_sender::HttpSender;
+use solana_sdk::commitment_config::CommitmentConfig;
+use tracing::{debug, warn};
+
+use crate::cli::Opts;
+
+/// How long an endpoint that timed out, throttled us or reported itself unhealthy is skipped.
+const COOLDOWN: Duration = Duration::from_secs(30);
+
+/// The RPC endpoints to use, in order of preference: `--rpc` (repeated or comma-separated),
+/// else `RPC_URLS` (comma-separated), else `RPC_URL`, else the cluster's public RPC.
+pub(crate) fn endpoints(opts: &Opts) -> Vec<String> {
+    if !opts.rpc.is_empty() {
+        return opts.rpc.clone();
+    }
+    if let Ok(urls) = std::env::var("RPC_URLS") {
+        let urls = split_urls(&urls);
+        if !urls.is_empty() {
+            return urls;
+        }
+    }
+    if let Ok(url) = std::env::var("RPC_URL") {
+        return vec![url];
+    }
+    vec![opts.cluster.default_rpc_url().to_string()]
+}
+
+/// Whether `RPC_URLS` or `RPC_URL` names an endpoint, so a config profile's `rpc` must not
+/// replace it.
+pub(crate) fn env_configured() -> bool {
+    std::env::var("RPC_URLS").is_ok_and(|urls| !split_urls(&urls).is_empty())
+        || std::env::var("RPC_URL").is_ok()
+}
+
+pub(crate) fn split_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|u| !u.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// A confirmed-commitment client over [`endpoints`]. With more than one endpoint, or with
+/// `--rpc-rate-limit`, requests go through a [`FailoverSender`].
+pub(crate) fn client(opts: &Opts) -> RpcClient {
+    let urls = endpoints(opts);
+    debug!("rpc endpoints: {}", urls.join(", "));
+    let config = RpcClientConfig::with_commitment(CommitmentConfig::confirmed());
+    if urls.len() == 1 && opts.rpc_rate_limit.is_none() {
+        return RpcClient::new_sender(HttpSender::new(&urls[0]), config);
+    }
+    RpcClient::new_sender(FailoverSender::new(&urls, opts.rpc_rate_limit), config)
+}
+
+struct Endpoint {
+    url: String,
+    sender: HttpSender,
+    /// Set while the endpoint is skipped after a failure.
+    down_until: Mutex<Option<Instant>>,
+    /// Earliest time the next request may go out under the rate limit.
+    next_slot: Mutex<Instant>,
+}
+
+impl Endpoint {
+    /// Time to wait before this endpoint may take another request.
+    fn wait(&self) -> Duration {
+        self.next_slot
+            .lock()
+            .unwrap()
+            .saturating_duration_since(Instant::now())
+    }
+
+    /// Wait until the rate limit lets a request through, and book the slot after it.
+    async fn throttle(&self, min_interval: Option<Duration>) {
+        let Some(interval) = min_interval else {
+            return;
+        };
+        let start = {
+            let mut next = self.next_slot.lock().unwrap();
+            let start = (*next).max(Instant::now());
+            *next = start + interval;
+            start
+        };
+        let wait = start.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    fn mark_down(&self) {
+        *self.down_until.lock().unwrap() = Some(Instant::now() + COOLDOWN);
+    }
+}
+
+/// Sends each request to one of several endpoints. An endpoint that times out, keeps
+/// answering 429 or reports itself unhealthy is skipped for [`COOLDOWN`] and the request is
+/// retried on the next one; once the cooldown is over the endpoint must pass `getHealth`
+/// before it is used again. Requests stick to the last endpoint that worked unless the rate
+/// limit makes another one free sooner.
+///
+/// RPC errors the node answered with (preflight failures, bad params) are returned as-is:
+/// another endpoint would give the same answer. Resending a `sendTransaction` after a
+/// timeout is safe because the cluster drops duplicate signatures.
+pub struct FailoverSender {
+    endpoints: Vec<Endpoint>,
+    min_interval: Option<Duration>,
+    current: AtomicUsize,
+}
+
+impl FailoverSender {
+    /// A sender over `urls` that sends at most `rate_limit` requests per second to each.
+    pub fn new(urls: &[String], rate_limit: Option<f64>) -> Self {
+        assert!(
+            !urls.is_empty(),
+            "FailoverSender needs at least one endpoint"
+        );
+        let now = Instant::now();
+        Self {
+            endpoints: urls
+                .iter()
+                .map(|url| Endpoint {
+                    url: url.clone(),
+                    sender: HttpSender::new(url),
+                    down_until: Mutex::new(None),
+                    next_slot: Mutex::new(now),
+                })
+                .collect(),
+            min_interval: rate_limit
+                .filter(|r| *r > 0.0)
+                .map(|r| Duration::from_secs_f64(1.0 / r)),
+            current: AtomicUsize::new(0),
+        }
+    }
+
+    /// Endpoints in rotation order from the current one, those free soonest first.
+    fn candidates(&self) -> Vec<usize> {
+        let n = self.endpoints.len();
+        let start = self.current.load(Ordering::Relaxed);
+        let mut order: Vec<usize> = (0..n).map(|k| (start + k) % n).collect();
+        order.sort_by_key(|&i| self.endpoints[i].wait());
+        order
+    }
+
+    /// Whether endpoint `i` may take requests, probing it with `getHealth` once its
+    /// cooldown is over.
+    async fn usable(&self, i: usize) -> bool {
+        let endpoint = &self.endpoints[i];
+        let down_until = *endpoint.down_until.lock().unwrap();
+        match down_until {
+            None => true,
+            Some(until) if Instant::now() < until => false,
+            Some(_) => {
+                endpoint.throttle(self.min_interval).await;
+                match endpoint
+                    .sender
+                    .send(RpcRequest::GetHealth, serde_json::Value::Null)
+                    .await
+                {
+                    Ok(_) => {
+                        debug!("rpc {} is healthy again", endpoint.url);
+                        *endpoint.down_until.lock().unwrap() = None;
+                        true
+                    }
+                    Err(e) => {
+                        debug!("rpc {} still unhealthy: {}", endpoint.url, e);
+                        endpoint.mark_down();
+                        false
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl RpcSender for FailoverSender {
+    async fn send(
+        &self,
+        request: RpcRequest,
+        params: serde_json::Value,
+    ) -> ClientResult<serde_json::Value> {
+        let mut candidates = Vec::new();
+        for i in self.candidates() {
+            if self.usable(i).await {
+                candidates.push(i);
+            }
+        }
+        if candidates.is_empty() {
+            // Everything is cooling down; try them all anyway rather than fail outright.
+            candidates = self.candidates();
+        }
+
+        let mut last_err = None;
+        for i in candidates {
+            let endpoint = &self.endpoints[i];
+            endpoint.throttle(self.min_interval).await;
+            match endpoint.sender.send(request, params.clone()).await {
+                Err(e) if is_endpoint_failure(e.kind()) => {
+                    warn!("rpc {} failed on {}: {}", endpoint.url, request, e);
+                    endpoint.mark_down();
+                    last_err = Some(e);
+                }
+                result => {
+                    if self.current.swap(i, Ordering::Relaxed) != i {
+                        debug!("rpc now using {}", endpoint.url);
+                    }
+                    return result;
+                }
+            }
+        }
+        Err(last_err.expect("at least one endpoint was tried"))
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        let mut total = RpcTransportStats::default();
+        for endpoint in &self.endpoints {
+            let stats = endpoint.sender.get_transport_stats();
+            total.request_count += stats.request_count;
+            total.elapsed_time += stats.elapsed_time;
+            total.rate_limited_time += stats.rate_limited_time;
+        }
+        total
+    }
+
+    fn url(&self) -> String {
+        self.endpoints[self.current.load(Ordering::Relaxed)]
+            .url
+            .clone()
+    }
+}
+
+/// Failures of the endpoint rather than of the request: transport errors (timeouts,
+/// refused connections, HTTP 429/5xx once the sender's own 429 retries are spent) and a
+/// node reporting itself behind.
+fn is_endpoint_failure(kind: &ClientErrorKind) -> bool {
+    match kind {
+        ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { data, .. }) => {
+            matches!(data, RpcResponseErrorData::NodeUnhealthy { .. })
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_client::client_error::ClientErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn split_urls_drops_blanks_and_whitespace() {
+        assert_eq!(
+            split_urls(" https://a.example , ,https://b.example,"),
+            vec!["https://a.example", "https://b.example"]
+        );
+        assert!(split_urls(" , ").is_empty());
+    }
+
+    #[test]
+    fn endpoint_failures_are_transport_errors_and_unhealthy_nodes() {
+        let io = ClientErrorKind::Io(std::io::Error::other("timed out"));
+        assert!(is_endpoint_failure(&io));
+        let unhealthy = ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32005,
+            message: "Node is behind".to_string(),
+            data: RpcResponseErrorData::NodeUnhealthy {
+                num_slots_behind: Some(42),
+            },
+        });
+        assert!(is_endpoint_failure(&unhealthy));
+        let bad_params = ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: -32602,
+            message: "Invalid params".to_string(),
+            data: RpcResponseErrorData::Empty,
+        });
+        assert!(!is_endpoint_failure(&bad_params));
+        let custom = ClientErrorKind::Custom("bad signature".to_string());
+        assert!(!is_endpoint_failure(&custom));
+    }
+
+    #[test]
+    fn candidates_start_at_the_current_endpoint_and_prefer_free_ones() {
+        let urls: Vec<String> = [
+            "https://a.example",
+            "https://b.example",
+            "https://c.example",
+        ]
+        .iter()
+        .map(|u| u.to_string())
+        .collect();
+        let sender = FailoverSender::new(&urls, Some(10.0));
+        assert_eq!(sender.candidates(), vec![0, 1, 2]);
+
+        sender.current.store(1, Ordering::Relaxed);
+        assert_eq!(sender.candidates(), vec![1, 2, 0]);
+
+        // An endpoint still inside its rate-limit interval goes last.
+        *sender.endpoints[1].next_slot.lock().unwrap() = Instant::now() + Duration::from_secs(5);
+        assert_eq!(sender.candidates(), vec![2, 0, 1]);
+    }
+}
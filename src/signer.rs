@@ -0,0 +1,319 @@
+use std::str::FromStr;
+
+use anyhow::{Context, Result, anyhow, bail};
+use bip39::{Language, Mnemonic, Seed};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_request::RpcRequest;
+use solana_sdk::{
+    derivation_path::DerivationPath,
+    pubkey::Pubkey,
+    signature::{Keypair, SeedDerivable, Signature, Signer},
+    signer::{
+        SignerError, keypair::keypair_from_seed_and_derivation_path, null_signer::NullSigner,
+    },
+};
+use tracing::debug;
+
+use crate::cli::Opts;
+use crate::keystore;
+
+/// The wallet that pays for and signs every transaction: a `--remote-signer`
+/// service when given, else the `--wallet` profile, the `--payer` key, an encrypted
+/// `--keystore`/`KEYSTORE_PATH` file or a `MNEMONIC` seed phrase, otherwise the
+/// `PRIVATE_KEY_B58` keypair from `.env`.
+/// With `--export-payer`, `--quote` or `cost` it is only a public key; nothing is signed
+/// for it.
+pub(crate) async fn load_payer(opts: &Opts) -> Result<Box<dyn Signer>> {
+    if opts.quote || opts.cost {
+        return Ok(Box::new(NullSigner::new(&payer_pubkey(opts).await?)));
+    }
+    if let Some(pubkey) = &opts.export_payer {
+        let pubkey = Pubkey::from_str(pubkey).context("invalid --export-payer")?;
+        return Ok(Box::new(NullSigner::new(&pubkey)));
+    }
+    if let Some(url) = &opts.remote_signer {
+        let signer = RemoteSigner::connect(url).await?;
+        debug!("remote signer {} for wallet {}", url, signer.pubkey);
+        return Ok(Box::new(signer));
+    }
+    if let Some(name) = &opts.wallet {
+        let source = wallet_source(name)?;
+        return load_source(&source, opts)
+            .await
+            .with_context(|| format!("load wallet {:?}", name));
+    }
+    if let Some(source) = &opts.payer {
+        return load_source(source, opts).await.context("invalid --payer");
+    }
+    if let Some(path) = opts
+        .keystore
+        .clone()
+        .or_else(|| std::env::var("KEYSTORE_PATH").ok())
+    {
+        return Ok(Box::new(keystore::load(&path)?));
+    }
+    if let Ok(words) = std::env::var("MNEMONIC") {
+        return Ok(Box::new(keypair_from_mnemonic(&words, &opts.derivation)?));
+    }
+    Ok(Box::new(env_keypair()?))
+}
+
+/// The payer's public key, found the way [`load_payer`] finds the wallet but without
+/// unlocking it: a keystore's recorded `pubkey` is read instead of decrypting it and a
+/// remote signer is only asked for its key. Plain keys and seed phrases are parsed and
+/// dropped.
+async fn payer_pubkey(opts: &Opts) -> Result<Pubkey> {
+    if let Some(pubkey) = &opts.export_payer {
+        return Pubkey::from_str(pubkey).context("invalid --export-payer");
+    }
+    if let Some(url) = &opts.remote_signer {
+        return Ok(RemoteSigner::connect(url).await?.pubkey);
+    }
+    if let Some(name) = &opts.wallet {
+        let source = wallet_source(name)?;
+        return source_pubkey(&source, opts)
+            .await
+            .with_context(|| format!("load wallet {:?}", name));
+    }
+    if let Some(source) = &opts.payer {
+        return source_pubkey(source, opts).await.context("invalid --payer");
+    }
+    if let Some(path) = opts
+        .keystore
+        .clone()
+        .or_else(|| std::env::var("KEYSTORE_PATH").ok())
+    {
+        return keystore::pubkey(&path);
+    }
+    if let Ok(words) = std::env::var("MNEMONIC") {
+        return Ok(keypair_from_mnemonic(&words, &opts.derivation)?.pubkey());
+    }
+    Ok(env_keypair()?.pubkey())
+}
+
+async fn source_pubkey(source: &str, opts: &Opts) -> Result<Pubkey> {
+    match source.trim().strip_prefix("keystore:") {
+        Some(path) => keystore::pubkey(&expand_home(path)),
+        None => Ok(load_source(source, opts).await?.pubkey()),
+    }
+}
+
+/// A key source as given to `--payer` or in the wallets file: `keystore:<file>`,
+/// `remote:<url>`, `mnemonic:<words>`, or anything `parse_keypair` takes.
+async fn load_source(source: &str, opts: &Opts) -> Result<Box<dyn Signer>> {
+    let source = source.trim();
+    if let Some(path) = source.strip_prefix("keystore:") {
+        return Ok(Box::new(keystore::load(&expand_home(path))?));
+    }
+    if let Some(url) = source.strip_prefix("remote:") {
+        let signer = RemoteSigner::connect(url).await?;
+        debug!("remote signer {} for wallet {}", url, signer.pubkey);
+        return Ok(Box::new(signer));
+    }
+    if let Some(words) = source.strip_prefix("mnemonic:") {
+        return Ok(Box::new(keypair_from_mnemonic(words, &opts.derivation)?));
+    }
+    Ok(Box::new(parse_keypair(&expand_home(source))?))
+}
+
+/// Key source of the `--wallet` profile `name`, from `WALLETS_FILE` or
+/// `~/.config/solana-liq-arb/wallets.toml` (one `name = "source"` per wallet).
+fn wallet_source(name: &str) -> Result<String> {
+    let path = std::env::var("WALLETS_FILE")
+        .unwrap_or_else(|_| expand_home("~/.config/solana-liq-arb/wallets.toml"));
+    let text =
+        std::fs::read_to_string(&path).with_context(|| format!("read wallets file {}", path))?;
+    let table: toml::value::Table =
+        toml::from_str(&text).with_context(|| format!("parse wallets file {}", path))?;
+    match table.get(name) {
+        Some(toml::Value::String(source)) => Ok(source.clone()),
+        Some(_) => bail!("wallet {:?} in {} must be a string", name, path),
+        None => {
+            let names = table.keys().cloned().collect::<Vec<_>>().join(", ");
+            bail!("no wallet {:?} in {} (known: {})", name, path, names)
+        }
+    }
+}
+
+/// `~/...` relative to `$HOME`; other paths are returned unchanged.
+pub(crate) fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => path.to_string(),
+    }
+}
+
+/// The `PRIVATE_KEY_B58` keypair from `.env`.
+pub fn env_keypair() -> Result<Keypair> {
+    let key = std::env::var("PRIVATE_KEY_B58").context("Set PRIVATE_KEY_B58 in .env")?;
+    parse_keypair(&key).context("invalid PRIVATE_KEY_B58")
+}
+
+/// Read a keypair file: Solana CLI JSON (`[12, 34, ...]`) or a base58 string.
+pub fn read_keypair(path: &str) -> Result<Keypair> {
+    let text = std::fs::read_to_string(path).with_context(|| format!("read {}", path))?;
+    decode_keypair(&text).with_context(|| format!("parse keypair file {}", path))
+}
+
+/// A keypair given as a path to a keypair file, a JSON byte array or base58 (Phantom
+/// exports). 64-byte keypairs and 32-byte seeds are both accepted.
+pub fn parse_keypair(source: &str) -> Result<Keypair> {
+    let source = source.trim();
+    if !source.starts_with('[') && std::path::Path::new(source).is_file() {
+        return read_keypair(source);
+    }
+    decode_keypair(source)
+}
+
+fn decode_keypair(text: &str) -> Result<Keypair> {
+    let text = text.trim();
+    let bytes = if text.starts_with('[') {
+        serde_json::from_str::<Vec<u8>>(text).context("invalid JSON keypair byte array")?
+    } else {
+        bs58::decode(text)
+            .into_vec()
+            .context("not a keypair file, JSON byte array or base58 key")?
+    };
+    match bytes.len() {
+        64 => Keypair::from_bytes(&bytes).context("Failed to parse 64-byte ed25519 keypair"),
+        32 => {
+            let seed: [u8; 32] = bytes
+                .as_slice()
+                .try_into()
+                .context("Seed must be 32 bytes")?;
+            Keypair::from_seed(&seed)
+                .map_err(|e| anyhow!("Failed to derive keypair from 32-byte seed: {e}"))
+        }
+        n => bail!(
+            "Decoded private key had {} bytes; expected 32 or 64 (Phantom exports 64)",
+            n
+        ),
+    }
+}
+
+/// Derive the keypair at `derivation` from a BIP39 seed phrase, the way Phantom and
+/// Solflare do (their first account is `m/44'/501'/0'/0'`). `MNEMONIC_PASSPHRASE` is the
+/// optional BIP39 passphrase.
+fn keypair_from_mnemonic(words: &str, derivation: &str) -> Result<Keypair> {
+    // Rejects unknown words, bad word counts and a wrong checksum, so a typo fails here
+    // instead of silently deriving some other wallet.
+    let mnemonic = Mnemonic::from_phrase(words, Language::English)
+        .map_err(|e| anyhow!("invalid mnemonic: {}", e))?;
+    let path = DerivationPath::from_absolute_path_str(derivation)
+        .map_err(|e| anyhow!("invalid --derivation {:?}: {}", derivation, e))?;
+    let passphrase = std::env::var("MNEMONIC_PASSPHRASE").unwrap_or_default();
+    let seed = Seed::new(&mnemonic, &passphrase);
+    let keypair = keypair_from_seed_and_derivation_path(seed.as_bytes(), Some(path))
+        .map_err(|e| anyhow!("derive keypair at {}: {}", derivation, e))?;
+    debug!("payer {} derived at {}", keypair.pubkey(), derivation);
+    Ok(keypair)
+}
+
+/// Signs through an HTTP JSON-RPC service that holds the key, so the key never
+/// reaches this process. The service answers `getPublicKey` with the base58 wallet
+/// address and `signMessage` (base58 message bytes) with a base58 ed25519 signature.
+pub struct RemoteSigner {
+    url: String,
+    pubkey: Pubkey,
+}
+
+impl RemoteSigner {
+    pub async fn connect(url: &str) -> Result<Self> {
+        let pubkey: String = RpcClient::new(url.to_string())
+            .send(
+                RpcRequest::Custom {
+                    method: "getPublicKey",
+                },
+                serde_json::Value::Null,
+            )
+            .await
+            .with_context(|| format!("getPublicKey from remote signer {}", url))?;
+        let pubkey = Pubkey::from_str(&pubkey)
+            .with_context(|| format!("remote signer returned a bad public key {:?}", pubkey))?;
+        Ok(Self {
+            url: url.to_string(),
+            pubkey,
+        })
+    }
+
+    /// Have the service sign `message`, checking the signature against the wallet it
+    /// reported.
+    pub async fn sign_message_remote(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let sig: String = RpcClient::new(self.url.clone())
+            .send(
+                RpcRequest::Custom {
+                    method: "signMessage",
+                },
+                serde_json::json!([bs58::encode(message).into_string()]),
+            )
+            .await
+            .map_err(|e| SignerError::Connection(e.to_string()))?;
+        let sig = Signature::from_str(&sig)
+            .map_err(|e| SignerError::Protocol(format!("bad signature {:?}: {}", sig, e)))?;
+        // Never put a signature on the wire that does not match the wallet we reported.
+        if !sig.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Protocol(format!(
+                "signature does not verify against {}",
+                self.pubkey
+            )));
+        }
+        Ok(sig)
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    /// `Signer` is synchronous and transactions are signed on the async runtime's thread,
+    /// which must not block on its own I/O, so the request runs to completion on a
+    /// thread with a runtime of its own.
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(|e| SignerError::Custom(format!("start signer runtime: {}", e)))?
+                        .block_on(self.sign_message_remote(message))
+                })
+                .join()
+                .map_err(|_| SignerError::Custom("remote signer thread panicked".to_string()))?
+        })
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "m/44'/501'/0'/0'";
+
+    #[test]
+    fn mnemonic_with_valid_checksum_derives() {
+        let words = "abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+                     abandon abandon about";
+        assert!(keypair_from_mnemonic(words, PATH).is_ok());
+    }
+
+    #[test]
+    fn mnemonic_typos_are_rejected() {
+        // Bad checksum, a word outside the list, and a bad word count.
+        for words in [
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+             abandon abandon",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+             abandon abuot",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon \
+             about",
+        ] {
+            assert!(keypair_from_mnemonic(words, PATH).is_err(), "{}", words);
+        }
+    }
+}
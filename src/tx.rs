@@ -1,50 +1,1304 @@
-use anyhow::{Result, bail};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use solana_account_decoder::UiAccountEncoding;
 use solana_sdk::{
+    account::Account,
     commitment_config::CommitmentConfig,
+    compute_budget::{self, ComputeBudgetInstruction},
+    hash::Hash,
     instruction::Instruction,
     message::Message,
+    packet::PACKET_DATA_SIZE,
+    program_pack::Pack,
     pubkey::Pubkey,
     signature::{Keypair, Signature, Signer},
     system_instruction,
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
 };
 use spl_associated_token_account::get_associated_token_address_with_program_id;
-use spl_token::{instruction as spl_token_ix, native_mint};
+use spl_token::{instruction as spl_token_ix, native_mint, state::Account as SplTokenAccount};
+
+use sha2::{Digest, Sha256};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::nonce_utils;
+use solana_client::rpc_config::{
+    RpcSendTransactionConfig, RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig,
+};
+use solana_client::rpc_request::RpcRequest;
+use solana_transaction_status::TransactionStatus;
+use tracing::{debug, info, trace, warn};
+
+use crate::amounts;
+use crate::cli::{CuPrice, Opts};
+use crate::error::{self, Error};
+use crate::hints;
+use crate::signer;
+
+/// `getRecentPrioritizationFees` accepts at most this many accounts.
+const MAX_FEE_ACCOUNTS: usize = 128;
+/// Instruction tag of `ComputeBudgetInstruction::SetComputeUnitPrice`.
+const SET_COMPUTE_UNIT_PRICE_TAG: u8 = 3;
+/// Instruction tag of `ComputeBudgetInstruction::SetComputeUnitLimit`.
+const SET_COMPUTE_UNIT_LIMIT_TAG: u8 = 2;
+/// Base fee per signature.
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+/// Anchor events the DEX programs log, named by [`ProgramEvent::name`]. Meteora DLMM
+/// emits its events through a self-CPI rather than a `Program data:` log line, so its
+/// events never show up in simulation logs.
+const EVENT_NAMES: &[&str] = &[
+    // Raydium CLMM
+    "SwapEvent",
+    "CreatePersonalPositionEvent",
+    "IncreaseLiquidityEvent",
+    "DecreaseLiquidityEvent",
+    "LiquidityCalculateEvent",
+    "LiquidityChangeEvent",
+    "CollectPersonalFeeEvent",
+    "UpdateRewardInfosEvent",
+    "PoolCreatedEvent",
+    "CollectProtocolFeeEvent",
+    "ConfigChangeEvent",
+    // Orca Whirlpools
+    "Traded",
+    "LiquidityIncreased",
+    "LiquidityDecreased",
+    "PoolInitialized",
+];
+
+/// What a command does with each transaction it builds once the simulation succeeds.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Finish {
+    /// Sign and send it.
+    #[default]
+    Send,
+    /// `--quote`: keep the simulated token and SOL changes; nothing is signed.
+    Quote,
+    /// `cost`: keep the lamports it needs (fees, tip, rent); nothing is signed.
+    Cost,
+    /// `--export-tx`: write it to this path for external signing, signed by the local
+    /// keypairs only. Later transactions of the command get numbered paths.
+    Export(String),
+}
+
+struct ResendConfig {
+    max_retries: u32,
+    interval: Duration,
+}
+
+impl Default for ResendConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            interval: Duration::from_secs(2),
+        }
+    }
+}
+
+struct NonceConfig {
+    account: Pubkey,
+    /// Signs the nonce advance; `None` when the payer is the nonce authority.
+    authority: Option<Keypair>,
+}
+
+/// The blockhash currently stored in a durable nonce account.
+async fn nonce_blockhash(rpc: &RpcClient, account: &Pubkey) -> error::Result<Hash> {
+    let acc = rpc.get_account(account).await?;
+    let data = nonce_utils::data_from_account(&acc).map_err(|e| Error::Decode {
+        what: "nonce account",
+        reason: e.to_string(),
+    })?;
+    Ok(data.blockhash())
+}
+
+/// Jito's mainnet tip accounts; any one of them can receive a bundle's tip.
+const JITO_TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+];
+const BUNDLE_CONFIRM_TIMEOUT: Duration = Duration::from_secs(60);
+/// Most transactions the block engine takes in one bundle, the tip transaction included.
+const MAX_BUNDLE_TXS: usize = 5;
+
+struct JitoConfig {
+    url: String,
+    tip_lamports: u64,
+}
+
+/// How a command's transactions are finished, priced, checked and sent. Built once from
+/// the command line and shared by every [`Flow`].
+#[derive(Default)]
+pub struct TxConfig {
+    pub finish: Finish,
+    /// `--cu-price auto`: replace the compute unit price with this percentile of the recent
+    /// prioritization fees paid on the transaction's writable accounts.
+    pub auto_cu_price: Option<u8>,
+    /// `--max-retries`/`--resend-interval-ms`: how often an unconfirmed transaction is
+    /// rebroadcast, and how many times one whose blockhash expired is re-signed.
+    resend: ResendConfig,
+    /// `--nonce-account`/`--nonce-authority`: sign against the nonce account's durable
+    /// blockhash, advancing the nonce as the first instruction, instead of a recent one.
+    nonce: Option<NonceConfig>,
+    /// `--allow-destination` plus `ALLOWED_DESTINATIONS` (comma-separated): wallets other
+    /// than the payer a transaction may pay tokens or SOL to.
+    pub allowed_destinations: Vec<Pubkey>,
+    /// `--jito`: send through the block engine in bundles, each ending with a tip
+    /// transfer, instead of the public RPC.
+    jito: Option<JitoConfig>,
+}
+
+impl TxConfig {
+    pub(crate) fn from_opts(opts: &Opts) -> Result<Self> {
+        let finish = if opts.cost {
+            Finish::Cost
+        } else if opts.quote {
+            Finish::Quote
+        } else if let Some(path) = &opts.export_tx {
+            Finish::Export(path.clone())
+        } else {
+            Finish::Send
+        };
+        let nonce = match &opts.nonce_account {
+            Some(account) => Some(NonceConfig {
+                account: Pubkey::from_str(account).context("invalid --nonce-account")?,
+                authority: opts
+                    .nonce_authority
+                    .as_deref()
+                    .map(|path| signer::read_keypair(path).context("read --nonce-authority"))
+                    .transpose()?,
+            }),
+            None => None,
+        };
+        let env = std::env::var("ALLOWED_DESTINATIONS").unwrap_or_default();
+        let allowed_destinations = opts
+            .allow_destination
+            .iter()
+            .map(String::as_str)
+            .chain(env.split(','))
+            .map(str::trim)
+            .filter(|w| !w.is_empty())
+            .map(|w| {
+                Pubkey::from_str(w).with_context(|| format!("invalid allowed destination {}", w))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            finish,
+            auto_cu_price: matches!(opts.cu_price, Some(CuPrice::Auto))
+                .then_some(opts.cu_price_percentile),
+            resend: ResendConfig {
+                max_retries: opts.max_retries,
+                interval: Duration::from_millis(opts.resend_interval_ms),
+            },
+            nonce,
+            allowed_destinations,
+            jito: opts.effective_jito().map(|url| JitoConfig {
+                url: url.trim_end_matches('/').to_string(),
+                tip_lamports: opts.jito_tip_lamports,
+            }),
+        })
+    }
+}
+
+/// Simulated effects of a transaction, computed without any signature.
+#[derive(Clone)]
+pub struct SimulationPreview {
+    pub err: Option<TransactionError>,
+    pub units_consumed: Option<u64>,
+    pub logs: Vec<String>,
+    /// Anchor events the programs logged, in emission order.
+    pub events: Vec<ProgramEvent>,
+    pub lamport_deltas: Vec<LamportDelta>,
+    pub token_deltas: Vec<TokenDelta>,
+}
+
+#[derive(Clone)]
+pub struct LamportDelta {
+    pub account: Pubkey,
+    pub before: u64,
+    pub after: u64,
+    /// Account data size after the transaction.
+    pub data_len: usize,
+    /// Program that owns the account after the transaction.
+    pub owner: Pubkey,
+}
+
+#[derive(Clone)]
+pub struct TokenDelta {
+    pub account: Pubkey,
+    pub mint: Pubkey,
+    /// Wallet (or PDA) the token account belongs to.
+    pub owner: Pubkey,
+    pub before: u64,
+    pub after: u64,
+}
+
+/// An Anchor event a program logged as a `Program data:` line, split into its
+/// discriminator and Borsh-encoded fields.
+#[derive(Clone, Debug)]
+pub struct ProgramEvent {
+    /// Program that was running when the event was logged.
+    pub program: Pubkey,
+    /// Event type, for the DEX events this crate knows.
+    pub name: Option<&'static str>,
+    /// First 8 bytes of `sha256("event:<Name>")`.
+    pub discriminator: [u8; 8],
+    pub data: Vec<u8>,
+}
+
+impl ProgramEvent {
+    /// The event as `T` (for example `raydium_amm_v3::states::SwapEvent`), if it is one.
+    pub fn decode<T: Discriminator + AnchorDeserialize>(&self) -> Option<T> {
+        if self.discriminator != T::DISCRIMINATOR {
+            return None;
+        }
+        T::try_from_slice(&self.data).ok()
+    }
+}
+
+/// The events in `logs`, each attributed to the program on top of the invoke stack.
+/// Payloads that are not base64 or are shorter than a discriminator are skipped.
+fn program_events(logs: &[String]) -> Vec<ProgramEvent> {
+    let mut stack: Vec<Pubkey> = Vec::new();
+    let mut events = Vec::new();
+    for line in logs {
+        if let Some(payload) = line.strip_prefix("Program data: ") {
+            let (Some(program), Ok(bytes)) = (stack.last(), BASE64.decode(payload.trim())) else {
+                continue;
+            };
+            let Some((discriminator, data)) = bytes.split_first_chunk::<8>() else {
+                continue;
+            };
+            events.push(ProgramEvent {
+                program: *program,
+                name: EVENT_NAMES.iter().copied().find(|name| {
+                    Sha256::digest(format!("event:{}", name))[..8] == discriminator[..]
+                }),
+                discriminator: *discriminator,
+                data: data.to_vec(),
+            });
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("Program"), Some(id), Some("invoke")) => {
+                if let Ok(id) = Pubkey::from_str(id) {
+                    stack.push(id);
+                }
+            }
+            (Some("Program"), Some(_), Some("success" | "failed:")) => {
+                stack.pop();
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+/// Simulate `ixs` with `fee_payer` and diff every writable account before and after.
+/// No signer is needed: signatures are not verified and the blockhash is replaced.
+pub async fn simulate_preview(
+    rpc: &RpcClient,
+    fee_payer: &Pubkey,
+    ixs: &[Instruction],
+) -> error::Result<SimulationPreview> {
+    let msg = Message::new(ixs, Some(fee_payer));
+    let writable: Vec<Pubkey> = msg
+        .account_keys
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| msg.is_writable(*i))
+        .map(|(_, k)| *k)
+        .collect();
+    let before = rpc.get_multiple_accounts(&writable).await?;
+
+    let tx = Transaction::new_unsigned(msg);
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        accounts: Some(RpcSimulateTransactionAccountsConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            addresses: writable.iter().map(|k| k.to_string()).collect(),
+        }),
+        ..RpcSimulateTransactionConfig::default()
+    };
+    let sim = rpc
+        .simulate_transaction_with_config(&tx, config)
+        .await?
+        .value;
+    let after: Vec<Option<Account>> = sim
+        .accounts
+        .unwrap_or_default()
+        .into_iter()
+        .map(|a| a.and_then(|ui| ui.decode::<Account>()))
+        .collect();
+
+    let mut lamport_deltas = Vec::new();
+    let mut token_deltas = Vec::new();
+    // A failed simulation returns no accounts: it changed nothing.
+    let changed = if sim.err.is_none() {
+        &writable[..]
+    } else {
+        &[]
+    };
+    for (i, account) in changed.iter().enumerate() {
+        let pre = before.get(i).cloned().flatten();
+        let post = after.get(i).cloned().flatten();
+        let lamports = |a: &Option<Account>| a.as_ref().map_or(0, |a| a.lamports);
+        if lamports(&pre) != lamports(&post) {
+            lamport_deltas.push(LamportDelta {
+                account: *account,
+                before: lamports(&pre),
+                after: lamports(&post),
+                data_len: post.as_ref().map_or(0, |a| a.data.len()),
+                owner: post
+                    .as_ref()
+                    .or(pre.as_ref())
+                    .map_or_else(Pubkey::default, |a| a.owner),
+            });
+        }
+        let token = |a: &Option<Account>| {
+            let a = a.as_ref()?;
+            if a.owner != spl_token::ID && a.owner != spl_token_2022::ID {
+                return None;
+            }
+            // Token-2022 shares the base layout; extensions follow it.
+            let base = a.data.get(..SplTokenAccount::LEN)?;
+            SplTokenAccount::unpack_from_slice(base).ok()
+        };
+        let (pre_tok, post_tok) = (token(&pre), token(&post));
+        if let Some((mint, owner)) = post_tok
+            .as_ref()
+            .or(pre_tok.as_ref())
+            .map(|t| (t.mint, t.owner))
+        {
+            let before = pre_tok.map_or(0, |t| t.amount);
+            let after = post_tok.map_or(0, |t| t.amount);
+            if before != after {
+                token_deltas.push(TokenDelta {
+                    account: *account,
+                    mint,
+                    owner,
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+
+    let logs = sim.logs.unwrap_or_default();
+    let events = program_events(&logs);
+    Ok(SimulationPreview {
+        err: sim.err,
+        units_consumed: sim.units_consumed,
+        logs,
+        events,
+        lamport_deltas,
+        token_deltas,
+    })
+}
+
+impl SimulationPreview {
+    /// Tokens `account` receives.
+    pub fn gain(&self, account: &Pubkey) -> u64 {
+        self.token_deltas
+            .iter()
+            .filter(|d| d.account == *account)
+            .map(|d| d.after.saturating_sub(d.before))
+            .sum()
+    }
+}
+
+/// Compute units `ixs` consumes with `fee_payer`, from one simulation without the account
+/// diff of [`simulate_preview`]; `None` when the simulation fails.
+pub async fn simulate_units(
+    rpc: &RpcClient,
+    fee_payer: &Pubkey,
+    ixs: &[Instruction],
+) -> error::Result<Option<u64>> {
+    let tx = Transaction::new_unsigned(Message::new(ixs, Some(fee_payer)));
+    let config = RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        ..RpcSimulateTransactionConfig::default()
+    };
+    let sim = rpc
+        .simulate_transaction_with_config(&tx, config)
+        .await?
+        .value;
+    if let Some(err) = sim.err {
+        debug!("simulation failed: {}", err);
+        return Ok(None);
+    }
+    Ok(sim.units_consumed)
+}
+
+/// What [`Flow::send`] did with a transaction.
+pub enum Sent {
+    /// Signed and sent. With `--jito` it lands with its bundle, on [`Flow::flush`] at the
+    /// latest.
+    Signed(Signature),
+    /// Only simulated (`--quote`, `cost`); the report keeps it too.
+    Simulated(Box<SimulationPreview>),
+    /// Written for external signing (`--export-tx`), with its simulation.
+    Exported(Box<SimulationPreview>),
+}
+
+impl Sent {
+    /// The signature of a transaction that landed.
+    pub fn signature(&self) -> Option<Signature> {
+        match self {
+            Sent::Signed(signature) => Some(*signature),
+            Sent::Simulated(_) | Sent::Exported(_) => None,
+        }
+    }
+
+    /// The simulated effects of a transaction that was quoted or exported rather than
+    /// sent, for a later transaction that builds on them.
+    pub fn unsent_preview(&self) -> Option<&SimulationPreview> {
+        match self {
+            Sent::Signed(_) => None,
+            Sent::Simulated(preview) | Sent::Exported(preview) => Some(preview),
+        }
+    }
+}
+
+/// Sends one command's transactions, in order. Each is simulated and must pass
+/// [`check_destinations`]; then, by [`Finish`], it is signed and sent, kept as a quote, or
+/// exported. [`Flow::finish`] returns the [`Report`] over all of them.
+///
+/// With `--jito`, signed transactions are held and sent together as one bundle, so a
+/// flow's transactions land all together or not at all.
+pub struct Flow<'a> {
+    rpc: &'a RpcClient,
+    config: &'a TxConfig,
+    payer: &'a dyn Signer,
+    report: Report,
+    /// Transactions handled so far.
+    count: usize,
+    /// `--jito`: signed transactions waiting for [`Flow::flush`], with their instructions.
+    bundle: Vec<(Transaction, Vec<Instruction>)>,
+}
+
+impl<'a> Flow<'a> {
+    pub fn new(rpc: &'a RpcClient, config: &'a TxConfig, payer: &'a dyn Signer) -> Self {
+        Self {
+            rpc,
+            config,
+            payer,
+            report: Report::default(),
+            count: 0,
+            bundle: Vec::new(),
+        }
+    }
+
+    pub fn rpc(&self) -> &'a RpcClient {
+        self.rpc
+    }
+
+    /// Whether `ixs` fit one transaction from the payer, with room for the nonce advance
+    /// [`Flow::send`] may add.
+    pub fn fits(&self, ixs: &[Instruction]) -> bool {
+        let mut ixs = ixs.to_vec();
+        self.add_nonce(&mut ixs);
+        let tx = Transaction::new_unsigned(Message::new(&ixs, Some(&self.payer.pubkey())));
+        bincode::serialized_size(&tx).is_ok_and(|size| size <= PACKET_DATA_SIZE as u64)
+    }
+
+    /// Whether `ixs` can go out as one transaction: it [fits](Flow::fits) and simulates
+    /// cleanly within `cu_limit`.
+    pub async fn fits_budget(&self, ixs: &[Instruction], cu_limit: u32) -> bool {
+        if !self.fits(ixs) {
+            return false;
+        }
+        match simulate_units(self.rpc, &self.payer.pubkey(), ixs).await {
+            Ok(units) => units.is_some_and(|u| u <= cu_limit as u64),
+            Err(e) => {
+                debug!("packed transaction simulation failed: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Simulate `ixs`, signed by the payer and `signers`, then sign and send, quote or
+    /// export it. When quoting or exporting, or with a `--jito` bundle pending, a
+    /// transaction after the first whose simulation fails is kept with a warning rather
+    /// than fatal.
+    pub async fn send(
+        &mut self,
+        mut ixs: Vec<Instruction>,
+        signers: &[&dyn Signer],
+    ) -> error::Result<Sent> {
+        let index = self.count;
+        self.count += 1;
+        let mut signers = [&[self.payer], signers].concat();
+        if let Some(authority) = self
+            .config
+            .nonce
+            .as_ref()
+            .and_then(|n| n.authority.as_ref())
+        {
+            signers.push(authority);
+        }
+        self.add_nonce(&mut ixs);
+        if let Some(percentile) = self.config.auto_cu_price {
+            apply_auto_cu_price(self.rpc, &mut ixs, percentile).await;
+        }
+        let preview = simulate_preview(self.rpc, &self.payer.pubkey(), &ixs).await?;
+        if let Some(sim_err) = preview.err.clone() {
+            for l in &preview.logs {
+                info!("sim log: {}", l);
+            }
+            debug!("simulate_transaction error: {:?}", sim_err);
+            if let Some(hint) = hints::explain(&sim_err, &ixs, &preview.logs) {
+                warn!("hint: {}", hint);
+            }
+            // A later transaction may only fail for want of what the earlier ones do: they
+            // never land when quoting or exporting, and not before a pending bundle is sent.
+            let earlier_pending = match self.config.finish {
+                Finish::Send => !self.bundle.is_empty(),
+                _ => index > 0,
+            };
+            if !earlier_pending {
+                return Err(Error::from_transaction_error(sim_err, &ixs, preview.logs));
+            }
+            warn!(
+                "transaction {} fails to simulate on its own ({}); it may need the earlier ones, which have not landed",
+                index + 1,
+                sim_err
+            );
+        }
+        for l in &preview.logs {
+            trace!("sim log: {}", l);
+        }
+        if let Some(units) = preview.units_consumed {
+            debug!("simulated compute units: {}", units);
+        }
+        if !preview.events.is_empty() {
+            debug!("simulated program events: {}", preview.events.len());
+        }
+        for d in &preview.lamport_deltas {
+            debug!(
+                "simulated SOL change {}: {:+} lamports",
+                d.account,
+                d.after as i128 - d.before as i128
+            );
+        }
+        for d in &preview.token_deltas {
+            debug!(
+                "simulated token change {} (mint {}): {:+}",
+                d.account,
+                d.mint,
+                d.after as i128 - d.before as i128
+            );
+        }
+
+        check_destinations(self.config, &self.payer.pubkey(), &signers, &ixs, &preview)?;
+
+        match &self.config.finish {
+            Finish::Quote | Finish::Cost => self.keep_quote(index, &ixs, preview).await,
+            Finish::Export(path) => {
+                let path = numbered_path(path, self.report.exported.len());
+                let msg = Message::new(&ixs, Some(&self.payer.pubkey()));
+                let mut tx = Transaction::new_unsigned(msg);
+                tx.try_partial_sign(&signers, self.recent_blockhash().await?.0)?;
+                export_tx(&path, &tx)?;
+                self.report.exported.push(path);
+                Ok(Sent::Exported(Box::new(preview)))
+            }
+            Finish::Send => {
+                let (tx, last_valid_block_height) = self.sign(&ixs, &signers).await?;
+                if self.config.jito.is_none() {
+                    let signature = self
+                        .send_with_retries(&ixs, &signers, tx, last_valid_block_height)
+                        .await?;
+                    self.report.signatures.push(signature);
+                    return Ok(Sent::Signed(signature));
+                }
+                let signature = tx.signatures[0];
+                self.bundle.push((tx, ixs));
+                if self.bundle.len() == self.bundle_size() {
+                    self.flush().await?;
+                }
+                Ok(Sent::Signed(signature))
+            }
+        }
+    }
+
+    /// `--jito`: send the transactions signed so far as one bundle, followed by a
+    /// transaction paying the tip, and wait for it to land. Call it before reading state
+    /// they change; [`Flow::finish`] flushes too. Does nothing without `--jito`.
+    pub async fn flush(&mut self) -> error::Result<()> {
+        let Some(jito) = &self.config.jito else {
+            return Ok(());
+        };
+        if self.bundle.is_empty() {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.bundle);
+        let payer = self.payer.pubkey();
+        let tip_ix = system_instruction::transfer(&payer, &jito_tip_account(), jito.tip_lamports);
+        let mut tip = Transaction::new_unsigned(Message::new(&[tip_ix], Some(&payer)));
+        // Not the durable nonce: nothing advances it in this transaction.
+        let (bh, _) = self
+            .rpc
+            .get_latest_blockhash_with_commitment(CommitmentConfig::processed())
+            .await?;
+        tip.try_sign(&[self.payer], bh)?;
+        self.send_bundle(jito, &pending, &tip).await?;
+        self.report
+            .signatures
+            .extend(pending.iter().map(|(tx, _)| tx.signatures[0]));
+        Ok(())
+    }
+
+    /// Everything the flow did, once any pending bundle has landed; print it with
+    /// [`Report::print`].
+    pub async fn finish(mut self) -> error::Result<Report> {
+        self.flush().await?;
+        Ok(self.report)
+    }
+
+    /// Transactions of the flow per bundle, leaving room for the tip transaction. Each
+    /// transaction advancing the same durable nonce needs a bundle of its own.
+    fn bundle_size(&self) -> usize {
+        match self.config.nonce {
+            Some(_) => 1,
+            None => MAX_BUNDLE_TXS - 1,
+        }
+    }
+
+    /// Put the nonce advance first when `--nonce-account` is set.
+    fn add_nonce(&self, ixs: &mut Vec<Instruction>) {
+        if let Some(nonce) = &self.config.nonce {
+            let authority = nonce
+                .authority
+                .as_ref()
+                .map_or(self.payer.pubkey(), |a| a.pubkey());
+            ixs.insert(
+                0,
+                system_instruction::advance_nonce_account(&nonce.account, &authority),
+            );
+        }
+    }
 
-use solana_client::rpc_client::RpcClient;
+    /// Keep the `index`-th simulated transaction in the report, with its cost under
+    /// `cost`. The `--jito` tip is counted on the first transaction of each bundle.
+    async fn keep_quote(
+        &mut self,
+        index: usize,
+        ixs: &[Instruction],
+        preview: SimulationPreview,
+    ) -> error::Result<Sent> {
+        let tip = match &self.config.jito {
+            // The tip goes in a transaction of its own, with its own signature.
+            Some(jito) if index.is_multiple_of(self.bundle_size()) => {
+                jito.tip_lamports + LAMPORTS_PER_SIGNATURE
+            }
+            _ => 0,
+        };
+        let cost = match self.config.finish {
+            Finish::Cost => {
+                Some(Cost::estimate(self.rpc, &self.payer.pubkey(), ixs, &preview, tip).await?)
+            }
+            _ => None,
+        };
+        self.report.quotes.push(Quote {
+            preview: preview.clone(),
+            cost,
+        });
+        Ok(Sent::Simulated(Box::new(preview)))
+    }
+
+    /// Sign `ixs` against a fresh blockhash; also returns that blockhash's last valid block
+    /// height (`None` for a durable nonce).
+    async fn sign(
+        &self,
+        ixs: &[Instruction],
+        signers: &[&dyn Signer],
+    ) -> error::Result<(Transaction, Option<u64>)> {
+        let (bh, last_valid_block_height) = self.recent_blockhash().await?;
+        let msg = Message::new(ixs, Some(&self.payer.pubkey()));
+        let mut tx = Transaction::new_unsigned(msg);
+        tx.try_sign(signers, bh)?;
+        Ok((tx, last_valid_block_height))
+    }
+
+    /// The blockhash to sign against and its last valid block height at processed
+    /// commitment: the durable nonce's (which has none) when `--nonce-account` is set.
+    async fn recent_blockhash(&self) -> error::Result<(Hash, Option<u64>)> {
+        match &self.config.nonce {
+            Some(nonce) => Ok((nonce_blockhash(self.rpc, &nonce.account).await?, None)),
+            None => {
+                let (bh, height) = self
+                    .rpc
+                    .get_latest_blockhash_with_commitment(CommitmentConfig::processed())
+                    .await?;
+                Ok((bh, Some(height)))
+            }
+        }
+    }
+
+    /// Send `tx` and rebroadcast it every resend interval until it confirms. When its
+    /// blockhash expires first, re-sign with a fresh one, up to `--max-retries` times.
+    /// Only the first send runs preflight; rebroadcasts skip it.
+    ///
+    /// Every signature sent so far is polled, and none is replaced while any of them is seen
+    /// at processed commitment, so a copy that landed but is not yet confirmed when its
+    /// blockhash expires is never sent a second time.
+    async fn send_with_retries(
+        &self,
+        ixs: &[Instruction],
+        signers: &[&dyn Signer],
+        mut tx: Transaction,
+        mut last_valid_block_height: Option<u64>,
+    ) -> error::Result<Signature> {
+        let rpc = self.rpc;
+        let resend = &self.config.resend;
+        let skip_preflight = RpcSendTransactionConfig {
+            skip_preflight: true,
+            ..Default::default()
+        };
+        if let Err(e) = rpc.send_transaction(&tx).await {
+            let Some(tx_err) = e.get_transaction_error() else {
+                return Err(e.into());
+            };
+            return Err(landed_error(tx_err, ixs));
+        }
+
+        let mut sent = vec![tx.signatures[0]];
+        let mut attempts = 1;
+        loop {
+            tokio::time::sleep(resend.interval).await;
+            // Expiry first: a copy that is not processed once its blockhash has expired can
+            // no longer land.
+            let expired = self
+                .blockhash_expired(&tx.message.recent_blockhash, last_valid_block_height)
+                .await?;
+            if let Some((signature, status)) = landed_status(rpc, &sent).await? {
+                if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                    return status
+                        .status
+                        .map(|()| signature)
+                        .map_err(|e| landed_error(e, ixs));
+                }
+                debug!("tx {} processed; waiting for confirmation", signature);
+                continue;
+            }
+            if !expired {
+                if let Err(e) = rpc.send_transaction_with_config(&tx, skip_preflight).await {
+                    debug!("rebroadcast of {} failed: {}", tx.signatures[0], e);
+                }
+                continue;
+            }
+            if attempts > resend.max_retries {
+                return Err(Error::NotLanded {
+                    signature: tx.signatures[0],
+                    attempts,
+                });
+            }
+            warn!(
+                "tx {} expired unconfirmed; re-signing with a fresh blockhash (retry {}/{})",
+                tx.signatures[0], attempts, resend.max_retries
+            );
+            (tx, last_valid_block_height) = self.sign(ixs, signers).await?;
+            sent.push(tx.signatures[0]);
+            rpc.send_transaction_with_config(&tx, skip_preflight)
+                .await?;
+            attempts += 1;
+        }
+    }
+
+    /// Whether a transaction signed against `blockhash` can no longer land: the processed
+    /// block height is past `last_valid_block_height`, or, for a durable nonce, the nonce
+    /// has moved on.
+    async fn blockhash_expired(
+        &self,
+        blockhash: &Hash,
+        last_valid_block_height: Option<u64>,
+    ) -> error::Result<bool> {
+        match (last_valid_block_height, &self.config.nonce) {
+            (Some(height), _) => Ok(self
+                .rpc
+                .get_block_height_with_commitment(CommitmentConfig::processed())
+                .await?
+                > height),
+            (None, Some(nonce)) => {
+                Ok(nonce_blockhash(self.rpc, &nonce.account).await? != *blockhash)
+            }
+            (None, None) => Ok(false),
+        }
+    }
+
+    /// Send `pending` and then `tip` as one bundle and wait for all of them to confirm.
+    /// A bundle either lands whole or not at all, and never enters the public mempool.
+    async fn send_bundle(
+        &self,
+        jito: &JitoConfig,
+        pending: &[(Transaction, Vec<Instruction>)],
+        tip: &Transaction,
+    ) -> error::Result<()> {
+        let encoded = pending
+            .iter()
+            .map(|(tx, _)| tx)
+            .chain([tip])
+            .map(|tx| {
+                bincode::serialize(tx)
+                    .map(|raw| bs58::encode(raw).into_string())
+                    .map_err(|e| Error::Decode {
+                        what: "transaction",
+                        reason: e.to_string(),
+                    })
+            })
+            .collect::<error::Result<Vec<_>>>()?;
+        let engine = RpcClient::new(format!("{}/api/v1/bundles", jito.url));
+        let bundle_id: String = engine
+            .send(
+                RpcRequest::Custom {
+                    method: "sendBundle",
+                },
+                serde_json::json!([encoded]),
+            )
+            .await?;
+        let signatures: Vec<Signature> = pending.iter().map(|(tx, _)| tx.signatures[0]).collect();
+        debug!(
+            "jito bundle {} submitted ({} txs, tip {} lamports)",
+            bundle_id,
+            encoded.len(),
+            jito.tip_lamports
+        );
+
+        let started = Instant::now();
+        while started.elapsed() < BUNDLE_CONFIRM_TIMEOUT {
+            let statuses = self.rpc.get_signature_statuses(&signatures).await?.value;
+            let mut confirmed = 0;
+            for (status, (_, ixs)) in statuses.iter().zip(pending) {
+                let Some(status) = status else { continue };
+                if let Err(tx_err) = &status.status {
+                    return Err(landed_error(tx_err.clone(), ixs));
+                }
+                if status.satisfies_commitment(CommitmentConfig::confirmed()) {
+                    confirmed += 1;
+                }
+            }
+            if confirmed == signatures.len() {
+                return Ok(());
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        Err(Error::BundleNotLanded {
+            bundle_id,
+            signature: signatures[0],
+        })
+    }
+}
 
-/// Sign, simulate, and send a transaction.
-pub fn simulate_and_send(
+/// `path` for the first exported transaction of a command, `<stem>-<n>.<ext>` for the
+/// n-th after it.
+fn numbered_path(path: &str, index: usize) -> String {
+    if index == 0 {
+        return path.to_string();
+    }
+    let p = std::path::Path::new(path);
+    let stem = p
+        .file_stem()
+        .map_or_else(String::new, |s| s.to_string_lossy().into_owned());
+    let name = match p.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, index + 1, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, index + 1),
+    };
+    p.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Programs whose instructions only move or wrap tokens and lamports around the pool
+/// instructions; the accounts they are given say nothing about the pool.
+const PLUMBING_PROGRAMS: [Pubkey; 5] = [
+    spl_token::ID,
+    spl_token_2022::ID,
+    spl_associated_token_account::ID,
+    solana_sdk::system_program::ID,
+    compute_budget::ID,
+];
+
+/// Refuse a transaction whose simulation pays tokens, or SOL, to a wallet that is neither the
+/// payer, a signer nor allowed with `--allow-destination`, so a bug or a tampered argument
+/// cannot send funds to a stranger. The Jito tip accounts are allowed too, and so is a
+/// program-owned (off-curve) owner passed to the pool program itself, such as the pool or
+/// vault authority its vaults belong to. An off-curve owner only reached through the token,
+/// ATA or system program is checked like any wallet.
+pub fn check_destinations(
+    config: &TxConfig,
+    payer: &Pubkey,
+    signers: &[&dyn Signer],
+    ixs: &[Instruction],
+    preview: &SimulationPreview,
+) -> error::Result<()> {
+    let pool_accounts: Vec<&Pubkey> = ixs
+        .iter()
+        .filter(|ix| !PLUMBING_PROGRAMS.contains(&ix.program_id))
+        .flat_map(|ix| ix.accounts.iter().map(|meta| &meta.pubkey))
+        .collect();
+    let allowed = |wallet: &Pubkey| {
+        wallet == payer
+            || (!wallet.is_on_curve() && pool_accounts.contains(&wallet))
+            || signers.iter().any(|s| s.pubkey() == *wallet)
+            || config.allowed_destinations.contains(wallet)
+            || (config.jito.is_some() && JITO_TIP_ACCOUNTS.contains(&wallet.to_string().as_str()))
+    };
+    for d in &preview.token_deltas {
+        if d.after > d.before && !allowed(&d.owner) {
+            return Err(Error::DestinationNotAllowed {
+                account: d.account,
+                owner: d.owner,
+            });
+        }
+    }
+    for d in &preview.lamport_deltas {
+        if d.after > d.before && d.owner == solana_sdk::system_program::id() && !allowed(&d.account)
+        {
+            return Err(Error::DestinationNotAllowed {
+                account: d.account,
+                owner: d.account,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Write `tx` as JSON: the base64 wire transaction and which required signers have signed.
+fn export_tx(path: &str, tx: &Transaction) -> error::Result<()> {
+    let raw = bincode::serialize(tx).map_err(|e| Error::Decode {
+        what: "transaction",
+        reason: e.to_string(),
+    })?;
+    let required = tx.message.header.num_required_signatures as usize;
+    let signers: Vec<_> = tx.message.account_keys[..required]
+        .iter()
+        .zip(&tx.signatures)
+        .map(|(pubkey, sig)| {
+            serde_json::json!({
+                "pubkey": pubkey.to_string(),
+                "signed": *sig != Signature::default(),
+            })
+        })
+        .collect();
+    let doc = serde_json::json!({
+        "transaction": BASE64.encode(raw),
+        "encoding": "base64",
+        "fee_payer": tx.message.account_keys[0].to_string(),
+        "recent_blockhash": tx.message.recent_blockhash.to_string(),
+        "signers": signers,
+    });
+    let text = serde_json::to_string_pretty(&doc).map_err(|e| Error::Decode {
+        what: "export json",
+        reason: e.to_string(),
+    })?;
+    std::fs::write(path, text)?;
+    info!(
+        "wrote unsigned transaction to {} ({} required signers)",
+        path, required
+    );
+    Ok(())
+}
+
+/// The first of `signatures` the cluster has seen at processed commitment or better, with
+/// its status.
+async fn landed_status(
+    rpc: &RpcClient,
+    signatures: &[Signature],
+) -> error::Result<Option<(Signature, TransactionStatus)>> {
+    let statuses = rpc.get_signature_statuses(signatures).await?.value;
+    Ok(signatures
+        .iter()
+        .zip(statuses)
+        .find_map(|(sig, status)| Some((*sig, status?))))
+}
+
+/// A transaction that landed (or failed preflight) with `tx_err`, hinted and classified.
+fn landed_error(tx_err: TransactionError, ixs: &[Instruction]) -> Error {
+    if let Some(hint) = hints::explain(&tx_err, ixs, &[]) {
+        warn!("hint: {}", hint);
+    }
+    Error::from_transaction_error(tx_err, ixs, Vec::new())
+}
+
+/// Swap the SetComputeUnitPrice instruction's price for the estimate over the writable
+/// accounts of `ixs`. Keeps the price already set when the RPC has no estimate.
+async fn apply_auto_cu_price(rpc: &RpcClient, ixs: &mut [Instruction], percentile: u8) {
+    let mut writable: Vec<Pubkey> = Vec::new();
+    for meta in ixs.iter().flat_map(|ix| &ix.accounts) {
+        if meta.is_writable && !writable.contains(&meta.pubkey) {
+            writable.push(meta.pubkey);
+        }
+    }
+    writable.truncate(MAX_FEE_ACCOUNTS);
+
+    let Some(price_ix) = ixs.iter_mut().find(|ix| {
+        ix.program_id == compute_budget::id()
+            && ix.data.first() == Some(&SET_COMPUTE_UNIT_PRICE_TAG)
+    }) else {
+        return;
+    };
+    match estimate_cu_price(rpc, &writable, percentile).await {
+        Ok(Some(price)) => {
+            debug!(
+                "auto cu price: {} microlamports (p{} over {} writable accounts)",
+                price,
+                percentile,
+                writable.len()
+            );
+            *price_ix = ComputeBudgetInstruction::set_compute_unit_price(price);
+        }
+        Ok(None) => {
+            warn!("no recent prioritization fees; keeping the fallback cu price")
+        }
+        Err(e) => warn!(
+            "getRecentPrioritizationFees failed ({}); keeping the fallback cu price",
+            e
+        ),
+    }
+}
+
+/// Nearest-rank `percentile` of the fees paid over recent slots on `accounts`.
+async fn estimate_cu_price(
     rpc: &RpcClient,
-    payer: &Keypair,
-    ixs: Vec<Instruction>,
-    signers: &[&Keypair],
-) -> Result<Signature> {
-    let bh = rpc.get_latest_blockhash()?;
-    let msg = Message::new(&ixs, Some(&payer.pubkey()));
-    let mut tx = Transaction::new_unsigned(msg);
-    tx.try_sign(signers, bh)?;
-    let sim = rpc.simulate_transaction(&tx)?;
-    if let Some(sim_err) = sim.value.err.clone() {
-        eprintln!("[debug] simulate_transaction error: {:?}", sim_err);
-        if let Some(logs) = sim.value.logs {
-            for l in logs {
-                eprintln!("[sim log] {}", l);
+    accounts: &[Pubkey],
+    percentile: u8,
+) -> Result<Option<u64>> {
+    let mut fees: Vec<u64> = rpc
+        .get_recent_prioritization_fees(accounts)
+        .await?
+        .into_iter()
+        .map(|f| f.prioritization_fee)
+        .collect();
+    if fees.is_empty() {
+        return Ok(None);
+    }
+    fees.sort_unstable();
+    let rank = (fees.len() * percentile as usize).div_ceil(100).max(1);
+    Ok(Some(fees[rank - 1]))
+}
+
+fn jito_tip_account() -> Pubkey {
+    // Spread tips across the accounts to avoid write-lock contention on a single one.
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let account = JITO_TIP_ACCOUNTS[nanos as usize % JITO_TIP_ACCOUNTS.len()];
+    Pubkey::from_str(account).expect("valid tip account")
+}
+
+/// A transaction of a `--quote` or `cost` run, simulated instead of sent.
+pub struct Quote {
+    pub preview: SimulationPreview,
+    /// `cost`: the lamports it needs.
+    pub cost: Option<Cost>,
+}
+
+/// The lamports a transaction needs from the fee payer, from its simulation.
+/// Rent for accounts it creates is refunded when they are closed later.
+pub struct Cost {
+    pub cu_limit: u32,
+    /// Microlamports per compute unit.
+    pub cu_price: u64,
+    pub signatures: u64,
+    pub network_fee: u64,
+    pub priority_fee: u64,
+    pub tip: u64,
+    pub rent: u64,
+    /// Accounts the transaction creates.
+    pub created: usize,
+    /// Lamports returned by the accounts it closes.
+    pub refunded: u64,
+}
+
+impl Cost {
+    async fn estimate(
+        rpc: &RpcClient,
+        fee_payer: &Pubkey,
+        ixs: &[Instruction],
+        preview: &SimulationPreview,
+        tip: u64,
+    ) -> error::Result<Self> {
+        let msg = Message::new(ixs, Some(fee_payer));
+        let signatures = msg.header.num_required_signatures as u64;
+
+        let budget_arg = |tag: u8| {
+            ixs.iter()
+                .filter(|ix| ix.program_id == compute_budget::id())
+                .find(|ix| ix.data.first() == Some(&tag))
+                .map(|ix| ix.data[1..].to_vec())
+        };
+        let cu_price = budget_arg(SET_COMPUTE_UNIT_PRICE_TAG)
+            .and_then(|d| d.get(..8)?.try_into().ok())
+            .map_or(0, u64::from_le_bytes);
+        let cu_limit = budget_arg(SET_COMPUTE_UNIT_LIMIT_TAG)
+            .and_then(|d| d.get(..4)?.try_into().ok())
+            .map_or(200_000, u32::from_le_bytes);
+
+        let mut rent = 0u64;
+        let mut created = 0usize;
+        let mut refunded = 0u64;
+        for d in &preview.lamport_deltas {
+            if d.before == 0 && d.after > 0 {
+                rent += rpc
+                    .get_minimum_balance_for_rent_exemption(d.data_len)
+                    .await?;
+                created += 1;
+            } else if d.after == 0 && d.account != *fee_payer {
+                refunded += d.before;
             }
         }
-        bail!("simulation failed: {:?}", sim_err);
-    } else if let Some(logs) = sim.value.logs {
-        for l in logs {
-            eprintln!("[sim log] {}", l);
+        Ok(Self {
+            cu_limit,
+            cu_price,
+            signatures,
+            network_fee: signatures * LAMPORTS_PER_SIGNATURE,
+            // Priority fees are charged on the requested limit, not the units consumed.
+            priority_fee: (cu_price as u128 * cu_limit as u128).div_ceil(1_000_000) as u64,
+            tip,
+            rent,
+            created,
+            refunded,
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.network_fee + self.priority_fee + self.tip + self.rent
+    }
+}
+
+/// What a command's transactions came to: the signatures that landed, or the quotes and
+/// exported files that stood in for them.
+#[derive(Default)]
+pub struct Report {
+    pub signatures: Vec<Signature>,
+    pub quotes: Vec<Quote>,
+    pub exported: Vec<String>,
+}
+
+impl Report {
+    /// Print each quoted transaction's simulated changes (`--quote`) or cost (`cost`),
+    /// numbered when the command built more than one, and the total cost over all of them.
+    pub fn print(&self) {
+        let n = self.quotes.len();
+        for (i, quote) in self.quotes.iter().enumerate() {
+            let which = if n > 1 {
+                format!(" tx {}/{}", i + 1, n)
+            } else {
+                String::new()
+            };
+            let units = quote
+                .preview
+                .units_consumed
+                .map_or("unknown".to_string(), |u| u.to_string());
+            match &quote.cost {
+                Some(cost) => {
+                    println!(
+                        "Cost{} (simulated, not sent): compute_units={} of {} limit",
+                        which, units, cost.cu_limit
+                    );
+                    print_cost(cost);
+                }
+                None => {
+                    println!(
+                        "Quote{} (simulated, not sent): compute_units={}",
+                        which, units
+                    );
+                    print_changes(&quote.preview);
+                }
+            }
+            if let Some(err) = &quote.preview.err {
+                println!(
+                    "  simulation failed: {} (it may need the transactions before it to land)",
+                    err
+                );
+            }
+        }
+        let costs: Vec<&Cost> = self.quotes.iter().filter_map(|q| q.cost.as_ref()).collect();
+        if costs.len() > 1 {
+            let total: u64 = costs.iter().map(|c| c.total()).sum();
+            let rent: u64 = costs.iter().map(|c| c.rent).sum();
+            println!(
+                "All {} transactions: {:>14} lamports ({} of it refundable rent)",
+                costs.len(),
+                amounts::group_thousands(total as u128),
+                amounts::group_thousands(rent as u128)
+            );
         }
     }
+}
 
-    let sig: Signature = rpc.send_and_confirm_transaction(&tx)?;
-    Ok(sig)
+/// The simulated token and SOL changes of a quoted transaction.
+fn print_changes(preview: &SimulationPreview) {
+    for d in &preview.token_deltas {
+        println!(
+            "  token {} mint={} {} -> {} ({:+})",
+            d.account,
+            d.mint,
+            d.before,
+            d.after,
+            d.after as i128 - d.before as i128
+        );
+    }
+    for d in &preview.lamport_deltas {
+        println!(
+            "  sol   {} {} -> {} ({:+} lamports)",
+            d.account,
+            d.before,
+            d.after,
+            d.after as i128 - d.before as i128
+        );
+    }
+}
+
+fn print_cost(cost: &Cost) {
+    println!(
+        "  network fee   {:>14} lamports ({} signature{})",
+        amounts::group_thousands(cost.network_fee as u128),
+        cost.signatures,
+        if cost.signatures == 1 { "" } else { "s" }
+    );
+    println!(
+        "  priority fee  {:>14} lamports ({} microlamports/CU)",
+        amounts::group_thousands(cost.priority_fee as u128),
+        cost.cu_price
+    );
+    if cost.tip > 0 {
+        println!(
+            "  jito tip      {:>14} lamports (with its transaction's fee, once per bundle)",
+            amounts::group_thousands(cost.tip as u128)
+        );
+    }
+    println!(
+        "  rent          {:>14} lamports ({} new account{}, refundable on close)",
+        amounts::group_thousands(cost.rent as u128),
+        cost.created,
+        if cost.created == 1 { "" } else { "s" }
+    );
+    println!(
+        "  total         {:>14} lamports",
+        amounts::group_thousands(cost.total() as u128)
+    );
+    if cost.refunded > 0 {
+        println!(
+            "  refunded      {:>14} lamports from closed accounts",
+            amounts::group_thousands(cost.refunded as u128)
+        );
+    }
 }
 
 /// Build instructions to wrap SOL into WSOL (creates ATA if missing).
-pub fn build_wrap_sol_ixs(
+pub async fn build_wrap_sol_ixs(
     rpc: &RpcClient,
     payer: &Pubkey,
     amount: u64,
@@ -53,7 +1307,8 @@ pub fn build_wrap_sol_ixs(
     let wsol_mint = native_mint::id();
     let ata = get_associated_token_address_with_program_id(payer, &wsol_mint, &spl_token::ID);
     if rpc
-        .get_account_with_commitment(&ata, CommitmentConfig::processed())?
+        .get_account_with_commitment(&ata, CommitmentConfig::processed())
+        .await?
         .value
         .is_none()
     {
@@ -77,3 +1332,147 @@ pub fn build_unwrap_sol_ix(payer: &Pubkey) -> Instruction {
     let ata = get_associated_token_address_with_program_id(payer, &wsol_mint, &spl_token::ID);
     spl_token_ix::close_account(&spl_token::ID, &ata, payer, payer, &[]).expect("close_account")
 }
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::instruction::AccountMeta;
+    use solana_sdk::signature::Keypair;
+
+    use super::*;
+
+    fn preview(
+        token_deltas: Vec<TokenDelta>,
+        lamport_deltas: Vec<LamportDelta>,
+    ) -> SimulationPreview {
+        SimulationPreview {
+            err: None,
+            units_consumed: None,
+            logs: Vec::new(),
+            events: Vec::new(),
+            lamport_deltas,
+            token_deltas,
+        }
+    }
+
+    #[test]
+    fn events_are_named_and_attributed_to_the_logging_program() {
+        let (outer, inner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut payload = Sha256::digest("event:Traded")[..8].to_vec();
+        payload.extend([1, 2, 3]);
+        let logs = vec![
+            format!("Program {} invoke [1]", outer),
+            format!("Program {} invoke [2]", inner),
+            format!("Program data: {}", BASE64.encode(&payload)),
+            format!("Program {} success", inner),
+            "Program data: AAAAAAAAAAAA".to_string(),
+            format!("Program {} success", outer),
+        ];
+        let events = program_events(&logs);
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            (events[0].program, events[0].name, &events[0].data[..]),
+            (inner, Some("Traded"), &[1, 2, 3][..])
+        );
+        assert_eq!((events[1].program, events[1].name), (outer, None));
+    }
+
+    fn token_in(owner: Pubkey) -> TokenDelta {
+        TokenDelta {
+            account: Pubkey::new_unique(),
+            mint: Pubkey::new_unique(),
+            owner,
+            before: 0,
+            after: 1_000,
+        }
+    }
+
+    fn ix(program_id: Pubkey, accounts: &[Pubkey]) -> Instruction {
+        Instruction::new_with_bytes(
+            program_id,
+            &[],
+            accounts
+                .iter()
+                .map(|pk| AccountMeta::new(*pk, false))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn destinations_allow_payer_signers_and_pool_authority() {
+        let payer = Keypair::new();
+        let extra = Keypair::new();
+        let dex = Pubkey::new_unique();
+        let (pool, _) = Pubkey::find_program_address(&[b"pool"], &dex);
+        let ixs = [ix(dex, &[payer.pubkey(), pool])];
+        let preview = preview(
+            vec![
+                token_in(payer.pubkey()),
+                token_in(extra.pubkey()),
+                token_in(pool),
+            ],
+            Vec::new(),
+        );
+        let signers: [&dyn Signer; 2] = [&payer, &extra];
+        assert!(
+            check_destinations(
+                &TxConfig::default(),
+                &payer.pubkey(),
+                &signers,
+                &ixs,
+                &preview
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn destinations_refuse_unrelated_pda() {
+        let payer = Keypair::new();
+        let dex = Pubkey::new_unique();
+        let (pool, _) = Pubkey::find_program_address(&[b"pool"], &dex);
+        let (stranger, _) = Pubkey::find_program_address(&[b"vault"], &Pubkey::new_unique());
+        // Creating the stranger's ATA does not make it part of the pool.
+        let ixs = [
+            ix(
+                spl_associated_token_account::ID,
+                &[payer.pubkey(), stranger],
+            ),
+            ix(dex, &[payer.pubkey(), pool]),
+        ];
+        let preview = preview(vec![token_in(stranger)], Vec::new());
+        let signers: [&dyn Signer; 1] = [&payer];
+        assert!(matches!(
+            check_destinations(&TxConfig::default(), &payer.pubkey(), &signers, &ixs, &preview),
+            Err(Error::DestinationNotAllowed { owner, .. }) if owner == stranger
+        ));
+    }
+
+    #[test]
+    fn destinations_refuse_sol_to_stranger_wallet() {
+        let payer = Keypair::new();
+        let stranger = Keypair::new().pubkey();
+        let ixs = [system_instruction::transfer(&payer.pubkey(), &stranger, 1)];
+        let preview = preview(
+            Vec::new(),
+            vec![LamportDelta {
+                account: stranger,
+                before: 0,
+                after: 1,
+                data_len: 0,
+                owner: solana_sdk::system_program::id(),
+            }],
+        );
+        let signers: [&dyn Signer; 1] = [&payer];
+        assert!(matches!(
+            check_destinations(&TxConfig::default(), &payer.pubkey(), &signers, &ixs, &preview),
+            Err(Error::DestinationNotAllowed { account, .. }) if account == stranger
+        ));
+    }
+
+    #[test]
+    fn export_paths_are_numbered_after_the_first() {
+        assert_eq!(numbered_path("tx.json", 0), "tx.json");
+        assert_eq!(numbered_path("tx.json", 1), "tx-2.json");
+        assert_eq!(numbered_path("out/tx", 2), "out/tx-3");
+    }
+}